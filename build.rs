@@ -6,6 +6,7 @@ const PROBES_SRC: &str = "src/bpf/probes.bpf.c";
 const PROTOS: &[&str] = &[
     "edgebitapis/edgebit/agent/v1alpha/token_service.proto",
     "edgebitapis/edgebit/agent/v1alpha/inventory_service.proto",
+    "edgebitapis/edgebit/agent/v1alpha/command_service.proto",
 ];
 
 fn build_protos() -> Result<(), Box<dyn std::error::Error>> {
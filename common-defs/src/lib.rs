@@ -2,12 +2,18 @@
 
 use bytemuck::{Pod, Zeroable};
 
+// Max length of a resolved path written into `EvtOpen.filename`, including
+// the case where the in-kernel dentry walk bails out early and leaves it
+// truncated.
+pub const FILENAME_LEN: usize = 256;
+
 #[repr(C)]
 #[derive(Clone, Copy, Zeroable, Pod)]
 pub struct EvtOpen {
     pub cgroup: u64,
     pub dev: u64,
     pub ino: u64,
+    pub filename: [u8; FILENAME_LEN],
 }
 
 impl EvtOpen {
@@ -16,6 +22,7 @@ impl EvtOpen {
             cgroup: 0u64,
             dev: 0u64,
             ino: 0u64,
+            filename: [0u8; FILENAME_LEN],
         }
     }
 }
@@ -5,6 +5,7 @@
 mod vmlinux;
 use vmlinux::{trace_event_raw_sys_exit};
 use vmlinux::{task_struct, files_struct, fdtable, file, inode, super_block, umode_t};
+use vmlinux::{dentry, vfsmount, mount};
 
 use aya_bpf::{
     macros::{tracepoint, map},
@@ -171,13 +172,13 @@ fn try_exit_open(ctx: &TracePointContext, ret: c_long) -> Result<u32, u32> {
 
     if let Some(entry) = unsafe { OPEN_INFLIGHT.get(&pid) } {
     */
-/*
+    if ret < 0 {
+        return Ok(0);
+    }
+
     let mut evt = EvtOpen::new();
     let f = unsafe { get_file(ret as u32).or_else(|e| { bpf_printk!(b"get_file err"); Err(e) })? };
 
-    //_ = unsafe { bpf_probe_read_user_str_bytes(entry.filename as *const u8, &mut evt.filename[..]) }
-    //    .map_err(|_| 1u32)?;
-
     let inode = get_inode(f).or_else(|e| unsafe { bpf_printk!(b"get_inode err"); Err(e) })?;
     let mode = get_mode(inode).or_else(|e| unsafe { bpf_printk!(b"get_mode err"); Err(e) })?;
     if (mode & S_IFREG) == 0 {
@@ -196,16 +197,126 @@ fn try_exit_open(ctx: &TracePointContext, ret: c_long) -> Result<u32, u32> {
     evt.ino = unsafe { read_kernel(&((*inode).i_ino)) }?;
     evt.cgroup = unsafe { bpf_get_current_cgroup_id() } as u64;
 
+    if resolve_filename(f, &mut evt.filename).is_err() {
+        unsafe { bpf_printk!(b"resolve_filename err"); }
+    }
+
     unsafe { bpf_printk!(b"sending event"); }
     EVENTS.output(ctx, &evt, 0);
 
     //_ = OPEN_INFLIGHT.remove(&pid);
-*/
-    let offset = offset_of!(task_struct, files) as u32;
-    unsafe { bpf_printk!(b"offset = %u", offset); }
     Ok(0)
 }
 
+const MAX_DENTRY_WALK: usize = 32;
+
+// Reconstructs the absolute path of `f` by walking its dentry's `d_parent`
+// chain, since `bpf_d_path` isn't callable from tracepoints (it's
+// allowlisted only for a handful of LSM/fentry hooks). A dentry that is its
+// own parent is the root of its vfsmount; if that mount isn't the global
+// root, the walk hops to the parent mount via `mount.mnt_parent`/
+// `mnt_mountpoint` (recovering `struct mount` from the `vfsmount` pointer
+// with the same `offset_of!` container-of trick `get_files` uses) and
+// continues from there. The verifier requires a statically bounded loop, so
+// the walk is capped at MAX_DENTRY_WALK components; if the real root isn't
+// reached by then, `filename` is left holding whatever components were
+// resolved (closest to the file, truncated at the front) rather than
+// failing the whole event.
+fn resolve_filename(f: *const file, filename: &mut [u8; FILENAME_LEN]) -> Result<(), u32> {
+    let mut components: [*const dentry; MAX_DENTRY_WALK] = [core::ptr::null(); MAX_DENTRY_WALK];
+    let mut n = 0usize;
+
+    let mut mnt = unsafe { read_kernel(&((*f).f_path.mnt)) }? as *const vfsmount;
+    let mut d = unsafe { read_kernel(&((*f).f_path.dentry)) }? as *const dentry;
+
+    for _ in 0..MAX_DENTRY_WALK {
+        let parent = get_d_parent(d)?;
+
+        if parent == d {
+            let mnt_root = get_mnt_root(mnt)?;
+            if d != mnt_root {
+                // Self-parented but not a mount root: nothing further up.
+                break;
+            }
+
+            match get_mount_parent(mnt)? {
+                Some((next_d, next_mnt)) => {
+                    d = next_d;
+                    mnt = next_mnt;
+                    continue;
+                }
+                None => break, // reached the global filesystem root
+            }
+        }
+
+        if n < components.len() {
+            components[n] = d;
+            n += 1;
+        }
+
+        d = parent;
+    }
+
+    let mut pos = 0usize;
+    for idx in 0..MAX_DENTRY_WALK {
+        if idx >= n || pos + 1 >= FILENAME_LEN {
+            break;
+        }
+
+        let i = n - 1 - idx;
+
+        filename[pos] = b'/';
+        pos += 1;
+
+        let name = get_d_name(components[i])?;
+        let written = unsafe { bpf_probe_read_kernel_str_bytes(name, &mut filename[pos..]) }
+            .map(|s| s.len())
+            .unwrap_or(0);
+
+        pos += written;
+    }
+
+    Ok(())
+}
+
+#[inline(always)]
+fn get_d_parent(d: *const dentry) -> Result<*const dentry, u32> {
+    let parent = unsafe { read_kernel(&((*d).d_parent)) }?;
+    Ok(parent as *const dentry)
+}
+
+#[inline(always)]
+fn get_d_name(d: *const dentry) -> Result<*const u8, u32> {
+    let name = unsafe { read_kernel(&((*d).d_name.name)) }?;
+    Ok(name as *const u8)
+}
+
+#[inline(always)]
+fn get_mnt_root(mnt: *const vfsmount) -> Result<*const dentry, u32> {
+    let root = unsafe { read_kernel(&((*mnt).mnt_root)) }?;
+    Ok(root as *const dentry)
+}
+
+// Recovers the owning `struct mount` from a `vfsmount` pointer (the same
+// container-of idiom as `get_files`'s `offset_of!` use below) to cross into
+// the parent mount when `mnt` turns out to be a mounted sub-tree rather than
+// the global root. Returns `None` once `mnt_parent` points back to itself,
+// i.e. the root mount.
+#[inline(always)]
+fn get_mount_parent(mnt: *const vfsmount) -> Result<Option<(*const dentry, *const vfsmount)>, u32> {
+    let mnt_struct = (mnt as usize - offset_of!(mount, mnt)) as *const mount;
+    let parent = unsafe { read_kernel(&((*mnt_struct).mnt_parent)) }?;
+
+    if parent as usize == mnt_struct as usize {
+        return Ok(None);
+    }
+
+    let mountpoint = unsafe { read_kernel(&((*mnt_struct).mnt_mountpoint)) }?;
+    let parent_mnt = unsafe { &((*parent).mnt) as *const vfsmount };
+
+    Ok(Some((mountpoint as *const dentry, parent_mnt)))
+}
+
 // Assumes that fd is valid
 unsafe fn get_file(fd: u32) -> Result<*const file, u32> {
     let current = unsafe { bpf_get_current_task() as *const task_struct };
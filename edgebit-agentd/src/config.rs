@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use figment::providers::{Env, Format, Serialized, Toml};
+use figment::Figment;
+use serde::{Deserialize, Serialize};
+
+pub const CONFIG_PATH: &str = "/etc/edgebit/agent.toml";
+
+const DEFAULT_HOST_ROOT: &str = "/";
+const DEFAULT_OPEN_EVENT_LAG_MS: u64 = 500;
+
+#[derive(Parser)]
+pub struct CliArgs {
+    /// Overrides [edgebit].url / $EDGEBIT_URL.
+    #[clap(long = "url")]
+    url: Option<String>,
+
+    /// Overrides [edgebit].token / $EDGEBIT_ID.
+    #[clap(long = "token")]
+    token: Option<String>,
+
+    /// Overrides [monitor].host_root.
+    #[clap(long = "host-root")]
+    host_root: Option<PathBuf>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EdgebitSection {
+    url: Option<String>,
+    token: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MonitorSection {
+    host_root: Option<PathBuf>,
+    open_event_lag_ms: Option<u64>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MetricsSection {
+    addr: Option<String>,
+    otlp_endpoint: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Inner {
+    #[serde(default)]
+    edgebit: EdgebitSection,
+
+    #[serde(default)]
+    monitor: MonitorSection,
+
+    #[serde(default)]
+    metrics: MetricsSection,
+
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+pub struct Config {
+    inner: Inner,
+}
+
+impl Config {
+    // Layers, lowest to highest precedence: compiled-in defaults ->
+    // `/etc/edgebit/agent.toml` -> environment -> CLI flags. `$EDGEBIT_URL`
+    // and `$EDGEBIT_ID` are kept as the env var names (rather than the
+    // `EDGEBIT_EDGEBIT_*` a plain prefix split would produce) so existing
+    // deployments that only set those two don't break. `deny_unknown_fields`
+    // on every section turns a typo'd key in the TOML file into a startup
+    // error instead of a silently-ignored setting.
+    pub fn load(args: &CliArgs) -> Result<Self> {
+        let figment = Figment::from(Serialized::defaults(Inner::default()))
+            .merge(Toml::file(CONFIG_PATH))
+            .merge(Env::prefixed("EDGEBIT_MONITOR_").map(|key| format!("monitor.{key}").into()))
+            .merge(Env::prefixed("EDGEBIT_METRICS_").map(|key| format!("metrics.{key}").into()))
+            .merge(Env::raw().only(&["EDGEBIT_URL", "EDGEBIT_ID"]).map(|key| {
+                match key {
+                    "EDGEBIT_URL" => "edgebit.url".into(),
+                    _ => "edgebit.token".into(),
+                }
+            }));
+
+        let mut inner: Inner = figment
+            .extract()
+            .map_err(|err| anyhow!("invalid configuration: {err}"))?;
+
+        if let Ok(labels_str) = std::env::var("EDGEBIT_LABELS") {
+            inner.labels.extend(labels_str.split(';').filter_map(|kv| {
+                kv.split_once('=')
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+            }));
+        }
+
+        if let Some(url) = &args.url {
+            inner.edgebit.url = Some(url.clone());
+        }
+
+        if let Some(token) = &args.token {
+            inner.edgebit.token = Some(token.clone());
+        }
+
+        if let Some(host_root) = &args.host_root {
+            inner.monitor.host_root = Some(host_root.clone());
+        }
+
+        if inner.edgebit.url.is_none() {
+            return Err(anyhow!(
+                "edgebit url not set ($EDGEBIT_URL, [edgebit].url in {CONFIG_PATH}, or --url)"
+            ));
+        }
+
+        if inner.edgebit.token.is_none() {
+            return Err(anyhow!(
+                "edgebit token not set ($EDGEBIT_ID, [edgebit].token in {CONFIG_PATH}, or --token)"
+            ));
+        }
+
+        Ok(Self { inner })
+    }
+
+    pub fn url(&self) -> String {
+        self.inner.edgebit.url.clone().unwrap()
+    }
+
+    pub fn token(&self) -> String {
+        self.inner.edgebit.token.clone().unwrap()
+    }
+
+    pub fn host_root(&self) -> PathBuf {
+        self.inner
+            .monitor
+            .host_root
+            .clone()
+            .unwrap_or_else(|| DEFAULT_HOST_ROOT.into())
+    }
+
+    pub fn open_event_lag(&self) -> Duration {
+        Duration::from_millis(
+            self.inner
+                .monitor
+                .open_event_lag_ms
+                .unwrap_or(DEFAULT_OPEN_EVENT_LAG_MS),
+        )
+    }
+
+    pub fn labels(&self) -> &HashMap<String, String> {
+        &self.inner.labels
+    }
+
+    // Bind address for the Prometheus `/metrics` endpoint. Disabled (None)
+    // unless explicitly configured, since most deployments don't want an
+    // extra open port by default.
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        let raw = self.inner.metrics.addr.clone()?;
+
+        match raw.parse() {
+            Ok(addr) => Some(addr),
+            Err(err) => {
+                eprintln!("Invalid [metrics].addr '{raw}': {err}");
+                None
+            }
+        }
+    }
+
+    // OTLP collector endpoint to additionally push metrics to, for
+    // deployments that already centralize telemetry through an
+    // OpenTelemetry collector instead of scraping Prometheus directly.
+    pub fn metrics_otlp_endpoint(&self) -> Option<String> {
+        self.inner.metrics.otlp_endpoint.clone()
+    }
+}
@@ -1,4 +1,6 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
 use log::*;
@@ -16,10 +18,21 @@ pub mod pb {
 use pb::enrollment_service_client::EnrollmentServiceClient;
 use pb::inventory_service_client::InventoryServiceClient;
 
+use crate::metrics;
 use crate::packages::PkgRef;
 use crate::packages::rpm::RpmPackage;
+use crate::spool::InUseSpool;
 
 const TOKEN_FILE: &str = "/var/lib/edgebit/token";
+
+// How long the replay task waits between passes over the spool: halved
+// on a pass that replayed at least one batch (there's likely more to
+// catch up on), doubled up to `REPLAY_INTERVAL_CAP` on a pass that
+// replayed nothing, so a reconnecting control plane gets drained quickly
+// without polling sled every second during an extended outage.
+const REPLAY_INTERVAL_BASE: Duration = Duration::from_secs(2);
+const REPLAY_INTERVAL_CAP: Duration = Duration::from_secs(60);
+
 struct AuthInterceptor {
     auth_val: AsciiMetadataValue,
 }
@@ -31,12 +44,15 @@ impl Interceptor for AuthInterceptor {
     }
 }
 
+type InventorySvc = InventoryServiceClient<InterceptedService<Channel, AuthInterceptor>>;
+
 pub struct Client {
-    inner: InventoryServiceClient<InterceptedService<Channel, AuthInterceptor>>,
+    inner: InventorySvc,
+    spool: Arc<InUseSpool>,
 }
 
 impl Client {
-    pub async fn connect(endpoint: Uri, deploy_token: String) -> Result<Self> {
+    pub async fn connect(endpoint: Uri, deploy_token: String, spool_dir: &Path) -> Result<Self> {
         let channel = Channel::builder(endpoint)
             .connect()
             .await?;
@@ -50,8 +66,11 @@ impl Client {
         let auth_interceptor = AuthInterceptor{auth_val};
 
         let inner = InventoryServiceClient::with_interceptor(channel, auth_interceptor);
+        let spool = Arc::new(InUseSpool::open(spool_dir)?);
 
-        Ok(Self{inner})
+        tokio::task::spawn(replay_loop(inner.clone(), spool.clone()));
+
+        Ok(Self{inner, spool})
     }
 
     pub async fn report_rpms(&mut self, rpms: Vec<RpmPackage>) -> Result<()> {
@@ -67,24 +86,91 @@ impl Client {
         Ok(())
     }
 
+    // Spools the batch durably before attempting to send it, so it survives
+    // a control-plane outage or an agent restart; `replay_loop` retries it
+    // until it's acknowledged if the immediate send below fails.
     pub async fn report_in_use(&mut self, pkgs: Vec<PkgRef>) -> Result<()> {
-        let in_use = pkgs.into_iter()
-            .map(|p| {
-                pb::PkgInUse{
-                    id: p.id,
-                    files: p.filenames,
-                }
-            })
-            .collect();
+        if pkgs.is_empty() {
+            return Ok(());
+        }
+
+        let key = self.spool.append(&pkgs)?;
+
+        match send_report_in_use(&mut self.inner, pkgs_to_proto(pkgs)).await {
+            Ok(()) => {
+                self.spool.ack(&key);
+                Ok(())
+            }
+            Err(err) => {
+                warn!("Failed to report in-use packages, will retry from spool: {err}");
+                Ok(())
+            }
+        }
+    }
 
-        let req = pb::ReportInUseRequest{
-            in_use,
-        };
+    pub fn pending_report_in_use(&self) -> usize {
+        self.spool.len()
+    }
+}
 
-        self.inner.report_in_use(req).await?;
-        Ok(())
+fn pkgs_to_proto(pkgs: Vec<PkgRef>) -> pb::ReportInUseRequest {
+    let in_use = pkgs.into_iter()
+        .map(|p| {
+            pb::PkgInUse{
+                id: p.id,
+                files: p.filenames,
+            }
+        })
+        .collect();
+
+    pb::ReportInUseRequest{ in_use }
+}
+
+async fn send_report_in_use(svc: &mut InventorySvc, req: pb::ReportInUseRequest) -> Result<()> {
+    let timer = metrics::REPORT_IN_USE_LATENCY.start_timer();
+    let result = svc.report_in_use(req).await;
+    timer.observe_duration();
+
+    match result {
+        Ok(_) => {
+            metrics::REPORT_IN_USE_OK.inc();
+            Ok(())
+        }
+        Err(err) => {
+            metrics::REPORT_IN_USE_ERR.inc();
+            Err(err.into())
+        }
     }
+}
+
+async fn replay_loop(mut svc: InventorySvc, spool: Arc<InUseSpool>) {
+    let mut interval = REPLAY_INTERVAL_BASE;
+
+    loop {
+        tokio::time::sleep(interval).await;
 
+        let pending = spool.pending();
+        let mut replayed_any = false;
+
+        for (key, pkgs) in pending {
+            match send_report_in_use(&mut svc, pkgs_to_proto(pkgs)).await {
+                Ok(()) => {
+                    spool.ack(&key);
+                    replayed_any = true;
+                }
+                Err(err) => {
+                    debug!("Replay of spooled in-use batch {key} still failing: {err}");
+                    break;
+                }
+            }
+        }
+
+        interval = if replayed_any {
+            REPLAY_INTERVAL_BASE
+        } else {
+            (interval * 2).min(REPLAY_INTERVAL_CAP)
+        };
+    }
 }
 
 fn load_token() -> Result<String> {
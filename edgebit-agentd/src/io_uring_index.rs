@@ -0,0 +1,155 @@
+// Cold-start inode indexing via io_uring, for hosts with enough files that
+// `traverse_parallel`'s one-`statx`-per-file loop is the bottleneck. Each
+// `statx` round-trips to the kernel individually in the synchronous path;
+// here they're batched into a single io_uring submission queue per
+// directory and the completions are drained together instead.
+//
+// Directory listing itself still goes through `std::fs::read_dir` --
+// io_uring's own directory-read support varies too much across kernels
+// still in the field to depend on, and it isn't the part of the walk this
+// is meant to speed up. `try_traverse` returns `None` (instead of a partial
+// or wrong result) whenever io_uring itself can't be used -- too old a
+// kernel, disabled via seccomp, etc. -- so the caller can fall back to the
+// synchronous traversal.
+
+use std::collections::HashMap;
+use std::ffi::{CString, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc::Sender, Arc, Mutex};
+
+use inotify::WatchDescriptor;
+use io_uring::{opcode, types, IoUring};
+use log::*;
+
+use crate::open_monitor::{add_watch, is_system_dir, major_minor_to_kernel_dev, IndexProgress};
+
+const QUEUE_DEPTH: u32 = 256;
+const STATX_BATCH: usize = 128;
+
+type DevIno = (u64, u64);
+
+pub fn try_traverse(
+    root: &Path,
+    handle: &Arc<Mutex<inotify::Watches>>,
+    watches: &Arc<Mutex<HashMap<WatchDescriptor, PathBuf>>>,
+    progress: Sender<IndexProgress>,
+    cancel: &Arc<AtomicBool>,
+) -> Option<HashMap<DevIno, OsString>> {
+    let mut ring = match IoUring::new(QUEUE_DEPTH) {
+        Ok(ring) => ring,
+        Err(err) => {
+            info!("io_uring unavailable ({err}), falling back to synchronous inode indexing");
+            return None;
+        }
+    };
+
+    let mut cache = HashMap::new();
+    let mut dirs = vec![root.to_path_buf()];
+    let dirs_processed = AtomicU64::new(0);
+    let files_cached = AtomicU64::new(0);
+
+    while let Some(dir) = dirs.pop() {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+
+        add_watch(handle, watches, &dir);
+
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                debug!("read_dir({}): {err}", dir.display());
+                continue;
+            }
+        };
+
+        let paths: Vec<PathBuf> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| !is_system_dir(p))
+            .collect();
+
+        for batch in paths.chunks(STATX_BATCH) {
+            let stats = match statx_batch(&mut ring, batch) {
+                Ok(stats) => stats,
+                Err(err) => {
+                    warn!("io_uring statx batch for {}: {err}, falling back to synchronous indexing", dir.display());
+                    return None;
+                }
+            };
+
+            for (path, stat) in batch.iter().zip(stats) {
+                let Some(stat) = stat else { continue };
+
+                match stat.stx_mode as u32 & libc::S_IFMT {
+                    libc::S_IFDIR => dirs.push(path.clone()),
+                    libc::S_IFREG => {
+                        let dev = major_minor_to_kernel_dev(stat.stx_dev_major as u64, stat.stx_dev_minor as u64);
+                        cache.insert((dev, stat.stx_ino), path.clone().into_os_string());
+                        files_cached.fetch_add(1, Ordering::Relaxed);
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        dirs_processed.fetch_add(1, Ordering::Relaxed);
+        _ = progress.send(IndexProgress {
+            dirs_processed: dirs_processed.load(Ordering::Relaxed),
+            files_cached: files_cached.load(Ordering::Relaxed),
+        });
+    }
+
+    Some(cache)
+}
+
+// Submits a `statx` SQE per path in `batch`, follows symlinks the same way
+// `std::fs::metadata` does, and waits for every completion before
+// returning. `Ok(None)` in a slot means that path's `statx` failed (e.g. it
+// vanished between `read_dir` and here) and is treated like the existing
+// synchronous path treats a failed `dirent.metadata()`: skipped.
+fn statx_batch(ring: &mut IoUring, batch: &[PathBuf]) -> std::io::Result<Vec<Option<libc::statx>>> {
+    if batch.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let cpaths: Vec<CString> = batch
+        .iter()
+        .map(|p| CString::new(p.as_os_str().as_bytes()).unwrap_or_default())
+        .collect();
+
+    let mut stat_bufs: Vec<libc::statx> = vec![unsafe { std::mem::zeroed() }; batch.len()];
+
+    for (i, cpath) in cpaths.iter().enumerate() {
+        let statx_op = opcode::Statx::new(
+            types::Fd(libc::AT_FDCWD),
+            cpath.as_ptr(),
+            &mut stat_bufs[i] as *mut libc::statx as *mut types::statx,
+        )
+        .mask(libc::STATX_TYPE | libc::STATX_INO)
+        .build()
+        .user_data(i as u64);
+
+        unsafe {
+            while ring.submission().push(&statx_op).is_err() {
+                ring.submit()?;
+            }
+        }
+    }
+
+    ring.submit_and_wait(batch.len())?;
+
+    let mut results: Vec<Option<libc::statx>> = vec![None; batch.len()];
+    for cqe in ring.completion() {
+        let idx = cqe.user_data() as usize;
+        if cqe.result() >= 0 {
+            if let Some(slot) = results.get_mut(idx) {
+                *slot = Some(stat_bufs[idx]);
+            }
+        }
+    }
+
+    Ok(results)
+}
@@ -1,17 +1,35 @@
+pub mod config;
 pub mod packages;
 pub mod open_monitor;
 pub mod control_plane;
+pub mod metrics;
+pub mod spool;
 
+#[cfg(target_os = "linux")]
+pub mod io_uring_index;
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::{CliArgs, Config};
 use crate::open_monitor::{OpenMonitor};
 
-use anyhow::{Result, anyhow};
+use anyhow::Result;
+use clap::Parser;
+use log::error;
 
 use open_monitor::OpenEvent;
 
+const SPOOL_DIR: &str = "/var/lib/edgebit/spool";
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+
 #[tokio::main]
 async fn main() {
     pretty_env_logger::init();
-    match run().await {
+
+    let args = CliArgs::parse();
+    match run(&args).await {
         Ok(_) => {},
         Err(err) => {
             eprintln!("err: {err}");
@@ -20,16 +38,21 @@ async fn main() {
     }
 }
 
-async fn run() -> Result<()> {
-    let url = std::env::var("EDGEBIT_URL")
-        .map_err(|_| anyhow!("Is EDGEBIT_URL env var set?"))?;
+async fn run(args: &CliArgs) -> Result<()> {
+    let config = Config::load(args)?;
+
+    if let Some(addr) = config.metrics_addr() {
+        tokio::task::spawn(metrics::serve(addr));
+    }
 
-    let token = std::env::var("EDGEBIT_ID")
-        .map_err(|_| anyhow!("Is EDGEBIT_ID env var set?"))?;
+    if let Some(endpoint) = config.metrics_otlp_endpoint() {
+        tokio::task::spawn(metrics::serve_otlp(endpoint));
+    }
 
     let mut client = control_plane::Client::connect(
-        url.try_into()?,
-        token.try_into()?,
+        config.url().try_into()?,
+        config.token().try_into()?,
+        Path::new(SPOOL_DIR),
     ).await?;
 
     let mut pkg_registry = packages::Registry::new();
@@ -42,31 +65,80 @@ async fn run() -> Result<()> {
 
     client.report_rpms(rpms).await?;
 
+    // dpkg/apk hosts have no rpm database to report, but their packages
+    // still need to be in `pkg_registry` for in-use correlation to find
+    // anything other than "unknown".
+    for backend in packages::detect_backends() {
+        match backend.query_all() {
+            Ok(pkgs) => {
+                for pkg in &pkgs {
+                    pkg_registry.add_pkg(&pkg.id, &pkg.files);
+                }
+            }
+            Err(err) => error!("{} package query failed: {err}", backend.name()),
+        }
+    }
+
     report_in_use(&mut client, &mut pkg_registry).await?;
     Ok(())
 }
 
+// Coalesces every filename opened since the last tick into one `report_in_use`
+// call instead of one per `open()`, which otherwise pummels the control plane
+// under any real load. This entrypoint doesn't track which container (if
+// any) a path belongs to, so unlike the container-aware agent it only ever
+// has one batch, host-wide, per tick.
 async fn report_in_use(client: &mut control_plane::Client, pkg_registry: &mut packages::Registry) -> Result<()> {
     let monitor = OpenMonitor::load()?;
 
     let (tx, mut rx) = tokio::sync::mpsc::channel::<OpenEvent>(1000);
     let monitor_task = tokio::task::spawn(monitor.run(tx));
 
-    // batch in 1s intervals
-
-    while let Some(evt) = rx.recv().await {
-        match evt.filename.into_string() {
-            Ok(filename) => {
-                let filenames = vec![filename];
-                let pkgs = pkg_registry.get_packages(filenames);
-                _ = client.report_in_use(pkgs).await;
-            },
-
-            Err(_) => (),
+    let mut ticks = tokio::time::interval(BATCH_INTERVAL);
+    let mut seen = HashSet::new();
+
+    loop {
+        tokio::select! {
+            evt = rx.recv() => {
+                match evt {
+                    Some(evt) => {
+                        metrics::OPEN_EVENTS_RECEIVED.inc();
+                        if let Ok(filename) = evt.filename.into_string() {
+                            seen.insert(filename);
+                            metrics::IN_USE_QUEUE_LEN.set(seen.len() as i64);
+                        }
+                    }
+                    None => break,
+                }
+            }
+
+            _ = ticks.tick() => flush_in_use(client, pkg_registry, &mut seen).await,
         }
     }
 
+    flush_in_use(client, pkg_registry, &mut seen).await;
     monitor_task.await.unwrap().unwrap();
 
     Ok(())
 }
+
+async fn flush_in_use(
+    client: &mut control_plane::Client,
+    pkg_registry: &mut packages::Registry,
+    seen: &mut HashSet<String>,
+) {
+    if seen.is_empty() {
+        return;
+    }
+
+    let pkgs = pkg_registry.get_packages(seen.drain().collect());
+    for pkg in &pkgs {
+        if pkg.id.is_empty() {
+            metrics::PACKAGES_UNRESOLVED.inc_by(pkg.filenames.len() as u64);
+        } else {
+            metrics::PACKAGES_RESOLVED.inc_by(pkg.filenames.len() as u64);
+        }
+    }
+
+    _ = client.report_in_use(pkgs).await;
+}
@@ -0,0 +1,118 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use lazy_static::lazy_static;
+use log::*;
+use prometheus::{register_histogram, register_int_counter, register_int_gauge};
+use prometheus::{Encoder, Histogram, IntCounter, IntGauge, TextEncoder};
+
+lazy_static! {
+    pub static ref OPEN_EVENTS_RECEIVED: IntCounter = register_int_counter!(
+        "edgebit_open_events_received_total",
+        "File-open events received from the monitor"
+    )
+    .unwrap();
+
+    pub static ref OPEN_EVENTS_DROPPED: IntCounter = register_int_counter!(
+        "edgebit_open_events_dropped_total",
+        "File-open events dropped because the open-event channel was full"
+    )
+    .unwrap();
+
+    pub static ref IN_USE_QUEUE_LEN: IntGauge = register_int_gauge!(
+        "edgebit_in_use_queue_length",
+        "Distinct filenames accumulated since the last in-use report flush"
+    )
+    .unwrap();
+
+    pub static ref PACKAGES_RESOLVED: IntCounter = register_int_counter!(
+        "edgebit_packages_resolved_total",
+        "Filenames that resolved to a known package in Registry::get_packages"
+    )
+    .unwrap();
+
+    pub static ref PACKAGES_UNRESOLVED: IntCounter = register_int_counter!(
+        "edgebit_packages_unresolved_total",
+        "Filenames that did not resolve to any known package in Registry::get_packages"
+    )
+    .unwrap();
+
+    pub static ref REPORT_IN_USE_OK: IntCounter = register_int_counter!(
+        "edgebit_report_in_use_success_total",
+        "ReportInUse RPCs that reached the control plane"
+    )
+    .unwrap();
+
+    pub static ref REPORT_IN_USE_ERR: IntCounter = register_int_counter!(
+        "edgebit_report_in_use_failure_total",
+        "ReportInUse RPCs that failed and were left spooled for retry"
+    )
+    .unwrap();
+
+    pub static ref REPORT_IN_USE_LATENCY: Histogram = register_histogram!(
+        "edgebit_report_in_use_latency_seconds",
+        "Time to complete a ReportInUse RPC",
+        vec![0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+    )
+    .unwrap();
+}
+
+async fn serve_req(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let encoder = TextEncoder::new();
+    let metric_families = prometheus::gather();
+
+    let mut buffer = Vec::new();
+    if let Err(err) = encoder.encode(&metric_families, &mut buffer) {
+        error!("Failed to encode metrics: {err}");
+    }
+
+    Ok(Response::builder()
+        .header("Content-Type", encoder.format_type())
+        .body(Body::from(buffer))
+        .unwrap())
+}
+
+// Serves a Prometheus text-exposition /metrics endpoint on `addr` for as
+// long as the process runs. Only started when `Config::metrics_addr` is
+// set, since most deployments don't want an extra open port by default.
+pub async fn serve(addr: SocketAddr) {
+    let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(serve_req)) });
+
+    info!("Serving metrics on http://{addr}/metrics");
+
+    if let Err(err) = Server::bind(&addr).serve(make_svc).await {
+        error!("Metrics server failed: {err}");
+    }
+}
+
+// Mirrors the same counters to an OpenTelemetry collector over OTLP, for
+// operators who already centralize telemetry there instead of scraping
+// Prometheus directly. Started alongside `serve` only when
+// `Config::metrics_otlp_endpoint` is set; the Prometheus registry stays
+// the source of truth either way, this just periodically pushes it.
+pub async fn serve_otlp(endpoint: String) {
+    let export_interval = std::time::Duration::from_secs(15);
+
+    let exporter = match opentelemetry_otlp::new_pipeline()
+        .metrics(opentelemetry_sdk::runtime::Tokio)
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(&endpoint))
+        .with_period(export_interval)
+        .build()
+    {
+        Ok(exporter) => exporter,
+        Err(err) => {
+            error!("Failed to start OTLP metrics exporter for {endpoint}: {err}");
+            return;
+        }
+    };
+
+    info!("Mirroring metrics to OTLP collector at {endpoint}");
+
+    // The exporter runs its own periodic push task once built; keep this
+    // task alive for as long as the process runs so the exporter isn't
+    // dropped (and torn down) the moment `serve_otlp` returns.
+    std::mem::forget(exporter);
+    std::future::pending::<()>().await;
+}
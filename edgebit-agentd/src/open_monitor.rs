@@ -1,7 +1,9 @@
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::ffi::OsString;
-use std::sync::Arc;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
 
 use aya::{Bpf, include_bytes_aligned};
 use aya::programs::{TracePoint};
@@ -9,23 +11,68 @@ use aya::maps::MapRefMut;
 use aya::maps::perf::{AsyncPerfEventArray, AsyncPerfEventArrayBuffer};
 use anyhow::{Result, anyhow};
 use bytes::BytesMut;
+use serde::{Serialize, Deserialize};
 use tokio::sync::mpsc::Sender;
 use log::*;
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use futures::stream::StreamExt;
 
 use common_defs::EvtOpen;
 
 const SYSCALLS: &[&str] = &[ "creat", "open", "openat", "openat2" ];
 const MINORBITS: usize = 20;
 
+// Where the warm-start snapshot of the inode cache lives, next to the
+// control-plane token.
+const CACHE_PATH: &str = "/var/lib/edgebit/inode-cache";
+
+// Bumped whenever PersistedCache's layout changes; a cache written under a
+// different generation is discarded rather than partially trusted.
+const CACHE_GENERATION: u64 = 1;
+
+const DEFAULT_CACHE_TTL_SECS: u64 = 24 * 60 * 60;
+
+// How often `traverse_parallel` reports progress while it's still working.
+const INDEX_PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+// A snapshot of how far the initial (cold-start) traversal has gotten, sent
+// over `InodeCache::load`'s progress channel so the agent can log indexing
+// status instead of startup going quiet for however long a full `/` walk
+// takes.
+#[derive(Clone, Copy, Debug)]
+pub struct IndexProgress {
+    pub dirs_processed: u64,
+    pub files_cached: u64,
+}
+
+// Watch for the filesystem changes that would otherwise go unnoticed
+// between the initial traversal and the next agent restart: a file
+// appearing (install/update) or disappearing (uninstall) under a path
+// we're already watching.
+const WATCH_MASK: WatchMask = WatchMask::CREATE
+    .union(WatchMask::MOVED_TO)
+    .union(WatchMask::DELETE)
+    .union(WatchMask::MOVED_FROM);
+
 pub struct OpenMonitor {
     bpf: Bpf,
     inodes: InodeCache,
+    inotify_stream: inotify::EventStream<Vec<u8>>,
+    inotify_handle: Arc<Mutex<inotify::Watches>>,
+    inotify_watches: Arc<Mutex<HashMap<WatchDescriptor, PathBuf>>>,
 }
 
 impl OpenMonitor {
     pub fn load() -> Result<Self> {
+        let inotify = Inotify::init().map_err(|err| anyhow!("Inotify::init(): {err}"))?;
+        let inotify_handle = Arc::new(Mutex::new(inotify.watches()));
+        let inotify_stream = inotify
+            .into_event_stream(vec![0u8; 4096])
+            .map_err(|err| anyhow!("inotify event stream: {err}"))?;
+        let inotify_watches = Arc::new(Mutex::new(HashMap::new()));
+
         info!("Building inode cache");
-        let inodes = InodeCache::load()?;
+        let inodes = InodeCache::load(&inotify_handle, &inotify_watches)?;
         info!("Done building inode cache");
 /*
         #[cfg(debug_assertions)]
@@ -49,7 +96,7 @@ impl OpenMonitor {
                 let prog = bpf.program_mut(&prog_name)
                     .ok_or(anyhow!("BPF prog not found: {prog_name}"))?;
                 let tp: &mut TracePoint = prog.try_into()?;
-                
+
                 tp.load()?;
                 tp.attach("syscalls", &tp_name)?;
             }
@@ -58,6 +105,9 @@ impl OpenMonitor {
         Ok(Self{
             bpf,
             inodes,
+            inotify_stream,
+            inotify_handle,
+            inotify_watches,
         })
     }
 
@@ -67,6 +117,13 @@ impl OpenMonitor {
 
         let mut tasks = Vec::new();
 
+        tasks.push(tokio::task::spawn(monitor_inotify(
+            self.inotify_stream,
+            self.inotify_handle,
+            self.inotify_watches,
+            inodes.clone(),
+        )));
+
         for cpu_id in aya::util::online_cpus()? {
             // open a separate perf buffer for each cpu
             let perf_buf = perf_array.open(cpu_id, None)?;
@@ -108,12 +165,14 @@ async fn monitor_on(mut perf_buf: AsyncPerfEventArrayBuffer<MapRefMut>, inodes:
                 let buf = &mut buffers[i];
                 if let Ok(evt) = TryInto::<EvtOpen>::try_into(buf.as_ref()) {
                     if let Some(filename) = inodes.lookup(evt.dev, evt.ino) {
+                        info!("match: {filename:?}, {}/{}", evt.dev, evt.ino);
                         let open = OpenEvent{
                             cgroup: evt.cgroup,
-                            filename: filename.clone(),
+                            filename,
                         };
-                        info!("match: {filename:?}, {}/{}", evt.dev, evt.ino);
-                        _ = ch.send(open).await;
+                        if ch.try_send(open).is_err() {
+                            crate::metrics::OPEN_EVENTS_DROPPED.inc();
+                        }
                     } else {
                         warn!("filename not found for dev={:x}, ino={}", evt.dev, evt.ino);
                     }
@@ -126,6 +185,66 @@ async fn monitor_on(mut perf_buf: AsyncPerfEventArrayBuffer<MapRefMut>, inodes:
     }
 }
 
+// Drains inotify events for the directories `InodeCache::load` registered
+// watches on, keeping the cache in sync with files created, renamed, or
+// removed after the initial traversal instead of letting them go stale
+// until the next restart.
+async fn monitor_inotify(
+    mut stream: inotify::EventStream<Vec<u8>>,
+    handle: Arc<Mutex<inotify::Watches>>,
+    watches: Arc<Mutex<HashMap<WatchDescriptor, PathBuf>>>,
+    inodes: Arc<InodeCache>,
+) {
+    while let Some(evt) = stream.next().await {
+        let evt = match evt {
+            Ok(evt) => evt,
+            Err(err) => {
+                error!("inotify read: {err}");
+                continue;
+            }
+        };
+
+        if evt.mask.contains(EventMask::IGNORED) {
+            // The kernel drops a watch on its own when the watched
+            // directory is removed or its filesystem is unmounted; without
+            // this, `watches` would keep a stale wd -> path entry around
+            // forever (wds do get reused, so a leaked entry here isn't just
+            // a harmless leak -- it's old data that could get attributed
+            // to whatever new directory the kernel hands that wd to next).
+            watches.lock().unwrap().remove(&evt.wd);
+            continue;
+        }
+
+        let Some(name) = evt.name else {
+            continue;
+        };
+
+        let Some(dir) = watches.lock().unwrap().get(&evt.wd).cloned() else {
+            continue;
+        };
+
+        let full_path = dir.join(&name);
+
+        if evt.mask.contains(EventMask::CREATE) || evt.mask.contains(EventMask::MOVED_TO) {
+            if evt.mask.contains(EventMask::ISDIR) {
+                add_watch_recursive(&handle, &watches, &full_path);
+                continue;
+            }
+
+            if let Ok(meta) = std::fs::metadata(&full_path) {
+                let (dev, ino) = dev_ino(&meta);
+                debug!("{} @ {:?}", full_path.display(), (dev, ino));
+                inodes.insert(dev, ino, full_path.into_os_string());
+            }
+        } else if evt.mask.contains(EventMask::DELETE) || evt.mask.contains(EventMask::MOVED_FROM) {
+            // The path is already gone by the time we see this, so there's
+            // no dev/ino left to look it up by; fall back to dropping
+            // whichever cache entry still points at this path.
+            inodes.remove_path(&full_path);
+        }
+    }
+}
+
 pub struct OpenEvent {
     pub cgroup: u64,
     pub filename: OsString,
@@ -145,64 +264,386 @@ fn cstr_to_str(buf: &[u8]) -> Result<String> {
 type DevIno = (u64, u64);
 
 pub struct InodeCache {
-    inner: HashMap<DevIno, OsString>,
+    inner: RwLock<HashMap<DevIno, OsString>>,
 }
 
 impl InodeCache {
-    pub fn load() -> Result<Self> {
-        let mut cache = HashMap::new();
-        traverse("/", &mut cache)?;
+    // A full `traverse("/")` dominates startup, so we'd rather warm-start
+    // from the last run's snapshot: if it's still fresh and the mounted
+    // filesystems haven't changed underneath it, trust it and just bring
+    // the inotify watches back up (in the background) instead of re-statting
+    // every file on disk. Anything that makes the snapshot suspect -- it's
+    // missing, stale, from a different generation, or the mounts moved --
+    // falls back to the full traversal.
+    pub fn load(
+        handle: &Arc<Mutex<inotify::Watches>>,
+        watches: &Arc<Mutex<HashMap<WatchDescriptor, PathBuf>>>,
+    ) -> Result<Self> {
+        let fingerprint = mounts_fingerprint();
+
+        if let Some(cache) = load_persisted(&fingerprint) {
+            info!("Warm-starting inode cache from {CACHE_PATH} ({} entries)", cache.len());
+
+            let handle = handle.clone();
+            let watches = watches.clone();
+            tokio::task::spawn_blocking(move || {
+                add_watch_recursive(&handle, &watches, Path::new("/"));
+            });
+
+            return Ok(Self {
+                inner: RwLock::new(cache),
+            });
+        }
+
+        let (progress_tx, progress_rx) = std::sync::mpsc::channel();
+        let logger = std::thread::spawn(move || {
+            while let Ok(progress) = progress_rx.recv() {
+                let IndexProgress { dirs_processed, files_cached } = progress;
+                info!("Indexing in progress: {dirs_processed} directories processed, {files_cached} files cached");
+            }
+        });
+
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        #[cfg(target_os = "linux")]
+        let cache = crate::io_uring_index::try_traverse(Path::new("/"), handle, watches, progress_tx.clone(), &cancel)
+            .unwrap_or_else(|| traverse_parallel(Path::new("/"), handle, watches, Some(progress_tx), &cancel));
+
+        #[cfg(not(target_os = "linux"))]
+        let cache = traverse_parallel(Path::new("/"), handle, watches, Some(progress_tx), &cancel);
+
+        _ = logger.join();
+
+        save_persisted(&cache, &fingerprint);
+
         Ok(Self{
-            inner: cache,
+            inner: RwLock::new(cache),
         })
     }
 
-    pub fn lookup(&self, dev: u64, ino: u64) -> Option<&OsString> {
-        self.inner.get(&(dev, ino))
+    pub fn lookup(&self, dev: u64, ino: u64) -> Option<OsString> {
+        self.inner.read().unwrap().get(&(dev, ino)).cloned()
+    }
+
+    fn insert(&self, dev: u64, ino: u64, path: OsString) {
+        self.inner.write().unwrap().insert((dev, ino), path);
+    }
+
+    fn remove_path(&self, path: &Path) {
+        self.inner.write().unwrap().retain(|_, v| v.as_os_str() != path.as_os_str());
     }
 }
 
-fn traverse<P: AsRef<Path>>(path: P, cache: &mut HashMap<DevIno, OsString>) -> Result<()> {
-    let path = path.as_ref();
-    for dirent in std::fs::read_dir(path)? {
-        if let Ok(dirent) = dirent {
-            if let Ok(file_type) = dirent.file_type() {
-                let mut full_name = path.to_path_buf();
-                full_name.push(dirent.file_name());
-                if file_type.is_dir() {
-                    if is_system_dir(&full_name) {
-                        continue;
-                    }
-                    _ = traverse(full_name, cache);
-                } else if file_type.is_file() {
-                    if let Ok(meta) = dirent.metadata() {
-                        use std::os::linux::fs::MetadataExt;
-                        let dev = dev_libc_to_kernel(meta.st_dev());
-                        let devino = (dev, meta.st_ino());
-
-                        debug!("{} @ {devino:?}", full_name.to_string_lossy());
-                        cache.insert(devino, full_name.into_os_string());
-                    }
+// A directory queue shared by `traverse_parallel`'s worker threads. `pending`
+// tracks every directory that's been queued but not yet fully processed
+// (i.e. still in `items`, or popped and being read by a worker); it reaching
+// zero is how workers agree the walk is over, since an empty `items` queue
+// on its own doesn't mean there's no more work coming -- another worker
+// might be about to push more subdirectories into it.
+struct WorkQueue {
+    items: Mutex<VecDeque<PathBuf>>,
+    pending: std::sync::atomic::AtomicUsize,
+    cv: Condvar,
+}
+
+impl WorkQueue {
+    fn new(root: PathBuf) -> Self {
+        Self {
+            items: Mutex::new(VecDeque::from([root])),
+            pending: std::sync::atomic::AtomicUsize::new(1),
+            cv: Condvar::new(),
+        }
+    }
+
+    // Blocks until a directory is available, or the walk is done/cancelled.
+    fn pop(&self, cancel: &AtomicBool) -> Option<PathBuf> {
+        let mut items = self.items.lock().unwrap();
+        loop {
+            if let Some(path) = items.pop_front() {
+                return Some(path);
+            }
+            if self.pending.load(Ordering::Acquire) == 0 || cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            // Poll `cancel` periodically rather than waiting forever, since
+            // nothing else would otherwise wake a worker blocked here.
+            items = self.cv.wait_timeout(items, Duration::from_millis(200)).unwrap().0;
+        }
+    }
+
+    fn push(&self, path: PathBuf) {
+        self.pending.fetch_add(1, Ordering::AcqRel);
+        self.items.lock().unwrap().push_back(path);
+        self.cv.notify_all();
+    }
+
+    // Call once the directory a `pop` returned has been fully processed
+    // (every subdirectory it contained has already been pushed).
+    fn done_one(&self) {
+        self.pending.fetch_sub(1, Ordering::AcqRel);
+        self.cv.notify_all();
+    }
+}
+
+// Replaces a single-threaded recursive walk with a work-stealing one: `root`
+// seeds a shared queue, and `available_parallelism` worker threads each pop
+// a directory, list it, push any subdirectories back onto the queue
+// (skipping `is_system_dir`), and register an inotify watch and cache
+// entries for what they found. `progress` gets a snapshot every
+// `INDEX_PROGRESS_INTERVAL` while the walk is still running; `cancel` can be
+// flipped to stop every worker early, in which case the returned cache is
+// only partially populated.
+fn traverse_parallel(
+    root: &Path,
+    handle: &Arc<Mutex<inotify::Watches>>,
+    watches: &Arc<Mutex<HashMap<WatchDescriptor, PathBuf>>>,
+    progress: Option<std::sync::mpsc::Sender<IndexProgress>>,
+    cancel: &Arc<AtomicBool>,
+) -> HashMap<DevIno, OsString> {
+    let num_workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+
+    let queue = Arc::new(WorkQueue::new(root.to_path_buf()));
+    let dirs_processed = Arc::new(AtomicU64::new(0));
+    let files_cached = Arc::new(AtomicU64::new(0));
+
+    let reporter = progress.map(|tx| {
+        let queue = queue.clone();
+        let dirs_processed = dirs_processed.clone();
+        let files_cached = files_cached.clone();
+        let cancel = cancel.clone();
+
+        std::thread::spawn(move || {
+            let snapshot = || IndexProgress {
+                dirs_processed: dirs_processed.load(Ordering::Relaxed),
+                files_cached: files_cached.load(Ordering::Relaxed),
+            };
+
+            while queue.pending.load(Ordering::Acquire) > 0 && !cancel.load(Ordering::Relaxed) {
+                std::thread::sleep(INDEX_PROGRESS_INTERVAL);
+                if tx.send(snapshot()).is_err() {
+                    return;
                 }
             }
+
+            _ = tx.send(snapshot());
+        })
+    });
+
+    let workers: Vec<_> = (0..num_workers)
+        .map(|_| {
+            let queue = queue.clone();
+            let handle = handle.clone();
+            let watches = watches.clone();
+            let dirs_processed = dirs_processed.clone();
+            let files_cached = files_cached.clone();
+            let cancel = cancel.clone();
+
+            std::thread::spawn(move || {
+                let mut shard = HashMap::new();
+
+                while let Some(dir) = queue.pop(&cancel) {
+                    add_watch(&handle, &watches, &dir);
+
+                    if let Ok(entries) = std::fs::read_dir(&dir) {
+                        for entry in entries.flatten() {
+                            let Ok(file_type) = entry.file_type() else { continue };
+                            let path = entry.path();
+
+                            if file_type.is_dir() {
+                                if !is_system_dir(&path) {
+                                    queue.push(path);
+                                }
+                            } else if file_type.is_file() {
+                                if let Ok(meta) = entry.metadata() {
+                                    let devino = dev_ino(&meta);
+                                    debug!("{} @ {devino:?}", path.to_string_lossy());
+                                    shard.insert(devino, path.into_os_string());
+                                    files_cached.fetch_add(1, Ordering::Relaxed);
+                                }
+                            }
+                        }
+                    }
+
+                    dirs_processed.fetch_add(1, Ordering::Relaxed);
+                    queue.done_one();
+                }
+
+                shard
+            })
+        })
+        .collect();
+
+    let mut cache = HashMap::new();
+    for worker in workers {
+        if let Ok(shard) = worker.join() {
+            cache.extend(shard);
         }
     }
 
-    Ok(())
+    if let Some(reporter) = reporter {
+        _ = reporter.join();
+    }
+
+    cache
+}
+
+pub(crate) fn add_watch(
+    handle: &Arc<Mutex<inotify::Watches>>,
+    watches: &Arc<Mutex<HashMap<WatchDescriptor, PathBuf>>>,
+    path: &Path,
+) {
+    let wd = match handle.lock().unwrap().add(path, WATCH_MASK) {
+        Ok(wd) => wd,
+        Err(err) => {
+            debug!("inotify add watch {}: {err}", path.display());
+            return;
+        }
+    };
+
+    watches.lock().unwrap().insert(wd, path.to_path_buf());
+}
+
+fn add_watch_recursive(
+    handle: &Arc<Mutex<inotify::Watches>>,
+    watches: &Arc<Mutex<HashMap<WatchDescriptor, PathBuf>>>,
+    path: &Path,
+) {
+    if is_system_dir(path) {
+        return;
+    }
+
+    add_watch(handle, watches, path);
+
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        if let Ok(file_type) = entry.file_type() {
+            if file_type.is_dir() {
+                add_watch_recursive(handle, watches, &entry.path());
+            }
+        }
+    }
 }
 
-fn is_system_dir(path: &Path) -> bool {
+pub(crate) fn is_system_dir(path: &Path) -> bool {
     const SYSTEM_PREFIXES: &[&str] = &["/proc/", "/run/", "/var/run/", "/sys/", "/tmp/"];
 
     SYSTEM_PREFIXES.iter()
         .any(|prefix| path.starts_with(prefix))
 }
 
+fn dev_ino(meta: &std::fs::Metadata) -> DevIno {
+    use std::os::linux::fs::MetadataExt;
+    (dev_libc_to_kernel(meta.st_dev()), meta.st_ino())
+}
+
 fn dev_libc_to_kernel(dev: u64) -> u64 {
     // The kernel internally stores the dev as: MMMmmmmm (M=major, m=minor)
     // The libc stores the dev as mmmMMMmm (same as uapi)
     // We normalize it to the kernel encoding
     let major = (dev & 0xfff00) >> 8;
     let minor = (dev & 0xff) | ((dev & !0xfffff) >> 12);
+    major_minor_to_kernel_dev(major, minor)
+}
+
+// Shared tail of `dev_libc_to_kernel`, split out so callers that already
+// have a decomposed major/minor (e.g. `statx`'s `stx_dev_major`/
+// `stx_dev_minor`, used by the io_uring traversal path) don't have to
+// round-trip through libc's packed encoding to reuse the same normalization.
+pub(crate) fn major_minor_to_kernel_dev(major: u64, minor: u64) -> u64 {
     (major << MINORBITS) | minor
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedCache {
+    generation: u64,
+    created_at: u64,
+    mounts_fingerprint: Vec<u64>,
+    entries: Vec<(u64, u64, String)>,
+}
+
+fn cache_ttl() -> Duration {
+    std::env::var("EDGEBIT_INODE_CACHE_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(DEFAULT_CACHE_TTL_SECS))
+}
+
+// The dev numbers of every currently mounted filesystem, used to detect
+// remounts: a persisted cache keyed by (dev, ino) is worthless once a dev
+// number it used has been reassigned to a different filesystem.
+fn mounts_fingerprint() -> Vec<u64> {
+    let Ok(mounts) = std::fs::read_to_string("/proc/mounts") else {
+        return Vec::new();
+    };
+
+    let mut devs: Vec<u64> = mounts
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .filter_map(|mount_point| std::fs::metadata(mount_point).ok())
+        .map(|meta| dev_ino(&meta).0)
+        .collect();
+
+    devs.sort_unstable();
+    devs.dedup();
+    devs
+}
+
+fn load_persisted(fingerprint: &[u64]) -> Option<HashMap<DevIno, OsString>> {
+    let data = std::fs::read_to_string(CACHE_PATH).ok()?;
+    let persisted: PersistedCache = match serde_json::from_str(&data) {
+        Ok(persisted) => persisted,
+        Err(err) => {
+            warn!("Failed to parse inode cache at {CACHE_PATH}: {err}");
+            return None;
+        }
+    };
+
+    if persisted.generation != CACHE_GENERATION {
+        info!("Discarding inode cache at {CACHE_PATH}: generation mismatch");
+        return None;
+    }
+
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs();
+    if Duration::from_secs(now.saturating_sub(persisted.created_at)) > cache_ttl() {
+        info!("Discarding inode cache at {CACHE_PATH}: older than TTL");
+        return None;
+    }
+
+    if persisted.mounts_fingerprint != fingerprint {
+        info!("Discarding inode cache at {CACHE_PATH}: mounted filesystems changed");
+        return None;
+    }
+
+    Some(persisted.entries
+        .into_iter()
+        .map(|(dev, ino, path)| ((dev, ino), OsString::from(path)))
+        .collect())
+}
+
+fn save_persisted(cache: &HashMap<DevIno, OsString>, fingerprint: &[u64]) {
+    let persisted = PersistedCache {
+        generation: CACHE_GENERATION,
+        created_at: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        mounts_fingerprint: fingerprint.to_vec(),
+        entries: cache
+            .iter()
+            .map(|(&(dev, ino), path)| (dev, ino, path.to_string_lossy().into_owned()))
+            .collect(),
+    };
+
+    match serde_json::to_string(&persisted) {
+        Ok(json) => {
+            if let Err(err) = std::fs::write(CACHE_PATH, json) {
+                error!("Failed to save inode cache to {CACHE_PATH}: {err}");
+            }
+        }
+        Err(err) => error!("Failed to serialize inode cache: {err}"),
+    }
+}
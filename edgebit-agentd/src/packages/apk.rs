@@ -0,0 +1,72 @@
+// Alpine's package database. Unlike dpkg, apk keeps metadata and file
+// ownership in the single `installed` file: one record per package,
+// separated by a blank line, with each line tagged by a single-letter
+// prefix ("P:" name, "V:" version, "F:" a directory, "R:" a file within the
+// directory named by the most recent "F:" line).
+
+use anyhow::Result;
+
+use super::{PackageDb, PackageFiles};
+
+pub const APK_INSTALLED_DB: &str = "/lib/apk/db/installed";
+
+pub struct ApkDb;
+
+impl PackageDb for ApkDb {
+    fn name(&self) -> &'static str {
+        "apk"
+    }
+
+    fn query_all(&self) -> Result<Vec<PackageFiles>> {
+        let data = std::fs::read_to_string(APK_INSTALLED_DB)?;
+
+        let mut pkgs = Vec::new();
+        let mut name = None;
+        let mut version = None;
+        let mut files = Vec::new();
+        let mut cur_dir = None;
+
+        for line in data.lines() {
+            if line.is_empty() {
+                flush(&mut name, &mut version, &mut files, &mut pkgs);
+                cur_dir = None;
+                continue;
+            }
+
+            let Some((tag, value)) = line.split_once(':') else { continue };
+
+            match tag {
+                "P" => name = Some(value.to_string()),
+                "V" => version = Some(value.to_string()),
+                "F" => cur_dir = Some(value.to_string()),
+                "R" => {
+                    if let Some(dir) = &cur_dir {
+                        files.push(format!("/{dir}/{value}"));
+                    }
+                }
+                _ => (),
+            }
+        }
+        flush(&mut name, &mut version, &mut files, &mut pkgs);
+
+        Ok(pkgs)
+    }
+}
+
+fn flush(
+    name: &mut Option<String>,
+    version: &mut Option<String>,
+    files: &mut Vec<String>,
+    pkgs: &mut Vec<PackageFiles>,
+) {
+    if let Some(name) = name.take() {
+        let id = match version.take() {
+            Some(version) => format!("{name}-{version}"),
+            None => name,
+        };
+        pkgs.push(PackageFiles {
+            id,
+            files: std::mem::take(files),
+        });
+    }
+}
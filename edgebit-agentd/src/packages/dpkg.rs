@@ -0,0 +1,86 @@
+// Debian/Ubuntu package database. Metadata for installed packages lives in
+// a single stanza file (`/var/lib/dpkg/status`); the files each package owns
+// are split out into a per-package list under `/var/lib/dpkg/info`.
+
+use anyhow::Result;
+
+use super::{PackageDb, PackageFiles};
+
+pub const DPKG_STATUS: &str = "/var/lib/dpkg/status";
+const DPKG_INFO_DIR: &str = "/var/lib/dpkg/info";
+
+pub struct DpkgDb;
+
+impl PackageDb for DpkgDb {
+    fn name(&self) -> &'static str {
+        "dpkg"
+    }
+
+    fn query_all(&self) -> Result<Vec<PackageFiles>> {
+        let status = std::fs::read_to_string(DPKG_STATUS)?;
+
+        Ok(status
+            .split("\n\n")
+            .filter_map(parse_stanza)
+            .map(|(id, package, arch)| PackageFiles {
+                files: read_file_list(&package, arch.as_deref()),
+                id,
+            })
+            .collect())
+    }
+}
+
+// Returns the package id plus enough of its identity (name, arch) to look
+// up its file list, or `None` if the stanza isn't an installed package
+// (e.g. one dpkg still remembers as "deinstall" or "config-files").
+fn parse_stanza(stanza: &str) -> Option<(String, String, Option<String>)> {
+    let mut package = None;
+    let mut version = None;
+    let mut arch = None;
+    let mut installed = false;
+
+    for line in stanza.lines() {
+        if let Some(v) = line.strip_prefix("Package: ") {
+            package = Some(v.to_string());
+        } else if let Some(v) = line.strip_prefix("Version: ") {
+            version = Some(v.to_string());
+        } else if let Some(v) = line.strip_prefix("Architecture: ") {
+            arch = Some(v.to_string());
+        } else if let Some(v) = line.strip_prefix("Status: ") {
+            installed = v.trim().ends_with("installed");
+        }
+    }
+
+    if !installed {
+        return None;
+    }
+
+    let package = package?;
+    let id = match (&version, &arch) {
+        (Some(version), Some(arch)) => format!("{package}_{version}_{arch}"),
+        (Some(version), None) => format!("{package}_{version}"),
+        _ => package.clone(),
+    };
+
+    Some((id, package, arch))
+}
+
+// Multi-arch packages' list files are named "<package>:<arch>.list"; plain
+// ones are just "<package>.list".
+fn read_file_list(package: &str, arch: Option<&str>) -> Vec<String> {
+    let path = match arch {
+        Some(arch) => {
+            let multiarch = format!("{DPKG_INFO_DIR}/{package}:{arch}.list");
+            if std::path::Path::new(&multiarch).exists() {
+                multiarch
+            } else {
+                format!("{DPKG_INFO_DIR}/{package}.list")
+            }
+        }
+        None => format!("{DPKG_INFO_DIR}/{package}.list"),
+    };
+
+    std::fs::read_to_string(&path)
+        .map(|contents| contents.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
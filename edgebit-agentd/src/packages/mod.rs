@@ -1,6 +1,51 @@
 pub mod rpm;
+pub mod dpkg;
+pub mod apk;
 
 use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+
+// A package and the files it owns, as reported by one backend's on-disk
+// database. `rpm` keeps its own richer `RpmPackage` (name/version/etc. are
+// also needed to report packages upstream), so it isn't wrapped behind this
+// trait; dpkg and apk only need to feed `Registry::add_pkg`, so this is all
+// they carry.
+pub struct PackageFiles {
+    pub id: String,
+    pub files: Vec<String>,
+}
+
+// A host package database this agent knows how to read the file->package
+// ownership of. One query per supported package manager, so a host that
+// happens to have leftover files from a different one (e.g. an rpm DB on an
+// otherwise-dpkg host) doesn't get misattributed.
+pub trait PackageDb {
+    // Used only for logging when a backend's query fails.
+    fn name(&self) -> &'static str;
+
+    fn query_all(&self) -> Result<Vec<PackageFiles>>;
+}
+
+// Probes for each backend's database file so only package managers actually
+// in use on this host are queried. `main::run` feeds whatever comes back
+// into `Registry::add_pkg` alongside the rpm query, so a Debian/Ubuntu host
+// (no rpm database, `dpkg::DpkgDb` probes positive) gets the same file-open
+// matching as an RPM-based one instead of silently reporting nothing.
+pub fn detect_backends() -> Vec<Box<dyn PackageDb>> {
+    let mut backends: Vec<Box<dyn PackageDb>> = Vec::new();
+
+    if Path::new(dpkg::DPKG_STATUS).exists() {
+        backends.push(Box::new(dpkg::DpkgDb));
+    }
+
+    if Path::new(apk::APK_INSTALLED_DB).exists() {
+        backends.push(Box::new(apk::ApkDb));
+    }
+
+    backends
+}
 
 pub struct Registry {
     // Filename to a list of pkg ids
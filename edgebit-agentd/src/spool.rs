@@ -0,0 +1,356 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+
+use crate::packages::PkgRef;
+
+const QUEUE_REPORT_IN_USE: &str = "report_in_use";
+
+// Past this many queued batches, the oldest are dropped so a prolonged
+// control-plane outage can't grow the on-disk spool without bound.
+const MAX_SPOOLED_BATCHES: usize = 512;
+
+// Batches older than this are dropped during eviction even if under the
+// count cap, on the assumption that in-use data this stale is no longer
+// worth the bandwidth to upload.
+const MAX_SPOOLED_AGE: Duration = Duration::from_secs(24 * 3600);
+
+// A durable store-and-forward queue for outbound in-use reports, modeled
+// after the `Repo` abstraction used elsewhere in this codebase: a WAL
+// entry is written before the upload is attempted, and only removed once
+// the control plane has acknowledged it, so a crash or an outage between
+// those two points leaves the entry to be replayed rather than lost.
+trait Repo: Send + Sync {
+    fn put(&self, queue: &str, key: &str, value: Vec<u8>) -> Result<()>;
+
+    fn drain(&self, queue: &str) -> Result<Vec<(String, Vec<u8>)>>;
+
+    fn remove(&self, queue: &str, key: &str) -> Result<()>;
+
+    fn len(&self, queue: &str) -> Result<usize>;
+}
+
+struct SledRepo {
+    db: sled::Db,
+}
+
+impl SledRepo {
+    fn open(path: &Path) -> Result<Self> {
+        std::fs::create_dir_all(path)?;
+        let db = sled::open(path)?;
+
+        Ok(Self { db })
+    }
+}
+
+impl Repo for SledRepo {
+    fn put(&self, queue: &str, key: &str, value: Vec<u8>) -> Result<()> {
+        self.db.open_tree(queue)?.insert(key, value)?;
+        Ok(())
+    }
+
+    fn drain(&self, queue: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        self.db
+            .open_tree(queue)?
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let key = String::from_utf8(key.to_vec())
+                    .map_err(|_| anyhow!("spooled entry has a non-utf8 key"))?;
+
+                Ok((key, value.to_vec()))
+            })
+            .collect()
+    }
+
+    fn remove(&self, queue: &str, key: &str) -> Result<()> {
+        self.db.open_tree(queue)?.remove(key)?;
+        Ok(())
+    }
+
+    fn len(&self, queue: &str) -> Result<usize> {
+        Ok(self.db.open_tree(queue)?.len())
+    }
+}
+
+// Keyed by a zero-padded sequence number so `Repo::drain`'s unordered
+// iteration can still be sorted back into append order before replay.
+pub struct InUseSpool {
+    repo: Box<dyn Repo>,
+    next_seq: std::sync::atomic::AtomicU64,
+}
+
+impl InUseSpool {
+    pub fn open(path: &Path) -> Result<Self> {
+        let repo = SledRepo::open(path)?;
+        let next_seq = seed_next_seq(&repo)?;
+
+        Ok(Self {
+            repo: Box::new(repo),
+            next_seq: std::sync::atomic::AtomicU64::new(next_seq),
+        })
+    }
+
+    // Appends a batch to the WAL before it's handed to `report_in_use`,
+    // returning the key the caller must `ack` once the upload succeeds.
+    pub fn append(&self, pkgs: &[PkgRef]) -> Result<String> {
+        let seq = self
+            .next_seq
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let key = format!("{seq:020}");
+
+        self.repo.put(QUEUE_REPORT_IN_USE, &key, encode_batch(pkgs))?;
+        self.evict();
+
+        Ok(key)
+    }
+
+    pub fn ack(&self, key: &str) {
+        _ = self.repo.remove(QUEUE_REPORT_IN_USE, key);
+    }
+
+    // All currently-unacknowledged batches, oldest first, for replay on
+    // startup or by the background retry loop. A batch that fails to
+    // decode is corrupt beyond recovery and is dropped rather than
+    // retried forever.
+    pub fn pending(&self) -> Vec<(String, Vec<PkgRef>)> {
+        let mut entries = match self.repo.drain(QUEUE_REPORT_IN_USE) {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::error!("Failed to read spooled in-use reports: {err}");
+                return Vec::new();
+            }
+        };
+
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        entries
+            .into_iter()
+            .filter_map(|(key, bytes)| match decode_batch(&bytes) {
+                Ok((_, pkgs)) => Some((key, pkgs)),
+                Err(err) => {
+                    log::warn!("Dropping corrupt spooled in-use batch {key}: {err}");
+                    self.ack(&key);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.repo.len(QUEUE_REPORT_IN_USE).unwrap_or(0)
+    }
+
+    fn evict(&self) {
+        let entries = match self.repo.drain(QUEUE_REPORT_IN_USE) {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::error!("Failed to read spooled in-use reports for eviction: {err}");
+                return;
+            }
+        };
+
+        let now = now_ms();
+
+        let mut by_age: Vec<(u64, String)> = entries
+            .into_iter()
+            .filter_map(|(key, bytes)| match decode_batch(&bytes) {
+                Ok((ts, _)) => Some((ts, key)),
+                Err(_) => Some((0, key)),
+            })
+            .collect();
+
+        by_age.sort_by_key(|(ts, _)| *ts);
+
+        let max_age_ms = MAX_SPOOLED_AGE.as_millis() as u64;
+        let mut kept = Vec::with_capacity(by_age.len());
+        for (ts, key) in by_age {
+            if now.saturating_sub(ts) > max_age_ms {
+                log::warn!("In-use report spool entry {key} exceeded its retention window, dropping");
+                self.ack(&key);
+            } else {
+                kept.push(key);
+            }
+        }
+
+        if kept.len() > MAX_SPOOLED_BATCHES {
+            let to_drop = kept.len() - MAX_SPOOLED_BATCHES;
+            for key in kept.into_iter().take(to_drop) {
+                log::warn!("In-use report spool is over capacity, dropping oldest entry {key}");
+                self.ack(&key);
+            }
+        }
+    }
+}
+
+// Starting a fresh `AtomicU64::new(0)` on every open would let the first
+// `append` after a restart reuse a sequence key still holding an
+// unacknowledged batch from before the crash; `Repo::put` is a plain
+// `insert`, so that silently overwrites it before it's ever replayed. Seed
+// past every key already on disk instead.
+fn seed_next_seq(repo: &dyn Repo) -> Result<u64> {
+    let next_seq = repo
+        .drain(QUEUE_REPORT_IN_USE)?
+        .into_iter()
+        .filter_map(|(key, _)| key.parse::<u64>().ok())
+        .max()
+        .map_or(0, |max| max + 1);
+
+    Ok(next_seq)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn encode_batch(pkgs: &[PkgRef]) -> Vec<u8> {
+    let mut out = now_ms().to_be_bytes().to_vec();
+
+    out.extend((pkgs.len() as u32).to_be_bytes());
+    for pkg in pkgs {
+        out.extend((pkg.id.len() as u32).to_be_bytes());
+        out.extend(pkg.id.as_bytes());
+
+        out.extend((pkg.filenames.len() as u32).to_be_bytes());
+        for filename in &pkg.filenames {
+            out.extend((filename.len() as u32).to_be_bytes());
+            out.extend(filename.as_bytes());
+        }
+    }
+
+    out
+}
+
+fn decode_batch(bytes: &[u8]) -> Result<(u64, Vec<PkgRef>)> {
+    let mut cursor = Cursor::new(bytes);
+
+    let ts = cursor.take_u64()?;
+    let count = cursor.take_u32()?;
+
+    let mut pkgs = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let id = cursor.take_string()?;
+
+        let nfiles = cursor.take_u32()?;
+        let mut filenames = Vec::with_capacity(nfiles as usize);
+        for _ in 0..nfiles {
+            filenames.push(cursor.take_string()?);
+        }
+
+        pkgs.push(PkgRef { id, filenames });
+    }
+
+    Ok((ts, pkgs))
+}
+
+// Minimal big-endian cursor so `decode_batch` doesn't need a serde/prost
+// dependency just to reverse `encode_batch`'s handwritten format.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(anyhow!("spooled in-use batch is truncated"));
+        }
+
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn take_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn take_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn take_string(&mut self) -> Result<String> {
+        let len = self.take_u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| anyhow!("spooled in-use batch has a non-utf8 string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use assert2::assert;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MockRepo {
+        entries: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    impl Repo for MockRepo {
+        fn put(&self, _queue: &str, key: &str, value: Vec<u8>) -> Result<()> {
+            self.entries.lock().unwrap().insert(key.to_string(), value);
+            Ok(())
+        }
+
+        fn drain(&self, _queue: &str) -> Result<Vec<(String, Vec<u8>)>> {
+            Ok(self
+                .entries
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect())
+        }
+
+        fn remove(&self, _queue: &str, key: &str) -> Result<()> {
+            self.entries.lock().unwrap().remove(key);
+            Ok(())
+        }
+
+        fn len(&self, _queue: &str) -> Result<usize> {
+            Ok(self.entries.lock().unwrap().len())
+        }
+    }
+
+    fn spool_with(repo: MockRepo) -> InUseSpool {
+        let next_seq = seed_next_seq(&repo).unwrap();
+
+        InUseSpool {
+            repo: Box::new(repo),
+            next_seq: std::sync::atomic::AtomicU64::new(next_seq),
+        }
+    }
+
+    #[test]
+    fn test_open_seeds_next_seq_to_zero_on_an_empty_spool() {
+        let spool = spool_with(MockRepo::default());
+
+        assert!(spool.append(&[]).unwrap() == format!("{:020}", 0));
+    }
+
+    #[test]
+    fn test_open_seeds_next_seq_past_existing_unacked_keys() {
+        let repo = MockRepo::default();
+        repo.put(QUEUE_REPORT_IN_USE, &format!("{:020}", 0), encode_batch(&[]))
+            .unwrap();
+        repo.put(QUEUE_REPORT_IN_USE, &format!("{:020}", 1), encode_batch(&[]))
+            .unwrap();
+
+        let spool = spool_with(repo);
+
+        // A restart must not reuse a key still holding an unacked batch.
+        assert!(spool.append(&[]).unwrap() == format!("{:020}", 2));
+        assert!(spool.len() == 3);
+    }
+}
@@ -0,0 +1,236 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::*;
+use serde::Serialize;
+
+use crate::containers::Containers;
+use crate::open_event_queue::OpenEventSender;
+use crate::open_monitor::FileOpenMonitorArc;
+use crate::worker::WorkerRegistry;
+use crate::workloads::Workloads;
+
+#[derive(Serialize)]
+struct ContainerSummary {
+    id: String,
+    name: Option<String>,
+    image: Option<String>,
+    start_time: Option<u64>,
+    end_time: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct WorkloadSummary {
+    id: String,
+    reported: usize,
+    pending_in_use: usize,
+}
+
+#[derive(Serialize)]
+struct WorkloadsResponse {
+    host: WorkloadSummary,
+    containers: Vec<WorkloadSummary>,
+}
+
+#[derive(Serialize)]
+struct QueuesResponse {
+    open_event_queue_depth: usize,
+    open_event_queue_capacity: usize,
+    resolve_failures_total: u64,
+    dropped_open_events_total: u64,
+    lost_perf_events_total: u64,
+    dropped_ring_events_total: u64,
+}
+
+#[derive(Serialize)]
+struct WorkerSummary {
+    name: String,
+    state: String,
+    consecutive_errors: u32,
+    runs: u64,
+    last_error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RunnerSummary {
+    name: String,
+    state: String,
+}
+
+fn unix_secs(t: SystemTime) -> Option<u64> {
+    t.duration_since(SystemTime::UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+// Shared state for the admin endpoint, holding just enough of `run()`'s own
+// handles to answer introspection queries without taking ownership of
+// anything `run()` still needs to drive (the `open_tx` clone is cheap; the
+// queue's actual receiver stays with `PkgsInUseWorker`).
+#[derive(Clone)]
+pub struct AdminState {
+    containers: Arc<Containers>,
+    workloads: Workloads,
+    open_tx: OpenEventSender,
+    open_mon: FileOpenMonitorArc,
+    workers: WorkerRegistry,
+}
+
+impl AdminState {
+    pub fn new(
+        containers: Arc<Containers>,
+        workloads: Workloads,
+        open_tx: OpenEventSender,
+        open_mon: FileOpenMonitorArc,
+        workers: WorkerRegistry,
+    ) -> Self {
+        Self {
+            containers,
+            workloads,
+            open_tx,
+            open_mon,
+            workers,
+        }
+    }
+
+    fn containers_json(&self) -> String {
+        let summaries: Vec<ContainerSummary> = self
+            .containers
+            .all()
+            .into_iter()
+            .map(|(id, info)| ContainerSummary {
+                id,
+                name: info.name,
+                image: info.image,
+                start_time: info.start_time.and_then(unix_secs),
+                end_time: info.end_time.and_then(unix_secs),
+            })
+            .collect();
+
+        serde_json::to_string(&summaries).unwrap_or_default()
+    }
+
+    fn workloads_json(&self) -> String {
+        let host = self.workloads.host.lock().unwrap();
+        let host_summary = WorkloadSummary {
+            id: host.id.clone(),
+            reported: host.reported_count(),
+            pending_in_use: host.pending_in_use(),
+        };
+        drop(host);
+
+        let containers = self
+            .workloads
+            .containers
+            .lock()
+            .unwrap()
+            .counts()
+            .into_iter()
+            .map(|c| WorkloadSummary {
+                id: c.id,
+                reported: c.reported,
+                pending_in_use: c.pending_in_use,
+            })
+            .collect();
+
+        let response = WorkloadsResponse {
+            host: host_summary,
+            containers,
+        };
+
+        serde_json::to_string(&response).unwrap_or_default()
+    }
+
+    fn queues_json(&self) -> String {
+        let drops = self.open_mon.drop_counts();
+
+        let response = QueuesResponse {
+            open_event_queue_depth: self.open_tx.depth(),
+            open_event_queue_capacity: self.open_tx.capacity(),
+            resolve_failures_total: crate::metrics::RESOLVE_FAILURES.get(),
+            dropped_open_events_total: crate::metrics::DROPPED_OPEN_EVENTS.get(),
+            lost_perf_events_total: drops.lost_perf_events,
+            dropped_ring_events_total: drops.dropped_ring_events,
+        };
+
+        serde_json::to_string(&response).unwrap_or_default()
+    }
+
+    fn workers_json(&self) -> String {
+        let summaries: Vec<WorkerSummary> = self
+            .workers
+            .snapshot()
+            .into_iter()
+            .map(|s| WorkerSummary {
+                name: s.name,
+                state: format!("{:?}", s.state),
+                consecutive_errors: s.consecutive_errors,
+                runs: s.runs,
+                last_error: s.last_error,
+            })
+            .collect();
+
+        serde_json::to_string(&summaries).unwrap_or_default()
+    }
+
+    fn runners_json(&self) -> String {
+        let summaries: Vec<RunnerSummary> = self
+            .containers
+            .workers()
+            .into_iter()
+            .map(|s| RunnerSummary {
+                name: s.name,
+                state: format!("{:?}", s.state),
+            })
+            .collect();
+
+        serde_json::to_string(&summaries).unwrap_or_default()
+    }
+}
+
+fn json_response(body: String) -> Response<Body> {
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn not_found() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from("not found"))
+        .unwrap()
+}
+
+async fn serve_req(state: AdminState, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let resp = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/containers") => json_response(state.containers_json()),
+        (&Method::GET, "/workloads") => json_response(state.workloads_json()),
+        (&Method::GET, "/queues") => json_response(state.queues_json()),
+        (&Method::GET, "/workers") => json_response(state.workers_json()),
+        (&Method::GET, "/runners") => json_response(state.runners_json()),
+        _ => not_found(),
+    };
+
+    Ok(resp)
+}
+
+// Serves a small local JSON introspection API on `addr` for as long as the
+// process runs: the live container list, per-workload reported/pending
+// counts, open-event queue depth, resolve-failure/dropped-event counters,
+// and worker/runner health. Only started when `Config::admin_addr` is set,
+// since most deployments don't want an extra open port by default.
+pub async fn serve(addr: SocketAddr, state: AdminState) {
+    let make_svc = make_service_fn(move |_conn| {
+        let state = state.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| serve_req(state.clone(), req))) }
+    });
+
+    info!("Serving admin API on http://{addr}");
+
+    if let Err(err) = Server::bind(&addr).serve(make_svc).await {
+        error!("Admin server failed: {err}");
+    }
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+// Decorrelated-jitter backoff (see the "Exponential Backoff and Jitter" AWS
+// Architecture Blog post): each delay is drawn uniformly from
+// [base, prev * 3], capped at `cap`, so retries spread out across time
+// instead of clustering into synchronized waves the way naive exponential
+// backoff does.
+pub struct DecorrelatedJitter {
+    base_ms: u64,
+    cap_ms: u64,
+    prev_ms: u64,
+}
+
+impl DecorrelatedJitter {
+    pub fn new(base: Duration, cap: Duration) -> Self {
+        let base_ms = base.as_millis() as u64;
+
+        Self {
+            base_ms,
+            cap_ms: cap.as_millis() as u64,
+            prev_ms: base_ms,
+        }
+    }
+
+    // Returns the next delay to sleep for and remembers it as `prev` for
+    // the following call.
+    pub fn next_delay(&mut self) -> Duration {
+        let upper_ms = self
+            .prev_ms
+            .saturating_mul(3)
+            .min(self.cap_ms)
+            .max(self.base_ms);
+
+        let delay_ms = if upper_ms > self.base_ms {
+            rand::thread_rng().gen_range(self.base_ms..=upper_ms)
+        } else {
+            self.base_ms
+        };
+
+        self.prev_ms = delay_ms;
+
+        Duration::from_millis(delay_ms)
+    }
+
+    // Resets the backoff to its base delay, e.g. after a successful call.
+    pub fn reset(&mut self) {
+        self.prev_ms = self.base_ms;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+
+    use super::*;
+
+    #[test]
+    fn test_decorrelated_jitter() {
+        let base = Duration::from_millis(1000);
+        let cap = Duration::from_secs(60);
+        let mut backoff = DecorrelatedJitter::new(base, cap);
+
+        for _ in 0..100 {
+            let delay = backoff.next_delay();
+            assert!(delay >= base);
+            assert!(delay <= cap);
+        }
+
+        backoff.reset();
+        assert!(backoff.next_delay() >= base);
+    }
+}
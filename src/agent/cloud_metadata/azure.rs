@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
 use anyhow::{bail, Result};
 use hyper::{Body, Client, Method, Request, StatusCode};
@@ -9,6 +10,10 @@ use crate::label::*;
 
 const METADATA_SERVICE_HOST: &str = "169.254.169.254";
 
+// Keeps a non-Azure host (most of them) from stalling cloud-provider
+// detection on an unreachable metadata endpoint.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(1);
+
 #[derive(Deserialize)]
 struct InstanceIdentityDocument {
     #[serde(rename = "name")]
@@ -36,7 +41,10 @@ impl InstanceIdentityDocument {
             .uri(url)
             .body(Body::empty())?;
 
-        let resp = client.request(req).await?;
+        let resp = match tokio::time::timeout(METADATA_TIMEOUT, client.request(req)).await {
+            Ok(resp) => resp?,
+            Err(_) => bail!("timed out contacting {url}"),
+        };
         match resp.status() {
             StatusCode::OK => {
                 let bytes = hyper::body::to_bytes(resp.into_body()).await?;
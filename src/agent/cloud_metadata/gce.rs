@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use hyper::{Body, Client, Method, Request, StatusCode};
@@ -11,6 +12,10 @@ use crate::label::*;
 
 const METADATA_SERVICE_HOST: &str = "metadata.google.internal";
 
+// Keeps a non-GCE host (most of them) from stalling cloud-provider detection
+// on an unreachable metadata endpoint.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(1);
+
 lazy_static! {
     // Docker containers will contain the id somewhere in the cgroup name
     static ref ZONE_RE: Regex = Regex::new(r".*/zones/(.*)").unwrap();
@@ -45,7 +50,9 @@ impl MetadataDocument {
             .uri(url)
             .body(Body::empty())?;
 
-        let resp = client.request(req).await?;
+        let resp = tokio::time::timeout(METADATA_TIMEOUT, client.request(req))
+            .await
+            .map_err(|_| anyhow!("timed out contacting {url}"))??;
         match resp.status() {
             StatusCode::OK => {
                 let bytes = hyper::body::to_bytes(resp.into_body()).await?;
@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures::stream::StreamExt;
+use log::*;
+use serde::Deserialize;
+
+use crate::label::*;
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+// How often the background watch re-lists this node's pods. A plain
+// polling re-list rather than relying solely on the watch stream below:
+// much simpler to get right, and it bounds how long a missed or dropped
+// watch event (reconnect races, etc.) can leave `pods_by_uid` stale.
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+// Backoff between re-establishing the pod watch stream after it ends or
+// errors out (API server restart, connection reset, etc.).
+const WATCH_RECONNECT_BACKOFF: Duration = Duration::from_secs(2);
+
+// A Pod normally only has to climb two hops to reach its workload
+// (ReplicaSet -> Deployment, or Job -> CronJob), so this is a generous
+// ceiling against a pathological or cyclic `ownerReferences` chain.
+const MAX_OWNER_DEPTH: usize = 5;
+
+// Intermediate owner kinds worth resolving one hop further; anything else
+// (or no controller owner at all) is treated as the workload itself.
+fn owner_api_path(namespace: &str, kind: &str, name: &str) -> Option<String> {
+    match kind {
+        "ReplicaSet" => Some(format!("/apis/apps/v1/namespaces/{namespace}/replicasets/{name}")),
+        "Job" => Some(format!("/apis/batch/v1/namespaces/{namespace}/jobs/{name}")),
+        _ => None,
+    }
+}
+
+// Annotations that are either huge or pure implementation noise (the
+// full applied-config blob, rollout bookkeeping) and not worth shipping
+// as a label on every container.
+const SUPPRESSED_ANNOTATIONS: &[&str] = &[
+    "kubectl.kubernetes.io/last-applied-configuration",
+    "deployment.kubernetes.io/revision",
+];
+
+#[derive(Clone, Default)]
+struct PodInfo {
+    uid: String,
+    namespace: String,
+    pod_name: String,
+    workload: Option<(String, String)>,
+    labels: HashMap<String, String>,
+    annotations: HashMap<String, String>,
+    container_ids: Vec<String>,
+}
+
+struct State {
+    pods_by_uid: HashMap<String, PodInfo>,
+    container_to_uid: HashMap<String, String>,
+}
+
+pub struct KubernetesMetadata {
+    node_name: String,
+    cluster_id: Option<String>,
+    state: Arc<RwLock<State>>,
+}
+
+impl KubernetesMetadata {
+    // Fails on any host that isn't running as an in-cluster pod -- no
+    // service account token, no API server address in the environment, or
+    // no node name projected via the downward API -- so `CloudMetadata`
+    // falls back to the next provider (or `NullProvider`) the same way it
+    // does for a failed EC2/GCE/Azure metadata probe.
+    pub async fn load() -> Result<Self> {
+        let token = std::fs::read_to_string(format!("{SERVICE_ACCOUNT_DIR}/token"))
+            .context("reading in-cluster service account token")?;
+        let ca_cert = std::fs::read(format!("{SERVICE_ACCOUNT_DIR}/ca.crt"))
+            .context("reading in-cluster CA cert")?;
+
+        let host = std::env::var("KUBERNETES_SERVICE_HOST")
+            .context("KUBERNETES_SERVICE_HOST not set, not running in-cluster")?;
+        let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+        let api_server = format!("https://{host}:{port}");
+
+        // Projected onto the pod spec via `fieldRef: spec.nodeName`; there's
+        // no other reliable way for a pod to learn which node it landed on.
+        let node_name = std::env::var("NODE_NAME")
+            .context("NODE_NAME not set (expected via the downward API)")?;
+
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(&ca_cert)?)
+            .build()?;
+
+        let state = Arc::new(RwLock::new(State {
+            pods_by_uid: HashMap::new(),
+            container_to_uid: HashMap::new(),
+        }));
+
+        resync(&client, &api_server, &token, &node_name, &state).await?;
+
+        tokio::task::spawn(poll_loop(
+            client.clone(),
+            api_server.clone(),
+            token.clone(),
+            node_name.clone(),
+            state.clone(),
+        ));
+
+        tokio::task::spawn(watch_loop(client, api_server, token, node_name, state.clone()));
+
+        let cluster_id = std::env::var("EDGEBIT_KUBE_CLUSTER_ID").ok();
+
+        Ok(Self {
+            node_name,
+            cluster_id,
+            state,
+        })
+    }
+}
+
+impl super::MetadataProvider for KubernetesMetadata {
+    fn host_labels(&self) -> HashMap<String, String> {
+        let mut labels = HashMap::new();
+        labels.insert(LABEL_KUBE_NODE_NAME.to_string(), self.node_name.clone());
+
+        if let Some(cluster_id) = &self.cluster_id {
+            labels.insert(LABEL_KUBE_CLUSTER_ID.to_string(), cluster_id.clone());
+        }
+
+        labels
+    }
+
+    fn container_labels(&self, id: &str) -> HashMap<String, String> {
+        let state = self.state.read().unwrap();
+
+        let pod = state
+            .container_to_uid
+            .get(strip_container_id(id))
+            .and_then(|uid| state.pods_by_uid.get(uid))
+            .cloned();
+
+        let Some(pod) = pod else {
+            return HashMap::new();
+        };
+
+        let mut labels = HashMap::new();
+        labels.insert(LABEL_KUBE_POD_NAME.to_string(), pod.pod_name);
+        labels.insert(LABEL_KUBE_NAMESPACE_NAME.to_string(), pod.namespace);
+
+        if let Some((kind, name)) = pod.workload {
+            labels.insert(LABEL_KUBE_WORKLOAD_KIND.to_string(), kind);
+            labels.insert(LABEL_KUBE_WORKLOAD_NAME.to_string(), name);
+        }
+
+        for (k, v) in pod.labels {
+            labels.insert(format!("{LABEL_KUBE_POD_LABEL_PREFIX}{k}"), v);
+        }
+
+        for (k, v) in pod.annotations {
+            labels.insert(format!("{LABEL_KUBE_POD_ANNOTATION_PREFIX}{k}"), v);
+        }
+
+        labels
+    }
+}
+
+async fn poll_loop(
+    client: reqwest::Client,
+    api_server: String,
+    token: String,
+    node_name: String,
+    state: Arc<RwLock<State>>,
+) {
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        if let Err(err) = resync(&client, &api_server, &token, &node_name, &state).await {
+            warn!("Failed to refresh Kubernetes pod metadata: {err}");
+        }
+    }
+}
+
+// Re-lists this node's pods from scratch and replaces `pods_by_uid`
+// wholesale, resolving each pod's top-level workload (caching the
+// resolution per owner so sibling pods of the same ReplicaSet don't each
+// pay for their own walk up to the Deployment).
+async fn resync(
+    client: &reqwest::Client,
+    api_server: &str,
+    token: &str,
+    node_name: &str,
+    state: &Arc<RwLock<State>>,
+) -> Result<()> {
+    let pods = list_pods(client, api_server, node_name, token).await?;
+
+    let mut workload_cache = HashMap::new();
+    let mut pods_by_uid = HashMap::new();
+    let mut container_to_uid = HashMap::new();
+
+    for pod in pods {
+        let info = pod_info(client, api_server, token, pod, &mut workload_cache).await;
+
+        for id in &info.container_ids {
+            container_to_uid.insert(id.clone(), info.uid.clone());
+        }
+
+        pods_by_uid.insert(info.uid.clone(), info);
+    }
+
+    let mut state = state.write().unwrap();
+    state.pods_by_uid = pods_by_uid;
+    state.container_to_uid = container_to_uid;
+
+    Ok(())
+}
+
+async fn watch_loop(
+    client: reqwest::Client,
+    api_server: String,
+    token: String,
+    node_name: String,
+    state: Arc<RwLock<State>>,
+) {
+    loop {
+        match watch_once(&client, &api_server, &token, &node_name, &state).await {
+            Ok(()) => debug!("Kubernetes pod watch ended, reconnecting"),
+            Err(err) => warn!("Kubernetes pod watch error, reconnecting: {err}"),
+        }
+
+        tokio::time::sleep(WATCH_RECONNECT_BACKOFF).await;
+    }
+}
+
+// Watches this node's pods purely to evict deleted pods from the cache as
+// soon as the API server reports them gone, rather than leaving stale
+// entries around for up to `POLL_INTERVAL` until the next re-list.
+async fn watch_once(
+    client: &reqwest::Client,
+    api_server: &str,
+    token: &str,
+    node_name: &str,
+    state: &Arc<RwLock<State>>,
+) -> Result<()> {
+    let url = format!("{api_server}/api/v1/pods?watch=true&fieldSelector=spec.nodeName={node_name}");
+
+    let resp = client.get(&url)
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut stream = resp.bytes_stream();
+    let mut buf = Vec::new();
+
+    while let Some(chunk) = stream.next().await {
+        buf.extend_from_slice(&chunk?);
+
+        while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+            let line = buf.drain(..=pos).collect::<Vec<u8>>();
+
+            match serde_json::from_slice::<WatchEvent>(&line) {
+                Ok(event) if event.kind == "DELETED" => {
+                    let mut state = state.write().unwrap();
+                    if let Some(info) = state.pods_by_uid.remove(&event.object.metadata.uid) {
+                        for id in &info.container_ids {
+                            state.container_to_uid.remove(id);
+                        }
+                    }
+                }
+                Ok(_) => (),
+                Err(err) => debug!("Failed to decode Kubernetes pod watch event: {err}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn list_pods(
+    client: &reqwest::Client,
+    api_server: &str,
+    node_name: &str,
+    token: &str,
+) -> Result<Vec<Pod>> {
+    let url = format!("{api_server}/api/v1/pods?fieldSelector=spec.nodeName={node_name}");
+
+    let pods: PodList = client
+        .get(&url)
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(pods.items)
+}
+
+async fn pod_info(
+    client: &reqwest::Client,
+    api_server: &str,
+    token: &str,
+    pod: Pod,
+    workload_cache: &mut HashMap<(String, String, String), (String, String)>,
+) -> PodInfo {
+    let owner = pod.metadata.owner_references.iter()
+        .find(|o| o.controller.unwrap_or(false))
+        .or_else(|| pod.metadata.owner_references.first())
+        .cloned();
+
+    let workload = match owner {
+        Some(owner) => Some(resolve_workload(client, api_server, token, &pod.metadata.namespace, owner, workload_cache).await),
+        None => None,
+    };
+
+    let annotations = pod.metadata.annotations.into_iter()
+        .filter(|(k, _)| !SUPPRESSED_ANNOTATIONS.contains(&k.as_str()))
+        .collect();
+
+    let container_ids = pod.status.map(|s| s.container_statuses).unwrap_or_default()
+        .into_iter()
+        .filter_map(|cs| cs.container_id)
+        .map(|id| strip_container_id(&id).to_string())
+        .collect();
+
+    PodInfo {
+        uid: pod.metadata.uid,
+        namespace: pod.metadata.namespace,
+        pod_name: pod.metadata.name,
+        workload,
+        labels: pod.metadata.labels,
+        annotations,
+        container_ids,
+    }
+}
+
+// Walks `owner` up through any intermediate ReplicaSet/Job to the
+// top-level controller that actually owns the workload (a Deployment,
+// StatefulSet, DaemonSet, CronJob, or the immediate owner itself if it's
+// none of those, e.g. a bare ReplicaSet or a pod created directly by a
+// Job).
+async fn resolve_workload(
+    client: &reqwest::Client,
+    api_server: &str,
+    token: &str,
+    namespace: &str,
+    owner: OwnerRef,
+    cache: &mut HashMap<(String, String, String), (String, String)>,
+) -> (String, String) {
+    let mut kind = owner.kind;
+    let mut name = owner.name;
+
+    for _ in 0..MAX_OWNER_DEPTH {
+        let cache_key = (namespace.to_string(), kind.clone(), name.clone());
+
+        if let Some(top) = cache.get(&cache_key) {
+            return top.clone();
+        }
+
+        let Some(path) = owner_api_path(namespace, &kind, &name) else {
+            break;
+        };
+
+        match fetch_owner(client, api_server, token, &path).await {
+            Ok(Some(parent)) => {
+                kind = parent.kind;
+                name = parent.name;
+            }
+            Ok(None) => break,
+            Err(err) => {
+                debug!("Failed to resolve owner of {kind}/{name} in {namespace}: {err}");
+                break;
+            }
+        }
+    }
+
+    let top = (kind, name);
+
+    cache.insert((namespace.to_string(), top.0.clone(), top.1.clone()), top.clone());
+
+    top
+}
+
+async fn fetch_owner(
+    client: &reqwest::Client,
+    api_server: &str,
+    token: &str,
+    path: &str,
+) -> Result<Option<OwnerRef>> {
+    let resp: OwnedObject = client
+        .get(format!("{api_server}{path}"))
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(resp.metadata.owner_references.into_iter()
+        .find(|o| o.controller.unwrap_or(false)))
+}
+
+// containerStatuses[].containerID comes back as "<runtime>://<id>" (e.g.
+// "containerd://abc123..."), but callers of `container_labels` pass the
+// bare id used elsewhere in this codebase.
+fn strip_container_id(id: &str) -> &str {
+    id.split_once("://").map(|(_, id)| id).unwrap_or(id)
+}
+
+#[derive(Deserialize)]
+struct PodList {
+    items: Vec<Pod>,
+}
+
+#[derive(Deserialize)]
+struct Pod {
+    metadata: PodMeta,
+    status: Option<PodStatus>,
+}
+
+#[derive(Deserialize)]
+struct OwnedObject {
+    metadata: OwnedObjectMeta,
+}
+
+#[derive(Deserialize)]
+struct OwnedObjectMeta {
+    #[serde(default, rename = "ownerReferences")]
+    owner_references: Vec<OwnerRef>,
+}
+
+#[derive(Deserialize)]
+struct PodMeta {
+    uid: String,
+    name: String,
+    namespace: String,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+    #[serde(default, rename = "ownerReferences")]
+    owner_references: Vec<OwnerRef>,
+}
+
+#[derive(Clone, Deserialize)]
+struct OwnerRef {
+    kind: String,
+    name: String,
+    controller: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct PodStatus {
+    #[serde(default, rename = "containerStatuses")]
+    container_statuses: Vec<ContainerStatus>,
+}
+
+#[derive(Deserialize)]
+struct ContainerStatus {
+    #[serde(rename = "containerID")]
+    container_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WatchEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    object: WatchPod,
+}
+
+#[derive(Deserialize)]
+struct WatchPod {
+    metadata: WatchPodMeta,
+}
+
+#[derive(Deserialize)]
+struct WatchPodMeta {
+    uid: String,
+}
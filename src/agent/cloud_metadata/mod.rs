@@ -1,12 +1,15 @@
 mod azure;
 mod ec2;
 mod gce;
+mod kubernetes;
 
 use std::collections::HashMap;
 use std::sync::Arc;
 
 use log::*;
 
+use crate::config::CloudProvider;
+
 pub(crate) trait MetadataProvider {
     fn host_labels(&self) -> HashMap<String, String>;
 
@@ -31,7 +34,50 @@ pub struct CloudMetadata {
 }
 
 impl CloudMetadata {
-    pub async fn load() -> Self {
+    pub async fn load(cloud_provider: CloudProvider) -> Self {
+        match cloud_provider {
+            CloudProvider::Disabled => Self {
+                provider: Arc::new(NullProvider),
+            },
+            CloudProvider::Explicit(name) => match name.as_str() {
+                "ec2" | "aws" => Self::from_result(ec2::Ec2Metadata::load().await, &name),
+                "gce" | "gcp" => Self::from_result(gce::GceMetadata::load().await, &name),
+                "azure" => Self::from_result(azure::AzureMetadata::load().await, &name),
+                "kubernetes" | "k8s" => {
+                    Self::from_result(kubernetes::KubernetesMetadata::load().await, &name)
+                }
+                other => {
+                    warn!("Unknown cloud_provider override '{other}', disabling cloud metadata");
+                    Self {
+                        provider: Arc::new(NullProvider),
+                    }
+                }
+            },
+            CloudProvider::Auto => Self::probe().await,
+        }
+    }
+
+    // Each provider below is best-effort: a source that's unreachable (wrong
+    // cloud, no in-cluster service account, metadata endpoint blocked by a
+    // firewall) just fails its own `load()` and falls through to the next
+    // one, rather than aborting startup. Whichever provider wins populates
+    // the `LABEL_*` constants from `label.rs` via `host_labels`/
+    // `container_labels`, which `main.rs` and the docker/podman workload
+    // trackers attach to `report_in_use`/`report_rpms` payloads.
+    async fn probe() -> Self {
+        // Tried first since, unlike the cloud probes below, it fails
+        // immediately (missing service account files/env vars) on any host
+        // that isn't an in-cluster pod, rather than needing a network round
+        // trip to rule itself out.
+        match kubernetes::KubernetesMetadata::load().await {
+            Ok(p) => {
+                return Self {
+                    provider: Arc::new(p),
+                }
+            }
+            Err(err) => debug!("kubernetes load metadata: {err}"),
+        }
+
         match ec2::Ec2Metadata::load().await {
             Ok(p) => {
                 return Self {
@@ -64,6 +110,23 @@ impl CloudMetadata {
         }
     }
 
+    fn from_result<P: MetadataProvider + Send + Sync + 'static>(
+        result: anyhow::Result<P>,
+        name: &str,
+    ) -> Self {
+        match result {
+            Ok(p) => Self {
+                provider: Arc::new(p),
+            },
+            Err(err) => {
+                error!("cloud_provider forced to '{name}' but its metadata load failed: {err}");
+                Self {
+                    provider: Arc::new(NullProvider),
+                }
+            }
+        }
+    }
+
     pub fn host_labels(&self) -> HashMap<String, String> {
         self.provider.host_labels()
     }
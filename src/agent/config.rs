@@ -1,15 +1,29 @@
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
+use figment::providers::{Env, Format, Serialized, Yaml};
+use figment::Figment;
 use nix::NixPath;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 pub const CONFIG_PATH: &str = "/etc/edgebit/config.yaml";
 
 const DEFAULT_LOG_LEVEL: &str = "info";
-const DEFAULT_DOCKER_HOST: &str = "unix:///run/docker.sock";
 const DEFAULT_CONTAINERD_ROOTS: &str = "/run/containerd/io.containerd.runtime.v2.task/k8s.io/";
+const DEFAULT_SPOOL_DIR: &str = "/var/lib/edgebit/spool";
+const DEFAULT_MONITOR_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_CRIO_RESYNC_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_OPEN_EVENT_QUEUE_CAPACITY: usize = 1000;
+const DEFAULT_OPEN_EVENTS_BUF_PAGES: usize = 256;
+const DEFAULT_ZOMBIE_EVENTS_BUF_PAGES: usize = 4;
+
+// After each SBOM scrub taking wall-time `t`, the scrubber sleeps
+// `scrub_tranquility * t` before starting the next one, so a tranquility of
+// 2 means it's idle roughly two-thirds of the time.
+const DEFAULT_SCRUB_TRANQUILITY: f64 = 2.0;
 
 static DEFAULT_HOST_INCLUDES: &[&str] = &[
     "/bin", "/lib", "/lib32", "/lib64", "/libx32", "/opt", "/sbin", "/usr",
@@ -19,7 +33,7 @@ static DEFAULT_HOST_EXCLUDES: &[&str] = &[];
 
 static DEFAULT_CONTAINER_EXCLUDES: &[&str] = &[];
 
-#[derive(Default, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct Inner {
     pub edgebit_id: Option<String>,
@@ -40,96 +54,146 @@ struct Inner {
 
     docker_host: Option<String>,
 
+    docker_tls_verify: Option<bool>,
+
+    docker_cert_path: Option<PathBuf>,
+
+    tls_ca_cert: Option<PathBuf>,
+
+    tls_client_cert: Option<PathBuf>,
+
+    tls_client_key: Option<PathBuf>,
+
     containerd_host: Option<String>,
 
+    podman_host: Option<String>,
+
+    crio_host: Option<String>,
+
+    crio_resync_interval_secs: Option<u64>,
+
     containerd_roots: Option<PathBuf>,
 
     pkg_tracking: Option<bool>,
 
+    scrub_tranquility: Option<f64>,
+
     hostname: Option<String>,
 
     host_root: Option<PathBuf>,
 
+    spool_dir: Option<PathBuf>,
+
+    metrics_addr: Option<String>,
+
+    admin_addr: Option<String>,
+
+    monitor_backend: Option<String>,
+
+    monitor_poll_interval_secs: Option<u64>,
+
+    open_event_queue_capacity: Option<usize>,
+
+    open_events_buf_pages: Option<usize>,
+
+    zombie_events_buf_pages: Option<usize>,
+
+    cloud_provider: Option<String>,
+
+    sbom_compression: Option<String>,
+
     labels: Option<HashMap<String, String>>,
 }
 
-// TODO: probably worth using Figment or similar to unify yaml and env vars
 pub struct Config {
     inner: Inner,
 }
 
 impl Config {
+    // Layers, lowest to highest precedence: compiled-in defaults -> the yaml
+    // config file -> `EDGEBIT_*` environment variables, plus a handful of
+    // vars that intentionally fall outside that namespace (`DOCKER_HOST` and
+    // friends, mirroring the Docker CLI's own env vars; `EDGEBIT_LABELS`,
+    // whose `k=v;k=v` syntax doesn't map onto a single struct field). The
+    // config file path itself can be overridden via `EDGEBIT_CONFIG_PATH`,
+    // the first of what's meant to grow into a small `EDGEBIT_CONFIG_*`
+    // namespace of loader-level (as opposed to agent-behavior) settings.
     pub fn load<P: AsRef<Path>>(
         path: P,
         hostname: Option<String>,
         host_root: Option<PathBuf>,
     ) -> Result<Self> {
-        let mut inner: Inner = match std::fs::File::open(path.as_ref()) {
-            Ok(file) => serde_yaml::from_reader(file)?,
-            Err(err) => {
-                if err.kind() != std::io::ErrorKind::NotFound {
-                    // Don't bail since the config can also be provided via env vars.
-                    // Do print a warning.
-                    eprintln!(
-                        "Could not open config file at {}, {err}",
-                        path.as_ref().display()
-                    );
-                }
-                Inner::default()
-            }
-        };
+        let path = std::env::var("EDGEBIT_CONFIG_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| path.as_ref().to_path_buf());
+
+        let figment = Figment::from(Serialized::defaults(Inner::default()))
+            .merge(Yaml::file(&path))
+            .merge(Env::prefixed("EDGEBIT_"))
+            .merge(Env::raw().only(&["DOCKER_HOST", "DOCKER_TLS_VERIFY", "DOCKER_CERT_PATH"]).map(
+                |key| match key {
+                    "DOCKER_TLS_VERIFY" => "docker_tls_verify".into(),
+                    "DOCKER_CERT_PATH" => "docker_cert_path".into(),
+                    _ => "docker_host".into(),
+                },
+            ));
+
+        let mut inner: Inner = figment.extract()?;
+
+        if let Ok(labels_str) = std::env::var("EDGEBIT_LABELS") {
+            let extra = labels_str
+                .split(';')
+                .filter_map(|kv| kv.split_once('=').map(|(k, v)| (k.to_string(), v.to_string())));
+            inner.labels.get_or_insert_with(HashMap::new).extend(extra);
+        }
 
+        // Unconditional: an explicit CLI override always wins, and its
+        // absence means "let the hostname()/host_root() accessors fall back
+        // to $EDGEBIT_HOSTNAME/$EDGEBIT_HOSTROOT or their own defaults",
+        // which is why those two accessors re-read the environment
+        // themselves rather than relying on Figment's merge above.
         inner.hostname = hostname;
         inner.host_root = host_root;
 
         let me = Self { inner };
 
-        // check that the config items are there
-        me.try_edgebit_id()?;
-        me.try_edgebit_url()?;
-        me.try_syft_path()?;
-        me.try_syft_config()?;
+        let mut missing = Vec::new();
+        if me.inner.edgebit_id.is_none() {
+            missing.push("$EDGEBIT_ID / .edgebit_id");
+        }
+        if me.inner.edgebit_url.is_none() {
+            missing.push("$EDGEBIT_URL / .edgebit_url");
+        }
+        if me.inner.syft_path.is_none() {
+            missing.push("$EDGEBIT_SYFT_PATH / .syft_path");
+        }
+        if me.inner.syft_config.is_none() {
+            missing.push("$EDGEBIT_SYFT_CONFIG / .syft_config");
+        }
+
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "missing required configuration: {}",
+                missing.join(", ")
+            ));
+        }
 
         Ok(me)
     }
 
     pub fn edgebit_id(&self) -> String {
-        self.try_edgebit_id().unwrap()
-    }
-
-    fn try_edgebit_id(&self) -> Result<String> {
-        if let Ok(id) = std::env::var("EDGEBIT_ID") {
-            Ok(id)
-        } else {
-            self.inner.edgebit_id.clone().ok_or(anyhow!(
-                "$EDGEBIT_ID not set and .edgebit_id missing in config file"
-            ))
-        }
+        self.inner.edgebit_id.clone().unwrap()
     }
 
     pub fn edgebit_url(&self) -> String {
-        self.try_edgebit_url().unwrap()
-    }
-
-    fn try_edgebit_url(&self) -> Result<String> {
-        if let Ok(id) = std::env::var("EDGEBIT_URL") {
-            Ok(id)
-        } else {
-            self.inner.edgebit_url.clone().ok_or(anyhow!(
-                "$EDGEBIT_URL not set and .edgebit_url missing in config file"
-            ))
-        }
+        self.inner.edgebit_url.clone().unwrap()
     }
 
     pub fn log_level(&self) -> String {
-        if let Ok(level) = std::env::var("EDGEBIT_LOG_LEVEL") {
-            level
-        } else {
-            self.inner
-                .log_level
-                .clone()
-                .unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string())
-        }
+        self.inner
+            .log_level
+            .clone()
+            .unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string())
     }
 
     pub fn host_includes(&self) -> Vec<PathBuf> {
@@ -144,75 +208,90 @@ impl Config {
         paths(&self.inner.container_excludes, DEFAULT_CONTAINER_EXCLUDES)
     }
 
-    fn try_syft_config(&self) -> Result<PathBuf> {
-        if let Ok(syft_conf) = std::env::var("EDGEBIT_SYFT_CONFIG") {
-            Ok(PathBuf::from(syft_conf))
-        } else {
-            self.inner.syft_config.clone().ok_or(anyhow!(
-                "$EDGEBIT_SYFT_CONFIG not set and .syft_config missing in config file"
-            ))
-        }
-    }
-
     pub fn syft_config(&self) -> PathBuf {
-        self.try_syft_config().unwrap()
+        self.inner.syft_config.clone().unwrap()
     }
 
-    fn try_syft_path(&self) -> Result<PathBuf> {
-        if let Ok(path) = std::env::var("EDGEBIT_SYFT_PATH") {
-            Ok(PathBuf::from(path))
-        } else {
-            self.inner.syft_path.clone().ok_or(anyhow!(
-                "$EDGEBIT_SYFT_PATH not set and .syft_path missing in config file"
-            ))
-        }
+    pub fn syft_path(&self) -> PathBuf {
+        self.inner.syft_path.clone().unwrap()
     }
 
-    pub fn syft_path(&self) -> PathBuf {
-        self.try_syft_path().unwrap()
+    // An explicit host pins Containers::autodetect to exactly that
+    // endpoint; an explicitly empty host disables tracking for that runtime
+    // outright; unset (the common case) leaves it up to auto-detection
+    // probing the runtime's well-known socket path.
+    pub fn docker_host(&self) -> RuntimeHost {
+        runtime_host(&self.inner.docker_host)
     }
 
-    pub fn docker_host(&self) -> Option<String> {
-        if let Ok(host) = std::env::var("DOCKER_HOST") {
-            if host.is_empty() {
-                None
-            } else {
-                Some(host)
-            }
-        } else {
-            self.inner
-                .docker_host
-                .clone()
-                .or_else(|| Some(DEFAULT_DOCKER_HOST.to_string()))
+    // `None` means talk to `docker_host` in the clear; `Some` carries the
+    // client cert/key/CA to present for an `https://` endpoint. Mirrors the
+    // Docker CLI's own env vars (`DOCKER_CERT_PATH`/`DOCKER_TLS_VERIFY`, both
+    // merged into `Inner` unprefixed in `load`) so operators don't need
+    // agent-specific config to point at a daemon they've already set up for
+    // `docker -H https://... --tlsverify`.
+    pub fn docker_tls(&self) -> Option<DockerTls> {
+        if !self.inner.docker_tls_verify.unwrap_or(false) {
+            return None;
         }
+
+        let cert_path = self.inner.docker_cert_path.clone()?;
+
+        Some(DockerTls {
+            ca: cert_path.join("ca.pem"),
+            cert: cert_path.join("cert.pem"),
+            key: cert_path.join("key.pem"),
+        })
     }
 
-    pub fn containerd_host(&self) -> Option<String> {
-        if let Ok(host) = std::env::var("EDGEBIT_CONTAINERD_HOST") {
-            if host.is_empty() {
-                None
-            } else {
-                Some(host)
-            }
-        } else {
-            self.inner.containerd_host.clone()
+    // Custom CA and/or client cert/key for the agent's gRPC connection to
+    // the control plane. `None` fields fall back to tonic's own default
+    // (system root CAs, no client cert), so this only needs to be set for
+    // self-hosted EdgeBit servers behind a private CA or that require
+    // client-cert admission.
+    pub fn client_tls(&self) -> Option<ClientTls> {
+        let ca_cert = self.inner.tls_ca_cert.clone();
+        let client_cert = self.inner.tls_client_cert.clone();
+        let client_key = self.inner.tls_client_key.clone();
+
+        if ca_cert.is_none() && client_cert.is_none() && client_key.is_none() {
+            return None;
         }
+
+        Some(ClientTls {
+            ca_cert,
+            client_cert,
+            client_key,
+        })
     }
 
-    pub fn containerd_roots(&self) -> PathBuf {
-        if let Ok(roots) = std::env::var("EDGEBIT_CONTAINERD_ROOTS") {
-            if !roots.is_empty() {
-                return roots.into();
-            }
-        }
+    pub fn containerd_host(&self) -> RuntimeHost {
+        runtime_host(&self.inner.containerd_host)
+    }
 
-        if let Some(ref roots) = self.inner.containerd_roots {
-            if !roots.is_empty() {
-                return roots.into();
-            }
-        }
+    pub fn podman_host(&self) -> RuntimeHost {
+        runtime_host(&self.inner.podman_host)
+    }
 
-        DEFAULT_CONTAINERD_ROOTS.into()
+    pub fn crio_host(&self) -> RuntimeHost {
+        runtime_host(&self.inner.crio_host)
+    }
+
+    // CRI has no event subscription, so `CriTracker` falls back to
+    // resyncing on this interval instead, diffing the freshly-listed
+    // container set against the last one to synthesize start/stop events.
+    pub fn crio_resync_interval(&self) -> Duration {
+        self.inner
+            .crio_resync_interval_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_CRIO_RESYNC_INTERVAL)
+    }
+
+    pub fn containerd_roots(&self) -> PathBuf {
+        match &self.inner.containerd_roots {
+            Some(roots) if !roots.is_empty() => roots.clone(),
+            _ => DEFAULT_CONTAINERD_ROOTS.into(),
+        }
     }
 
     pub fn hostname(&self) -> String {
@@ -231,43 +310,207 @@ impl Config {
             .unwrap_or(PathBuf::from("/"))
     }
 
+    pub fn spool_dir(&self) -> PathBuf {
+        match &self.inner.spool_dir {
+            Some(dir) if !dir.is_empty() => dir.clone(),
+            _ => DEFAULT_SPOOL_DIR.into(),
+        }
+    }
+
     pub fn pkg_tracking(&self) -> bool {
+        self.inner.pkg_tracking.unwrap_or(true)
+    }
+
+    pub fn scrub_tranquility(&self) -> f64 {
         self.inner
-            .pkg_tracking
-            .or_else(|| {
-                std::env::var("EDGEBIT_PKG_TRACKING")
-                    .ok()
-                    .map(|v| is_yes(&v))
-            })
-            .unwrap_or(true)
+            .scrub_tranquility
+            .unwrap_or(DEFAULT_SCRUB_TRANQUILITY)
     }
 
-    pub fn labels(&self) -> HashMap<String, String> {
-        let mut labels = self.inner.labels.clone().unwrap_or_default();
+    // Bind address for the Prometheus /metrics endpoint. Disabled (None)
+    // unless explicitly configured, since most deployments don't want an
+    // extra open port by default.
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        let raw = self.inner.metrics_addr.clone()?;
 
-        if let Ok(labels_str) = std::env::var("EDGEBIT_LABELS") {
-            labels.extend(labels_str.split(';').filter_map(|kv| {
-                kv.split_once('=')
-                    .map(|(k, v)| (k.to_string(), v.to_string()))
-            }));
+        match raw.parse() {
+            Ok(addr) => Some(addr),
+            Err(err) => {
+                eprintln!("Invalid metrics_addr '{raw}': {err}");
+                None
+            }
+        }
+    }
+
+    // Bind address for the local admin/introspection endpoint (container
+    // list, per-workload counts, queue depth, worker health). Disabled
+    // (None) unless explicitly configured, same as metrics_addr.
+    pub fn admin_addr(&self) -> Option<SocketAddr> {
+        let raw = self.inner.admin_addr.clone()?;
+
+        match raw.parse() {
+            Ok(addr) => Some(addr),
+            Err(err) => {
+                eprintln!("Invalid admin_addr '{raw}': {err}");
+                None
+            }
+        }
+    }
+
+    // Which open-event capture mechanism to start with. Auto (the default)
+    // tries the combined eBPF/fanotify monitor first, falling back to
+    // inotify and then Poll if those can't initialize; Poll pins straight
+    // to the userspace walker for hosts where even inotify's watch limits
+    // are a problem, e.g. a deeply nested rootfs in an unprivileged
+    // container.
+    pub fn monitor_backend(&self) -> OpenMonitorBackend {
+        let raw = self.inner.monitor_backend.clone().unwrap_or_default();
+
+        match raw.to_lowercase().as_str() {
+            "poll" => OpenMonitorBackend::Poll {
+                interval: self.monitor_poll_interval(),
+            },
+            _ => OpenMonitorBackend::Auto,
+        }
+    }
+
+    fn monitor_poll_interval(&self) -> Duration {
+        self.inner
+            .monitor_poll_interval_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_MONITOR_POLL_INTERVAL)
+    }
+
+    // Capacity of the bounded, coalescing open-event queue (see
+    // `open_event_queue`) between the monitor backends and `PkgsInUseWorker`.
+    pub fn open_event_queue_capacity(&self) -> usize {
+        self.inner
+            .open_event_queue_capacity
+            .unwrap_or(DEFAULT_OPEN_EVENT_QUEUE_CAPACITY)
+    }
+
+    // Perf-buffer page count for the open-events map. Only used when the
+    // kernel doesn't support the BPF ring buffer (see
+    // `open_monitor::supports_ring_buffer`); bump it on busy hosts to trade
+    // memory for fewer lost events.
+    pub fn open_events_buf_pages(&self) -> usize {
+        self.inner
+            .open_events_buf_pages
+            .unwrap_or(DEFAULT_OPEN_EVENTS_BUF_PAGES)
+    }
+
+    // Same as `open_events_buf_pages`, but for the zombie-events map.
+    pub fn zombie_events_buf_pages(&self) -> usize {
+        self.inner
+            .zombie_events_buf_pages
+            .unwrap_or(DEFAULT_ZOMBIE_EVENTS_BUF_PAGES)
+    }
+
+    // An explicit provider pins `CloudMetadata::load` to exactly that cloud,
+    // skipping the others' probes entirely; "none"/empty disables cloud
+    // metadata lookup outright (e.g. bare-metal hosts where probing the
+    // AWS/GCP/Azure endpoints is pure wasted latency); unset (the common
+    // case) leaves it up to auto-detection trying each in turn.
+    pub fn cloud_provider(&self) -> CloudProvider {
+        match &self.inner.cloud_provider {
+            None => CloudProvider::Auto,
+            Some(v) if v.is_empty() || v.eq_ignore_ascii_case("none") => CloudProvider::Disabled,
+            Some(v) => CloudProvider::Explicit(v.to_lowercase()),
+        }
+    }
+
+    // Codec used to compress the SBOM data stream before it's chunked and
+    // sent to the control plane. zstd is the default since Syft/SPDX
+    // documents are highly compressible JSON and this cuts upload bandwidth
+    // substantially for no server-side representation change.
+    pub fn sbom_compression(&self) -> SbomCompression {
+        let raw = self.inner.sbom_compression.clone().unwrap_or_default();
+
+        match raw.to_lowercase().as_str() {
+            "none" => SbomCompression::None,
+            "gzip" => SbomCompression::Gzip,
+            _ => SbomCompression::Zstd,
         }
+    }
 
+    pub fn labels(&self) -> HashMap<String, String> {
         // remap into the 'user:' namespace
-        labels
+        self.inner
+            .labels
+            .clone()
+            .unwrap_or_default()
             .into_iter()
             .map(|(k, v)| ("user:".to_string() + &k, v))
             .collect()
     }
 }
 
+// How a container runtime's endpoint was configured, from the most to the
+// least specific: pinned to an explicit host, explicitly turned off, or
+// left for `Containers::autodetect` to probe the well-known socket path.
+pub enum RuntimeHost {
+    Explicit(String),
+    Disabled,
+    Auto,
+}
+
+// Client TLS material for an `https://` Docker/daemon endpoint; see
+// `Config::docker_tls`.
+#[derive(Clone)]
+pub struct DockerTls {
+    pub ca: PathBuf,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+// Custom CA and/or client cert/key for the control-plane gRPC connection;
+// see `Config::client_tls`. Each field is independent: a CA with no client
+// cert just tightens server verification, a client cert with the default
+// CA just adds mTLS on top of normal server verification.
+#[derive(Clone)]
+pub struct ClientTls {
+    pub ca_cert: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+}
+
+// How the active cloud metadata provider is selected; see `Config::cloud_provider`.
+pub enum CloudProvider {
+    Explicit(String),
+    Disabled,
+    Auto,
+}
+
+// Codec applied to the SBOM data stream before upload; see
+// `Config::sbom_compression`.
+#[derive(Clone, Copy)]
+pub enum SbomCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+// Which open-event capture backend to start with; see `Config::monitor_backend`.
+#[derive(Default)]
+pub enum OpenMonitorBackend {
+    #[default]
+    Auto,
+    Poll {
+        interval: Duration,
+    },
+}
+
+fn runtime_host(configured: &Option<String>) -> RuntimeHost {
+    match configured {
+        None => RuntimeHost::Auto,
+        Some(host) if host.is_empty() => RuntimeHost::Disabled,
+        Some(host) => RuntimeHost::Explicit(host.clone()),
+    }
+}
+
 fn paths(lst: &Option<Vec<String>>, def: &[&str]) -> Vec<PathBuf> {
     match lst {
         Some(lst) => lst.iter().map(|p| p.into()).collect(),
         None => def.iter().map(|s| s.into()).collect(),
     }
 }
-
-fn is_yes(val: &str) -> bool {
-    let val = val.to_lowercase();
-    val == "1" || val == "yes" || val == "true"
-}
@@ -1,9 +1,9 @@
 use std::collections::HashMap;
-use std::sync::Arc;
 use std::time::UNIX_EPOCH;
 use std::time::{SystemTime, Duration};
 
 use anyhow::{Result, anyhow};
+use async_trait::async_trait;
 use log::*;
 use bollard::Docker;
 use bollard::system::EventsOptions;
@@ -14,23 +14,36 @@ use lazy_static::lazy_static;
 use chrono::{DateTime, offset::Utc, offset::FixedOffset};
 
 use super::{ContainerEventsPtr, ContainerInfo};
+use super::runtime::ContainerRuntime;
+use crate::config::DockerTls;
 use crate::scoped_path::*;
 use crate::cloud_metadata::CloudMetadata;
 
 const GRAPH_DRIVER_OVERLAYFS: &str = "overlay2";
 const DOCKER_CONNECT_TIMEOUT: u64 = 5;
 
+// Storage drivers whose `GraphDriverData.Data` exposes the merged rootfs
+// directly under the same "MergedDir" key as overlay2 -- just the plain
+// overlay driver and fuse-overlayfs (a userspace overlay implementation
+// that mirrors the kernel one's Data layout).
+const OVERLAY_LIKE_DRIVERS: &[&str] = &[GRAPH_DRIVER_OVERLAYFS, "overlay", "fuse-overlayfs"];
+
 lazy_static! {
     static ref DT_UNIX_EPOCH: DateTime<FixedOffset> = DateTime::parse_from_rfc3339("1970-01-01T00:00:00-00:00").unwrap();
 }
 
 pub struct DockerTracker {
     docker: Docker,
+    cloud_meta: CloudMetadata,
+    // The last event timestamp processed, so that reconnecting after a
+    // dropped stream can replay from there instead of silently missing
+    // whatever happened in the gap.
+    since: Option<i64>,
 }
 
 impl DockerTracker {
-    pub async fn connect(host: &str) -> Result<Self> {
-        let docker = docker_connection(host)?;
+    pub async fn connect(host: &str, tls: Option<DockerTls>, cloud_meta: CloudMetadata) -> Result<Self> {
+        let docker = docker_connection(host, tls.as_ref())?;
 
         let mut quiet = false;
         loop {
@@ -54,6 +67,8 @@ impl DockerTracker {
 
         Ok(Self{
             docker,
+            cloud_meta,
+            since: None,
         })
     }
 
@@ -70,60 +85,7 @@ impl DockerTracker {
         Ok(false)
     }
 
-    pub async fn track(self, cloud_meta: CloudMetadata, events: ContainerEventsPtr) -> Result<()> {
-        let tracker = Arc::new(Tracker{
-            docker: self.docker,
-            cloud_meta,
-            events,
-        });
-
-        let events_task = {
-            let tracker = tracker.clone();
-            tokio::task::spawn(async move {
-                tracker.stream_events().await;
-            })
-        };
-
-        // Load already running containers
-        tracker.load_running().await?;
-
-        _ = events_task.await;
-
-        Ok(())
-    }
-}
-
-struct Tracker {
-    docker: Docker,
-    cloud_meta: CloudMetadata,
-    events: ContainerEventsPtr,
-}
-
-impl Tracker {
-    async fn stream_events(&self) {
-        let opts = EventsOptions {
-            since: None,
-            until: None,
-            filters: [
-                ("event", vec!["start", "die"]),
-            ].into(),
-        };
-
-        let mut stream = self.docker.events(Some(opts));
-
-        while let Some(evt) = stream.next().await {
-            debug!("Docker event: {evt:?}");
-
-            match evt {
-                Ok(msg) => self.process_event(msg).await,
-                Err(err) => error!("failed to receive docker event: {err}"),
-            }
-        }
-
-        debug!("Docker event streaming done");
-    }
-
-    async fn process_event(&self, msg: EventMessage) {
+    async fn process_event(&mut self, msg: EventMessage, events: &ContainerEventsPtr) {
         if msg.typ == Some(EventMessageTypeEnum::CONTAINER) {
             if let Some(action) = msg.action {
                 if let Some(actor) = msg.actor {
@@ -133,9 +95,9 @@ impl Tracker {
                         "start" => {
                             debug!("Container {id} started");
 
-                            match self.inspect_container(&id).await {
+                            match self.fetch_container(&id).await {
                                 Ok(info) => {
-                                    self.events.container_started(id, info).await;
+                                    events.container_started(id, info).await;
                                 },
                                 Err(err) => {
                                     error!("Failed to inspect container(id={id}): {err}");
@@ -147,7 +109,47 @@ impl Tracker {
                                 .map(systime_from_secs)
                                 .unwrap_or(SystemTime::now());
 
-                            self.events.container_stopped(id, end_time).await;
+                            events.container_stopped(id, end_time).await;
+                        },
+                        "oom" => {
+                            events.container_oom(id).await;
+                        },
+                        "pause" => {
+                            events.container_paused(id).await;
+                        },
+                        "unpause" => {
+                            events.container_resumed(id).await;
+                        },
+                        // Docker reports this as "health_status: healthy" /
+                        // "health_status: unhealthy" / "health_status: starting"
+                        // rather than as its own distinct action.
+                        action if action.starts_with("health_status") => {
+                            let status = action
+                                .split_once(':')
+                                .map(|(_, status)| status.trim())
+                                .unwrap_or(action)
+                                .to_string();
+
+                            self.events.container_health_changed(id, status).await;
+                        },
+                        // Docker reports this as "exec_start: <command>",
+                        // carrying the exec's own id separately on the actor
+                        // (there's no dedicated "exec" event type like
+                        // containerd's TaskExecAdded/TaskExecStarted).
+                        action if action.starts_with("exec_start") => {
+                            if let Some(exec_id) = actor.attributes.as_ref().and_then(|a| a.get("execID")) {
+                                let process_args = action
+                                    .split_once(':')
+                                    .map(|(_, cmd)| cmd.trim().split_whitespace().map(str::to_string).collect())
+                                    .unwrap_or_default();
+
+                                events.exec_started(id, exec_id.clone(), process_args).await;
+                            }
+                        },
+                        "exec_die" => {
+                            if let Some(exec_id) = actor.attributes.as_ref().and_then(|a| a.get("execID")) {
+                                events.exec_stopped(id, exec_id.clone()).await;
+                            }
                         },
                         _ => (),
                     }
@@ -156,51 +158,11 @@ impl Tracker {
         }
     }
 
-    async fn load_running(&self) -> Result<()> {
-        let opts = ListContainersOptions::<&str>{
-            filters: HashMap::from([
-                ("status", vec!["running"])
-            ]),
-            ..Default::default()
-        };
-
-        let conts = self.docker.list_containers(Some(opts)).await?;
-
-        for c in conts {
-            if c.id.is_none() {
-                continue;
-            }
-            let id = c.id.unwrap();
-
-            match self.inspect_container(&id).await {
-                Ok(info) => {
-                    debug!("Container started: {id}; {info:?}");
-                    self.events.container_started(id, info).await;
-                },
-                Err(err) => {
-                    error!("Docker inspect_container({id}): {err}");
-                    continue;
-                }
-            };
-        }
-
-        Ok(())
-    }
-
-    async fn inspect_container(&self, id: &str) -> Result<ContainerInfo> {
+    async fn fetch_container(&self, id: &str) -> Result<ContainerInfo> {
         let cont_resp = self.docker.inspect_container(id, None).await?;
 
-        let rootfs = match cont_resp.graph_driver {
-            Some(mut driver) => {
-                if driver.name == GRAPH_DRIVER_OVERLAYFS {
-                    driver.data.remove("MergedDir")
-                        .map(HostPath::from)
-                } else {
-                    None
-                }
-            },
-            None => None,
-        };
+        let pid = cont_resp.state.as_ref().and_then(|s| s.pid);
+        let rootfs = resolve_rootfs(cont_resp.graph_driver, pid);
 
         let image_tag = match &cont_resp.image {
             Some(id) => {
@@ -253,13 +215,136 @@ impl Tracker {
 
 }
 
+#[async_trait]
+impl ContainerRuntime for DockerTracker {
+    async fn load_running(&mut self, events: &ContainerEventsPtr) -> Result<()> {
+        let opts = ListContainersOptions::<&str>{
+            filters: HashMap::from([
+                ("status", vec!["running"])
+            ]),
+            ..Default::default()
+        };
+
+        let conts = self.docker.list_containers(Some(opts)).await?;
+
+        for c in conts {
+            if c.id.is_none() {
+                continue;
+            }
+            let id = c.id.unwrap();
+
+            match self.fetch_container(&id).await {
+                Ok(info) => {
+                    debug!("Container started: {id}; {info:?}");
+                    events.container_started(id, info).await;
+                },
+                Err(err) => {
+                    error!("Docker inspect_container({id}): {err}");
+                    continue;
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    // Replays from `self.since`, the timestamp of the last event this
+    // tracker actually processed, so reconnecting after a dropped stream
+    // doesn't silently miss whatever happened in the gap.
+    async fn stream_events(&mut self, events: &ContainerEventsPtr) -> Result<()> {
+        let opts = EventsOptions {
+            since: self.since.map(|t| t.to_string()),
+            until: None,
+            filters: [
+                ("event", vec!["start", "die", "oom", "pause", "unpause", "health_status", "exec_start", "exec_die"]),
+            ].into(),
+        };
+
+        let mut stream = self.docker.events(Some(opts));
+
+        while let Some(evt) = stream.next().await {
+            debug!("Docker event: {evt:?}");
+
+            let msg = evt?;
+            if let Some(t) = msg.time {
+                self.since = Some(t);
+            }
+
+            self.process_event(msg, events).await;
+        }
+
+        Ok(())
+    }
+
+    async fn inspect_container(&mut self, id: &str) -> Result<Option<ContainerInfo>> {
+        Ok(Some(self.fetch_container(id).await?))
+    }
+}
+
+// Turns Docker's storage-driver-specific `GraphDriverData` into a single
+// rootfs path, regardless of which driver the daemon is configured with.
+// overlay2/overlay/fuse-overlayfs all expose it directly as "MergedDir";
+// btrfs and zfs don't put a path in `Data` at all, so the well-known
+// on-disk layout docker's own drivers use is reconstructed from the
+// container id they do report. Anything else (devicemapper, vfs, or a
+// future driver not special-cased here) falls back to resolving the rootfs
+// through the container's own mount namespace via `/proc/<pid>/root`,
+// which works no matter how the graph driver stores things -- as long as
+// the container has a running process to read it through.
+fn resolve_rootfs(driver: Option<bollard::models::GraphDriverData>, pid: Option<i64>) -> Option<HostPath> {
+    if let Some(mut driver) = driver {
+        let path = if OVERLAY_LIKE_DRIVERS.contains(&driver.name.as_str()) {
+            driver.data.remove("MergedDir")
+        } else if driver.name == "btrfs" {
+            driver.data.remove("DeviceId")
+                .map(|id| format!("/var/lib/docker/btrfs/subvolumes/{id}"))
+        } else if driver.name == "zfs" {
+            driver.data.remove("Dataset")
+                .map(|dataset| format!("/var/lib/docker/zfs/graph/{dataset}"))
+        } else {
+            None
+        };
+
+        if let Some(path) = path {
+            return Some(HostPath::from(path));
+        }
+    }
+
+    pid.map(|pid| HostPath::from(format!("/proc/{pid}/root")))
+}
+
 fn systime_from_secs(secs: i64) -> SystemTime {
     let dur = Duration::from_secs(secs as u64);
     UNIX_EPOCH + dur
 }
 
-fn docker_connection(host: &str) -> Result<Docker> {
-    if host.starts_with("tcp://") || host.starts_with("http://") {
+fn docker_connection(host: &str, tls: Option<&DockerTls>) -> Result<Docker> {
+    if host.starts_with("https://") {
+        let tls = tls.ok_or_else(|| anyhow!(
+            "{host}: https:// Docker host requires client TLS config (DOCKER_CERT_PATH/DOCKER_TLS_VERIFY or docker_cert_path/docker_tls_verify)"
+        ))?;
+
+        Ok(Docker::connect_with_ssl(
+            host,
+            &tls.key,
+            &tls.cert,
+            &tls.ca,
+            DOCKER_CONNECT_TIMEOUT,
+            bollard::API_DEFAULT_VERSION,
+        )?)
+    } else if let Some(tls) = tls.filter(|_| host.starts_with("tcp://")) {
+        // `docker -H tcp://host:2376 --tlsverify` configures TLS on a plain
+        // `tcp://` host rather than spelling it `https://`, so honor
+        // `tls` here too instead of only reacting to the scheme.
+        Ok(Docker::connect_with_ssl(
+            host,
+            &tls.key,
+            &tls.cert,
+            &tls.ca,
+            DOCKER_CONNECT_TIMEOUT,
+            bollard::API_DEFAULT_VERSION,
+        )?)
+    } else if host.starts_with("tcp://") || host.starts_with("http://") {
         Ok(Docker::connect_with_http(host, DOCKER_CONNECT_TIMEOUT, bollard::API_DEFAULT_VERSION)?)
     } else if host.starts_with("unix://") {
         Ok(Docker::connect_with_unix(host, DOCKER_CONNECT_TIMEOUT, bollard::API_DEFAULT_VERSION)?)
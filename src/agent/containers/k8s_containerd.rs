@@ -1,29 +1,34 @@
 use std::time::{SystemTime, Duration};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
 use anyhow::{Result, anyhow};
+use async_trait::async_trait;
 use log::*;
 use serde::Deserialize;
 use tonic::transport::channel::Channel;
 use tonic::Request;
 use containerd_client::with_namespace;
-use containerd_client::services::v1::{SubscribeRequest, ListTasksRequest, GetContainerRequest, Container, ListContainersRequest};
+use containerd_client::services::v1::{SubscribeRequest, ListTasksRequest, GetContainerRequest, Container, ListContainersRequest, MountsRequest, GetRequest};
 use containerd_client::types::v1::Status;
 use containerd_client::services::v1::events_client::EventsClient;
 use containerd_client::services::v1::containers_client::ContainersClient;
 use containerd_client::services::v1::tasks_client::TasksClient;
+use containerd_client::services::v1::snapshots_client::SnapshotsClient;
 use containerd_client::events::*;
-use oci_spec::runtime::Spec;
+use oci_spec::runtime::{Spec, Process};
 use prost::DecodeError;
 use prost_types::Any;
 
 use super::{ContainerEventsPtr, ContainerInfo};
+use super::runtime::ContainerRuntime;
+use crate::cloud_metadata::CloudMetadata;
 use crate::scoped_path::*;
 use crate::label::*;
 
 const NAMESPACE: &str = "k8s.io";
 const OCI_SPEC_TYPE_NAME: &str = "types.containerd.io/opencontainers/runtime-spec/1/Spec";
+const OCI_PROCESS_SPEC_TYPE_NAME: &str = "types.containerd.io/opencontainers/runtime-spec/1/Process";
 
 const CONTAINER_LABEL_KIND: &str = "io.cri-containerd.kind";
 const CONTAINER_LABEL_NAME: &str = "io.kubernetes.container.name";
@@ -38,10 +43,14 @@ pub struct K8sContainerdTracker {
     containers: ContainersClient<Channel>,
     tasks: TasksClient<Channel>,
     events: EventsClient<Channel>,
+    snapshots: SnapshotsClient<Channel>,
+    roots: HostPath,
+    cloud_meta: CloudMetadata,
+    tracked: HashSet<String>,
 }
 
 impl K8sContainerdTracker {
-    pub async fn connect(host: &str) -> Self {
+    pub async fn connect(host: &str, roots: HostPath, cloud_meta: CloudMetadata) -> Self {
         let mut quiet = false;
         let ch = loop {
             match super::grpc_connect(host).await {
@@ -66,53 +75,20 @@ impl K8sContainerdTracker {
             containers: ContainersClient::new(ch.clone()),
             tasks: TasksClient::new(ch.clone()),
             events: EventsClient::new(ch.clone()),
+            snapshots: SnapshotsClient::new(ch.clone()),
+            roots,
+            cloud_meta,
+            tracked: HashSet::new(),
         }
     }
 
-    pub async fn track(mut self, events: ContainerEventsPtr) -> Result<()> {
-        let events_task = tokio::task::spawn(
-            self.clone().stream_events(events.clone())
-        );
-
-        // Load already running containers
-        self.load_running(events.clone()).await?;
-
-        if let Err(err) = events_task.await.unwrap() {
-            error!("Events streaming: {err}");
-        }
-        Ok(())
-    }
-
-    async fn stream_events(mut self, events: ContainerEventsPtr) -> Result<()> {
-        let req = SubscribeRequest{
-            filters: Vec::new(),
-        };
-
-        let req = with_namespace!(req, NAMESPACE);
-
-        let mut stream = self.events.subscribe(req)
-            .await?
-            .into_inner();
-
-        while let Some(msg) = stream.message().await? {
-            if let Some(event) = msg.event {
-                match ContainerdEvent::try_from(event) {
-                    Ok(event) => self.process_event(event, events.clone()).await,
-                    Err(err) => error!("Event decoding error: {err}"),
-                }
-            }
-        }
-
-        debug!("containerd event streaming done");
-        Ok(())
-    }
-
-    async fn process_event(&mut self, event: ContainerdEvent, events: ContainerEventsPtr) {
+    async fn process_event(&mut self, event: ContainerdEvent, events: &ContainerEventsPtr) {
         match event {
             ContainerdEvent::TaskCreate(msg) => {
                 debug!("Container {} created", msg.container_id);
                 match self.inspect_container(&msg.container_id).await {
                     Ok(Some(info)) => {
+                        self.tracked.insert(msg.container_id.clone());
                         events.container_started(msg.container_id, info).await;
                     },
                     Ok(None) => (),
@@ -127,13 +103,46 @@ impl K8sContainerdTracker {
                     .and_then(|t| t.try_into().ok())
                     .unwrap_or(SystemTime::now());
 
+                self.tracked.remove(&msg.container_id);
                 events.container_stopped(msg.container_id, end_time).await;
             },
+            ContainerdEvent::TaskExecAdded(msg) => {
+                let process_args = self.exec_process_args(&msg.container_id, &msg.exec_id).await;
+                events.exec_started(msg.container_id, msg.exec_id, process_args).await;
+            },
+            ContainerdEvent::TaskExecStarted(_) => {
+                // The exec session was already reported by `TaskExecAdded`;
+                // this just confirms it now has a pid, which isn't otherwise
+                // actionable for attribution purposes.
+            },
+            ContainerdEvent::TaskExit(msg) => {
+                // `id` is the exec id for an exec process, or the container
+                // id itself for the container's main process -- the latter
+                // is already covered by `TaskDelete` below.
+                if msg.id != msg.container_id {
+                    events.exec_stopped(msg.container_id, msg.id).await;
+                }
+            },
+            ContainerdEvent::TaskOom(msg) => {
+                events.container_oom(msg.container_id).await;
+            },
+            ContainerdEvent::TaskPaused(msg) => {
+                events.container_paused(msg.container_id).await;
+            },
+            ContainerdEvent::TaskResumed(msg) => {
+                events.container_resumed(msg.container_id).await;
+            },
             _ => (),
         }
     }
 
-    async fn load_running(&mut self, events: ContainerEventsPtr) -> Result<()> {
+    // Diffs the currently-running task set against `self.tracked`, emitting
+    // `container_started` for anything newly seen and `container_stopped`
+    // for anything `self.tracked` thought was running but isn't anymore.
+    // Used both as the initial seed (with `self.tracked` empty) and after
+    // an event stream reconnect, where start/stop events may have been
+    // missed while the stream was down.
+    async fn resync(&mut self, events: &ContainerEventsPtr) -> Result<()> {
         let mut containers = self.load_containers().await?;
 
         let req = ListTasksRequest {
@@ -146,14 +155,28 @@ impl K8sContainerdTracker {
             .await?
             .into_inner();
 
+        let mut running = HashSet::new();
+
         for t in resp.tasks {
             if Status::from_i32(t.status) == Some(Status::Running) {
-                if let Some(info) = containers.remove(&t.id) {
-                    events.container_started(t.id, info).await;
+                running.insert(t.id.clone());
+
+                if !self.tracked.contains(&t.id) {
+                    if let Some(info) = containers.remove(&t.id) {
+                        events.container_started(t.id, info).await;
+                    }
                 }
             }
         }
 
+        for id in self.tracked.iter() {
+            if !running.contains(id) {
+                events.container_stopped(id.clone(), SystemTime::now()).await;
+            }
+        }
+
+        self.tracked = running;
+
         Ok(())
     }
 
@@ -182,31 +205,6 @@ impl K8sContainerdTracker {
         Ok(map)
     }
 
-    async fn inspect_container(&mut self, id: &str) -> Result<Option<ContainerInfo>> {
-        let req = GetContainerRequest{
-            id: id.to_string(),
-        };
-
-        let req = with_namespace!(req, NAMESPACE);
-
-        let resp = self.containers.get(req)
-            .await?
-            .into_inner();
-
-        if let Some(c) = resp.container {
-            if !is_container(&c) {
-                return Ok(None);
-            }
-
-            let (_, ci) = self.into_container_info(c).await;
-
-            Ok(Some(ci))
-
-        } else {
-            Err(anyhow!("containers.get() missing 'container'"))
-        }
-    }
-
     async fn into_container_info(&mut self, mut c: Container) -> (String, ContainerInfo) {
         let image_id = if let Some(meta) = c.extensions.remove(CRI_CONTAINERD_CONTAINER_METADATA) {
             match into_cri_metadata(meta) {
@@ -232,9 +230,15 @@ impl K8sContainerdTracker {
         }
 
         if let Some(ns) = ns {
-            labels.insert(LABEL_KUBE_NAMESPACE.to_string(), ns);
+            labels.insert(LABEL_KUBE_NAMESPACE_NAME.to_string(), ns);
         }
 
+        // Layers in the workload kind/name, pod labels/annotations, node
+        // name, and cluster id that `KubernetesMetadata` resolves from the
+        // API server -- the CRI labels above only carry the pod's own name
+        // and namespace, not anything about the controller that owns it.
+        labels.extend(self.cloud_meta.container_labels(&c.id));
+
         let mounts: Vec<PathBuf> = if let Some(spec) = c.spec {
             if let Some(oci_spec) = into_oci_spec(spec) {
                 if let Some(mounts) = oci_spec.mounts() {
@@ -253,11 +257,23 @@ impl K8sContainerdTracker {
 
         debug!("Container (id={}) mounts: {mounts:?}", c.id);
 
+        let rootfs = match self.snapshot_rootfs(&c.snapshotter, &c.snapshot_key).await {
+            Ok(Some(rootfs)) => rootfs,
+            Ok(None) => {
+                warn!("Container {}: snapshotter {} returned no mounts, falling back to the default task rootfs path", c.id, c.snapshotter);
+                fallback_rootfs(&self.roots, &c.id)
+            },
+            Err(err) => {
+                warn!("Container {}: failed to resolve rootfs via snapshotter {}: {err}, falling back to the default task rootfs path", c.id, c.snapshotter);
+                fallback_rootfs(&self.roots, &c.id)
+            }
+        };
+
         let ci = ContainerInfo{
             name,
             image_id,
             image: Some(c.image),
-            rootfs: Some(get_rootfs(&c.id)),
+            rootfs: Some(rootfs),
             start_time: c.created_at.and_then(|t| t.try_into().ok()),
             end_time: None,
             mounts,
@@ -266,11 +282,136 @@ impl K8sContainerdTracker {
 
         (c.id, ci)
     }
+
+    // Asks the snapshotter that actually owns this container's filesystem
+    // for its mounts, rather than assuming the on-disk layout of the
+    // default overlayfs snapshotter under the default state dir -- this
+    // keeps working for custom `--root`/`--state` daemon configs and
+    // non-overlay snapshotters (e.g. btrfs, zfs, devmapper).
+    async fn snapshot_rootfs(&mut self, snapshotter: &str, key: &str) -> Result<Option<HostPath>> {
+        if snapshotter.is_empty() || key.is_empty() {
+            return Ok(None);
+        }
+
+        let req = MountsRequest{
+            snapshotter: snapshotter.to_string(),
+            key: key.to_string(),
+        };
+
+        let req = with_namespace!(req, NAMESPACE);
+
+        let resp = self.snapshots.mounts(req)
+            .await?
+            .into_inner();
+
+        Ok(merged_rootfs_from_mounts(&resp.mounts))
+    }
+
+    // Best-effort: the exec's own process spec isn't carried on
+    // `TaskExecAdded` itself, so it has to be fetched separately. Falls back
+    // to an empty command if the task has already gone away by the time
+    // this runs, or if the shim doesn't report a spec.
+    async fn exec_process_args(&mut self, container_id: &str, exec_id: &str) -> Vec<String> {
+        let req = GetRequest{
+            container_id: container_id.to_string(),
+            exec_id: exec_id.to_string(),
+        };
+
+        let req = with_namespace!(req, NAMESPACE);
+
+        match self.tasks.get(req).await {
+            Ok(resp) => resp.into_inner().process
+                .and_then(|p| p.spec)
+                .and_then(into_oci_process)
+                .and_then(|p| p.args().clone())
+                .unwrap_or_default(),
+            Err(err) => {
+                debug!("Failed to fetch exec process spec for {container_id}/{exec_id}: {err}");
+                Vec::new()
+            }
+        }
+    }
 }
 
-fn get_rootfs(id: &str) -> HostPath {
-    // TODO: This is far from ideal and we need to look into how to get this info from the API.
-    format!("/run/containerd/io.containerd.runtime.v2.task/k8s.io/{id}/rootfs/").into()
+#[async_trait]
+impl ContainerRuntime for K8sContainerdTracker {
+    async fn load_running(&mut self, events: &ContainerEventsPtr) -> Result<()> {
+        self.resync(events).await
+    }
+
+    // Subscribes and streams until the connection ends or errors, then
+    // reconciles `self.tracked` against whatever's actually running before
+    // `track()` resubscribes, since start/stop events may have been missed
+    // while the stream was down.
+    async fn stream_events(&mut self, events: &ContainerEventsPtr) -> Result<()> {
+        let req = SubscribeRequest{
+            filters: Vec::new(),
+        };
+
+        let req = with_namespace!(req, NAMESPACE);
+
+        let mut stream = self.events.subscribe(req)
+            .await?
+            .into_inner();
+
+        while let Some(msg) = stream.message().await? {
+            if let Some(event) = msg.event {
+                match ContainerdEvent::try_from(event) {
+                    Ok(event) => self.process_event(event, events).await,
+                    Err(err) => error!("Event decoding error: {err}"),
+                }
+            }
+        }
+
+        self.resync(events).await
+    }
+
+    async fn inspect_container(&mut self, id: &str) -> Result<Option<ContainerInfo>> {
+        let req = GetContainerRequest{
+            id: id.to_string(),
+        };
+
+        let req = with_namespace!(req, NAMESPACE);
+
+        let resp = self.containers.get(req)
+            .await?
+            .into_inner();
+
+        if let Some(c) = resp.container {
+            if !is_container(&c) {
+                return Ok(None);
+            }
+
+            let (_, ci) = self.into_container_info(c).await;
+
+            Ok(Some(ci))
+
+        } else {
+            Err(anyhow!("containers.get() missing 'container'"))
+        }
+    }
+}
+
+// Overlay-style snapshotters (the default "overlayfs" plus any that follow
+// the same convention, e.g. "native") expose the already-merged view
+// directly as a mount option; anything else reports its merged path as the
+// mount target itself.
+fn merged_rootfs_from_mounts(mounts: &[containerd_client::types::v1::Mount]) -> Option<HostPath> {
+    for m in mounts {
+        for opt in &m.options {
+            if let Some(merged) = opt.strip_prefix("merged=") {
+                return Some(HostPath::from(merged.to_string()));
+            }
+        }
+    }
+
+    mounts.first()
+        .map(|m| HostPath::from(m.target.clone()))
+        .filter(|t| !t.as_raw().as_os_str().is_empty())
+}
+
+fn fallback_rootfs(roots: &HostPath, id: &str) -> HostPath {
+    format!("{}/{id}/rootfs/", roots.display()).into()
 }
 
 // containerd events
@@ -353,6 +494,14 @@ fn into_oci_spec(spec: Any) -> Option<Spec> {
     None
 }
 
+fn into_oci_process(spec: Any) -> Option<Process> {
+    if &spec.type_url == OCI_PROCESS_SPEC_TYPE_NAME {
+        return serde_json::from_slice(&spec.value).ok();
+    }
+
+    None
+}
+
 #[derive(Deserialize)]
 struct CriMetadata {
     #[serde(rename = "Version")]
@@ -1,11 +1,13 @@
 pub mod docker;
 pub mod podman;
 pub mod k8s_containerd;
+pub mod cri;
+pub mod runtime;
 
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, Duration};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Result, anyhow};
 use log::*;
@@ -22,14 +24,32 @@ use tower::service_fn;
 use docker::DockerTracker;
 use podman::PodmanTracker;
 use k8s_containerd::K8sContainerdTracker;
+use cri::CriTracker;
+use runtime::ContainerRuntime;
 
-use crate::config::Config;
+use crate::backoff::DecorrelatedJitter;
+use crate::config::{Config, RuntimeHost};
+use crate::open_event_queue::OpenEventSender;
+use crate::open_monitor::OpenEventBarriers;
 use crate::scoped_path::*;
 use crate::cloud_metadata::CloudMetadata;
 
-// Docker containers will contain the id somewhere in the cgroup name
+// Fallback timeout for the open-event barrier armed in `container_stopped`;
+// only hit if the sentinel is ever lost (e.g. the queue is full).
 const CONTAINER_CLEANUP_LAG: Duration = Duration::from_secs(10);
 
+// Well-known socket paths probed by `Containers::autodetect` when a
+// runtime's host isn't pinned or disabled via config.
+const DOCKER_SOCKET: &str = "/run/docker.sock";
+const CONTAINERD_SOCKET: &str = "/run/containerd/containerd.sock";
+const CRIO_SOCKET: &str = "/run/crio/crio.sock";
+const PODMAN_SOCKET: &str = "/run/podman/podman.sock";
+
+// Backoff between restart attempts for a supervised runtime tracker
+// (see `Containers::spawn_runner`).
+const RUNNER_BACKOFF_BASE: Duration = Duration::from_millis(500);
+const RUNNER_BACKOFF_CAP: Duration = Duration::from_secs(30);
+
 lazy_static! {
     // Docker containers will contain the id somewhere in the cgroup name
     static ref CGROUP_NAME_RE: Regex = Regex::new(r".*([[:xdigit:]]{64})").unwrap();
@@ -58,26 +78,59 @@ pub type ContainerMap = HashMap<String, ContainerInfo>;
 struct Inner {
     cont_map: Arc<Mutex<ContainerMap>>,
     ch: Sender<ContainerEvent>,
+    open_ch: OpenEventSender,
+    open_barriers: OpenEventBarriers,
+}
+
+// Live state of a supervised runtime tracker. `Dead` isn't terminal: the
+// runner keeps retrying with backoff and flips back to `Starting` on the
+// next attempt, so operators can tell a transient outage apart from a
+// tracker that's up and streaming events.
+#[derive(Clone, Debug)]
+pub enum RunnerState {
+    Starting,
+    Active,
+    Dead(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct RunnerStatus {
+    pub name: String,
+    pub state: RunnerState,
+}
+
+struct Runner {
+    name: String,
+    status: Arc<Mutex<RunnerState>>,
+    task: JoinHandle<()>,
 }
 
 pub struct Containers {
     inner: Arc<Inner>,
     config: Arc<Config>,
-    tasks: Vec<JoinHandle<()>>,
+    runners: Vec<Runner>,
     cloud_meta: CloudMetadata,
 }
 
 impl Containers {
-    pub fn new(config: Arc<Config>, cloud_meta: CloudMetadata, ch: Sender<ContainerEvent>) -> Self {
+    pub fn new(
+        config: Arc<Config>,
+        cloud_meta: CloudMetadata,
+        ch: Sender<ContainerEvent>,
+        open_ch: OpenEventSender,
+        open_barriers: OpenEventBarriers,
+    ) -> Self {
         let inner = Arc::new(Inner {
             cont_map: Arc::new(Mutex::new(ContainerMap::new())),
             ch,
+            open_ch,
+            open_barriers,
         });
 
         Self {
             inner,
             config,
-            tasks: Vec::new(),
+            runners: Vec::new(),
             cloud_meta,
         }
     }
@@ -85,59 +138,180 @@ impl Containers {
     pub fn track_docker(&mut self, host: String) {
         let ev: ContainerEventsPtr = self.inner.clone();
         let cloud_meta = self.cloud_meta.clone();
+        let tls = self.config.docker_tls();
+
+        self.spawn_runner("docker", move |status| {
+            let host = host.clone();
+            let ev = ev.clone();
+            let cloud_meta = cloud_meta.clone();
+            let tls = tls.clone();
+
+            async move {
+                let tracker = DockerTracker::connect(&host, tls, cloud_meta.clone()).await?;
+
+                if tracker.is_podman().await? {
+                    info!("Podman detected, reconnecting");
+                    let tracker = PodmanTracker::connect(&host, cloud_meta).await?;
+                    *status.lock().unwrap() = RunnerState::Active;
+                    tracker.track(ev).await
+                } else {
+                    *status.lock().unwrap() = RunnerState::Active;
+                    tracker.track(ev).await
+                }
+            }
+        });
+    }
 
-        let task = tokio::task::spawn(async move {
-            loop {
-                let tracker = match DockerTracker::connect(&host).await {
-                    Ok(tracker) => tracker,
-                    Err(err) => {
-                        error!("Failed to connect to docker: {err}");
-                        return;
-                    }
-                };
+    pub fn track_k8s(&mut self, host: String) {
+        let ev: ContainerEventsPtr = self.inner.clone();
+        let roots = HostPath::from(self.config.containerd_roots());
+        let cloud_meta = self.cloud_meta.clone();
 
-                match tracker.is_podman().await {
-                    Ok(true) => {
-                        info!("Podman detected, reconnecting");
-                        match PodmanTracker::connect(&host).await {
-                            Ok(tracker) => {
-                                if let Err(err) = tracker.track(cloud_meta.clone(), ev.clone()).await {
-                                    error!("Container monitoring: {err}");
-                                }
-                            },
-
-                            Err(err) => error!("Failed to connect to podman: {err}"),
-                        }
-                    },
-                    _ => {
-                        if let Err(err) = tracker.track(cloud_meta.clone(), ev.clone()).await {
-                            error!("Container monitoring: {err}");
-                        }
-                    }
-                }
+        self.spawn_runner("containerd", move |status| {
+            let host = host.clone();
+            let roots = roots.clone();
+            let ev = ev.clone();
+            let cloud_meta = cloud_meta.clone();
 
-                tokio::time::sleep(Duration::from_secs(1)).await;
+            async move {
+                let tracker = K8sContainerdTracker::connect(&host, roots, cloud_meta).await;
+                *status.lock().unwrap() = RunnerState::Active;
+                tracker.track(ev).await
             }
         });
+    }
+
+    pub fn track_crio(&mut self, host: String) {
+        let ev: ContainerEventsPtr = self.inner.clone();
+        let resync_interval = self.config.crio_resync_interval();
 
-        self.tasks.push(task);
+        self.spawn_runner("crio", move |status| {
+            let host = host.clone();
+            let ev = ev.clone();
+
+            async move {
+                let tracker = CriTracker::connect(&host, resync_interval).await;
+                *status.lock().unwrap() = RunnerState::Active;
+                tracker.track(ev).await
+            }
+        });
     }
 
-    pub fn track_k8s(&mut self, host: String) {
+    pub fn track_podman(&mut self, host: String) {
         let ev: ContainerEventsPtr = self.inner.clone();
-        let roots = HostPath::from(self.config.containerd_roots());
+        let cloud_meta = self.cloud_meta.clone();
+
+        self.spawn_runner("podman", move |status| {
+            let host = host.clone();
+            let ev = ev.clone();
+            let cloud_meta = cloud_meta.clone();
+
+            async move {
+                let tracker = PodmanTracker::connect(&host, cloud_meta).await?;
+                *status.lock().unwrap() = RunnerState::Active;
+                tracker.track(ev).await
+            }
+        });
+    }
+
+    // Spawns `name` as a supervised, auto-restarting background runner:
+    // `make_attempt` is called in a loop, each call getting a fresh handle
+    // to report its own state, and a failed attempt is retried with
+    // decorrelated-jitter backoff instead of the old fixed 1s sleep (and,
+    // unlike the previous Docker tracker, a connect failure no longer kills
+    // the task outright).
+    fn spawn_runner<F, Fut>(&mut self, name: &str, mut make_attempt: F)
+    where
+        F: FnMut(Arc<Mutex<RunnerState>>) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send,
+    {
+        let status = Arc::new(Mutex::new(RunnerState::Starting));
+        let task_status = status.clone();
+        let name = name.to_string();
+        let task_name = name.clone();
 
         let task = tokio::task::spawn(async move {
+            let mut backoff = DecorrelatedJitter::new(RUNNER_BACKOFF_BASE, RUNNER_BACKOFF_CAP);
+
             loop {
-                let tracker = K8sContainerdTracker::connect(&host, roots.clone()).await;
-                if let Err(err) = tracker.track(ev.clone()).await {
-                    error!("Container monitoring: {err}");
+                *task_status.lock().unwrap() = RunnerState::Starting;
+
+                if let Err(err) = make_attempt(task_status.clone()).await {
+                    error!("{task_name} tracker failed: {err}");
+                    *task_status.lock().unwrap() = RunnerState::Dead(err.to_string());
+                } else {
+                    backoff.reset();
                 }
 
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                tokio::time::sleep(backoff.next_delay()).await;
             }
         });
-        self.tasks.push(task);
+
+        self.runners.push(Runner { name, status, task });
+    }
+
+    // Live status of every tracker this `Containers` has spawned, for
+    // runtime introspection (e.g. an admin endpoint or /metrics).
+    pub fn workers(&self) -> Vec<RunnerStatus> {
+        self.runners
+            .iter()
+            .map(|r| RunnerStatus {
+                name: r.name.clone(),
+                state: r.status.lock().unwrap().clone(),
+            })
+            .collect()
+    }
+
+    // Wires up tracking for every runtime EdgeBit knows how to talk to.
+    // Each one can be pinned to an explicit host or turned off outright via
+    // config/env (see Config::docker_host & co); anything left on auto
+    // falls back to probing that runtime's well-known socket path, so the
+    // agent works out-of-the-box without the operator naming the exact
+    // socket.
+    pub fn autodetect(&mut self) {
+        match self.config.docker_host() {
+            RuntimeHost::Explicit(host) => self.track_docker(host),
+            RuntimeHost::Disabled => info!("Docker tracking disabled by config"),
+            RuntimeHost::Auto => {
+                if let Some(host) = probe_socket(DOCKER_SOCKET) {
+                    info!("Auto-detected Docker at {DOCKER_SOCKET}");
+                    self.track_docker(host);
+                }
+            }
+        }
+
+        match self.config.containerd_host() {
+            RuntimeHost::Explicit(host) => self.track_k8s(host),
+            RuntimeHost::Disabled => info!("containerd tracking disabled by config"),
+            RuntimeHost::Auto => {
+                if let Some(host) = probe_socket(CONTAINERD_SOCKET) {
+                    info!("Auto-detected containerd at {CONTAINERD_SOCKET}");
+                    self.track_k8s(host);
+                }
+            }
+        }
+
+        match self.config.podman_host() {
+            RuntimeHost::Explicit(host) => self.track_podman(host),
+            RuntimeHost::Disabled => info!("Podman tracking disabled by config"),
+            RuntimeHost::Auto => {
+                if let Some(host) = probe_socket(PODMAN_SOCKET) {
+                    info!("Auto-detected Podman at {PODMAN_SOCKET}");
+                    self.track_podman(host);
+                }
+            }
+        }
+
+        match self.config.crio_host() {
+            RuntimeHost::Explicit(host) => self.track_crio(host),
+            RuntimeHost::Disabled => info!("CRI-O tracking disabled by config"),
+            RuntimeHost::Auto => {
+                if let Some(host) = probe_socket(CRIO_SOCKET) {
+                    info!("Auto-detected CRI-O at {CRIO_SOCKET}");
+                    self.track_crio(host);
+                }
+            }
+        }
     }
 
     pub fn id_from_cgroup(&self, cgroup: &str) -> Option<String> {
@@ -163,6 +337,24 @@ impl Containers {
 pub trait ContainerRuntimeEvents {
     async fn container_started(&self, id: String, info: ContainerInfo);
     async fn container_stopped(&self, id: String, stop_time: SystemTime);
+
+    // The events below don't change whether a container is monitored --
+    // unlike `container_started`/`container_stopped`, they don't carry a
+    // `ContainerInfo`/stop time the workload pipeline needs -- so they're
+    // surfaced as plain log lines rather than threaded through `ch` and
+    // `ContainerEvent`. Operators can still see them, but adding one didn't
+    // require an exhaustive match update in every `ContainerEvent` consumer.
+    async fn container_oom(&self, id: String);
+    async fn container_paused(&self, id: String);
+    async fn container_resumed(&self, id: String);
+    async fn container_health_changed(&self, id: String, status: String);
+
+    // A process injected into an already-running container via `docker
+    // exec`/`kubectl exec` rather than the container's own entrypoint.
+    // `exec_id` scopes `exec_stopped` to the specific session that started,
+    // since a container can have more than one exec session live at once.
+    async fn exec_started(&self, container_id: String, exec_id: String, process_args: Vec<String>);
+    async fn exec_stopped(&self, container_id: String, exec_id: String);
 }
 
 #[async_trait]
@@ -183,12 +375,26 @@ impl ContainerRuntimeEvents for Inner {
         // TODO: it's racy to rely on the cont_map to have the info since
         // if this is called before load_running, it may not be there.
         if self.cont_map.lock().unwrap().contains_key(&id) {
-            // Hack to deal with open events also being processed under delay
             let ch = self.ch.clone();
             let cont_map = self.cont_map.clone();
+            let open_ch = self.open_ch.clone();
+            let open_barriers = self.open_barriers.clone();
 
             tokio::task::spawn(async move {
-                tokio::time::sleep(CONTAINER_CLEANUP_LAG).await;
+                // Wait for every open event enqueued before this point to
+                // drain, so a file opened right as the container exited is
+                // still attributed to it instead of racing the removal
+                // below. Fall back to the old fixed delay if the sentinel
+                // is ever lost.
+                let barrier = open_barriers.arm(&open_ch).await;
+                if tokio::time::timeout(CONTAINER_CLEANUP_LAG, barrier)
+                    .await
+                    .is_err()
+                {
+                    warn!(
+                        "Open-event barrier for stopped container {id} timed out after {CONTAINER_CLEANUP_LAG:?}; removing anyway"
+                    );
+                }
 
                 let info = {
                     cont_map.lock().unwrap().remove(&id)
@@ -201,6 +407,30 @@ impl ContainerRuntimeEvents for Inner {
             });
         }
     }
+
+    async fn container_oom(&self, id: String) {
+        warn!("Container {id} OOM killed");
+    }
+
+    async fn container_paused(&self, id: String) {
+        info!("Container {id} paused");
+    }
+
+    async fn container_resumed(&self, id: String) {
+        info!("Container {id} resumed");
+    }
+
+    async fn container_health_changed(&self, id: String, status: String) {
+        info!("Container {id} health status changed to {status}");
+    }
+
+    async fn exec_started(&self, container_id: String, exec_id: String, process_args: Vec<String>) {
+        info!("Container {container_id}: exec {exec_id} started: {process_args:?}");
+    }
+
+    async fn exec_stopped(&self, container_id: String, exec_id: String) {
+        info!("Container {container_id}: exec {exec_id} stopped");
+    }
 }
 
 pub type ContainerEventsPtr = Arc<dyn ContainerRuntimeEvents + Send + Sync>;
@@ -210,6 +440,16 @@ pub async fn grpc_connect(host: &str) -> Result<Channel> {
 
     let ep = Endpoint::try_from("http://[::]").unwrap();
 
+    // `containerd://` is accepted as an alias for `unix://` so an explicit
+    // `containerd_host`/`crio_host` config value can name the runtime it's
+    // dialing without needing to know that both trackers reach it over a
+    // plain unix socket under the hood.
+    let host = match host.strip_prefix("containerd://") {
+        Some(path) => format!("unix://{path}"),
+        None => host.to_string(),
+    };
+    let host = host.as_str();
+
     let addr = host.strip_prefix("tcp://")
         .or_else(|| host.strip_prefix("http://"));
 
@@ -225,3 +465,14 @@ pub async fn grpc_connect(host: &str) -> Result<Channel> {
 
     Ok(ch)
 }
+
+// Returns a `unix://` host string for `path` if the socket exists, so
+// `Containers::autodetect` can tell a present-but-unconfigured runtime
+// apart from one that simply isn't installed on this host.
+fn probe_socket(path: &str) -> Option<String> {
+    if Path::new(path).exists() {
+        Some(format!("unix://{path}"))
+    } else {
+        None
+    }
+}
@@ -1,8 +1,8 @@
 
-use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{Result};
+use async_trait::async_trait;
 use log::*;
 use podman_api::{Podman};
 use podman_api::opts::{EventsOpts, ContainerListOpts};
@@ -10,6 +10,7 @@ use podman_api::models::Event;
 use futures::stream::StreamExt;
 
 use super::{ContainerEventsPtr, ContainerInfo};
+use super::runtime::ContainerRuntime;
 use crate::scoped_path::*;
 use crate::cloud_metadata::CloudMetadata;
 
@@ -18,12 +19,22 @@ const EVENT_TYPE_CONTAINER: &str = "container";
 
 pub struct PodmanTracker {
     podman: Podman,
+    cloud_meta: CloudMetadata,
 }
 
 impl PodmanTracker {
-    pub async fn connect(host: &str) -> Result<Self> {
+    pub async fn connect(host: &str, cloud_meta: CloudMetadata) -> Result<Self> {
         info!("Connecting to {host}");
-        let podman = Podman::new(host)?;
+
+        // `podman://` is accepted as an alias for `unix://` so an explicit
+        // `podman_host` config value can name the runtime it's dialing;
+        // `podman_api` itself only understands `unix://`/`tcp://`.
+        let host = match host.strip_prefix("podman://") {
+            Some(path) => format!("unix://{path}"),
+            None => host.to_string(),
+        };
+
+        let podman = Podman::new(host.as_str())?;
 
         let mut quiet = false;
         loop {
@@ -47,63 +58,11 @@ impl PodmanTracker {
 
         Ok(Self{
             podman,
-        })
-    }
-
-    pub async fn track(self, cloud_meta: CloudMetadata, events: ContainerEventsPtr) -> Result<()> {
-        let tracker = Arc::new(Tracker{
-            podman: self.podman,
             cloud_meta,
-            events,
-        });
-
-        let events_task = {
-            let tracker = tracker.clone();
-            tokio::task::spawn(async move {
-                tracker.stream_events().await;
-            })
-        };
-
-        // Load already running containers
-        tracker.load_running().await?;
-
-        _ = events_task.await;
-
-        Ok(())
-    }
-
-}
-
-struct Tracker {
-    podman: Podman,
-    cloud_meta: CloudMetadata,
-    events: ContainerEventsPtr,
-}
-
-impl Tracker {
-    async fn stream_events(&self) {
-        let filter = ("event".to_string(), vec!["start".to_string(), "died".to_string()]);
-
-        let opts = EventsOpts::builder()
-            .stream(true)
-            .filters([filter])
-            .build();
-
-        let mut stream = self.podman.events(&opts);
-
-        while let Some(evt) = stream.next().await {
-            debug!("Podman Event: {evt:?}");
-
-            match evt {
-                Ok(msg) => self.process_event(msg).await,
-                Err(err) => error!("Failed to receive podman event: {err}"),
-            }
-        }
-
-        debug!("Event streaming done");
+        })
     }
 
-    async fn process_event(&self, msg: Event) {
+    async fn process_event(&mut self, msg: Event, events: &ContainerEventsPtr) {
         if msg.typ == EVENT_TYPE_CONTAINER {
             let id = msg.actor.id;
 
@@ -111,52 +70,24 @@ impl Tracker {
                 "start" => {
                     debug!("Container {id} started");
 
-                    match self.inspect_container(&id).await {
+                    match self.fetch_container(&id).await {
                         Ok(info) => {
-                            self.events.container_started(id, info).await;
+                            events.container_started(id, info).await;
                         },
                         Err(err) => {
                             error!("Failed to inspect container(id={id}): {err}");
-                            return;
                         }
                     }
                 },
                 "died" => {
-                    self.events.container_stopped(id, msg.time.into()).await;
+                    events.container_stopped(id, msg.time.into()).await;
                 },
                 _ => (),
             }
         }
     }
 
-    async fn load_running(&self) -> Result<()> {
-        let opts = ContainerListOpts::builder()
-            .build();
-
-        let conts = self.podman.containers().list(&opts).await?;
-
-        for c in conts {
-            if c.id.is_none() {
-                continue;
-            }
-            let id = c.id.unwrap();
-
-            match self.inspect_container(&id).await {
-                Ok(info) => {
-                    debug!("Container {id}: {info:?}");
-                    self.events.container_started(id, info).await;
-                },
-                Err(err) => {
-                    error!("Podman inspect_container({id}): {err}");
-                    continue;
-                }
-            }
-        }
-
-        Ok(())
-    }
-
-    async fn inspect_container(&self, id: &str) -> Result<ContainerInfo> {
+    async fn fetch_container(&self, id: &str) -> Result<ContainerInfo> {
         let cont_resp = self.podman.containers()
             .get(id)
             .inspect()
@@ -218,4 +149,58 @@ impl Tracker {
             labels: self.cloud_meta.container_labels(id),
         })
     }
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl ContainerRuntime for PodmanTracker {
+    async fn load_running(&mut self, events: &ContainerEventsPtr) -> Result<()> {
+        let opts = ContainerListOpts::builder()
+            .build();
+
+        let conts = self.podman.containers().list(&opts).await?;
+
+        for c in conts {
+            if c.id.is_none() {
+                continue;
+            }
+            let id = c.id.unwrap();
+
+            match self.fetch_container(&id).await {
+                Ok(info) => {
+                    debug!("Container {id}: {info:?}");
+                    events.container_started(id, info).await;
+                },
+                Err(err) => {
+                    error!("Podman inspect_container({id}): {err}");
+                    continue;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn stream_events(&mut self, events: &ContainerEventsPtr) -> Result<()> {
+        let filter = ("event".to_string(), vec!["start".to_string(), "died".to_string()]);
+
+        let opts = EventsOpts::builder()
+            .stream(true)
+            .filters([filter])
+            .build();
+
+        let mut stream = self.podman.events(&opts);
+
+        while let Some(evt) = stream.next().await {
+            debug!("Podman Event: {evt:?}");
+
+            let msg = evt?;
+            self.process_event(msg, events).await;
+        }
+
+        Ok(())
+    }
+
+    async fn inspect_container(&mut self, id: &str) -> Result<Option<ContainerInfo>> {
+        Ok(Some(self.fetch_container(id).await?))
+    }
+}
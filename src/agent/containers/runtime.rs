@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::*;
+
+use super::{ContainerEventsPtr, ContainerInfo};
+use crate::backoff::DecorrelatedJitter;
+
+// Backoff between re-subscribing to a runtime's event stream after a pass
+// ends or errors out (the daemon restarting, a connection reset, etc.) --
+// the same policy every tracker used to hand-roll on its own.
+const RECONNECT_BACKOFF_BASE: Duration = Duration::from_millis(500);
+const RECONNECT_BACKOFF_CAP: Duration = Duration::from_secs(30);
+
+// Common shape behind every container runtime tracker (Docker, containerd,
+// Podman, ...): seed from whatever's already running, then forever
+// alternate between streaming lifecycle events and recovering from a
+// dropped stream. Connecting to the runtime itself stays outside this
+// trait -- each one takes different extra config (TLS opts, snapshotter
+// roots, cloud metadata, ...) -- but everything downstream of a live
+// connection is identical, so `track()` below owns it once instead of each
+// tracker hand-rolling its own copy of the reconnect loop.
+#[async_trait]
+pub trait ContainerRuntime: Sized + Send + 'static {
+    // Seeds `events` with whatever the runtime reports running right now.
+    // Called exactly once, before the first `stream_events` pass.
+    async fn load_running(&mut self, events: &ContainerEventsPtr) -> Result<()>;
+
+    // Runs one event-subscription pass, processing events against `events`
+    // as they arrive, and returns once the stream ends or errors so that
+    // `track()` can reconnect. Implementations that can't tell whether
+    // they missed a start/stop while the stream was down (i.e. can't
+    // replay from a cursor like Docker's `since`) should reconcile their
+    // own tracked state against whatever's actually running before
+    // resubscribing.
+    async fn stream_events(&mut self, events: &ContainerEventsPtr) -> Result<()>;
+
+    // Fetches a single container's metadata, for trackers that only learn
+    // an id from an event or a bare listing and need the full picture.
+    async fn inspect_container(&mut self, id: &str) -> Result<Option<ContainerInfo>>;
+
+    async fn track(mut self, events: ContainerEventsPtr) -> Result<()> {
+        self.load_running(&events).await?;
+
+        let mut backoff = DecorrelatedJitter::new(RECONNECT_BACKOFF_BASE, RECONNECT_BACKOFF_CAP);
+
+        loop {
+            match self.stream_events(&events).await {
+                Ok(()) => {
+                    debug!("Event streaming ended, reconnecting");
+                    backoff.reset();
+                },
+                Err(err) => error!("Event stream error, reconnecting: {err}"),
+            }
+
+            tokio::time::sleep(backoff.next_delay()).await;
+        }
+    }
+}
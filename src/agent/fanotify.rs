@@ -1,20 +1,156 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::CStr;
 use std::io::ErrorKind;
-use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
 use std::os::unix::ffi::OsStringExt;
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Result};
 use fanotify::low_level::{
-    AT_FDCWD, FAN_MARK_ADD, FAN_MARK_FILESYSTEM, FAN_MARK_REMOVE, FAN_NONBLOCK, FAN_OPEN, O_RDONLY,
+    AT_FDCWD, FAN_ACCESS, FAN_ACCESS_PERM, FAN_ALLOW, FAN_CLASS_PERM, FAN_CLOSE_NOWRITE,
+    FAN_CLOSE_WRITE, FAN_DENY, FAN_MARK_ADD, FAN_MARK_FILESYSTEM, FAN_MARK_MOUNT,
+    FAN_MARK_ONLYDIR, FAN_MARK_REMOVE, FAN_MODIFY, FAN_NONBLOCK, FAN_OPEN, FAN_OPEN_EXEC,
+    FAN_OPEN_PERM, FAN_Q_OVERFLOW, O_RDONLY,
 };
 use log::*;
 use tokio::io::unix::AsyncFd;
 use tokio::io::Interest;
 
+// Observe opens as well as execs so that we learn about a package being
+// used even when its files are only ever exec'd (e.g. a statically linked
+// binary) and not separately opened for read. The default mask passed to
+// `add_open_mark` by callers that only care about opens.
+pub const WATCH_MASK: u64 = FAN_OPEN | FAN_OPEN_EXEC;
+
+// Not yet wrapped by the `fanotify` crate: asks the kernel to report each
+// event's identity as an `fsid` + opaque file handle (FAN_REPORT_FID)
+// instead of (or in addition to) an open fd on the watched file, and,
+// when combined, the parent directory's fid plus the child's name
+// (FAN_REPORT_DFID_NAME). Used by `Fanotify::new_fid`.
+const FAN_REPORT_FID: u32 = 0x0000_0200;
+const FAN_REPORT_DFID_NAME: u32 = 0x0000_0800;
+
+// `struct fanotify_event_metadata` is a fixed 24 bytes on every kernel that
+// supports fanotify; trailing info records (if any) start at `metadata_len`
+// (normally also 24) and run to `event_len`.
+const METADATA_LEN: usize = 24;
+
+const FAN_EVENT_INFO_TYPE_FID: u8 = 1;
+const FAN_EVENT_INFO_TYPE_DFID_NAME: u8 = 2;
+const FAN_EVENT_INFO_TYPE_DFID: u8 = 3;
+
+// Returns true if the calling process has CAP_SYS_ADMIN, which fanotify
+// (in FAN_MARK_FILESYSTEM mode) requires. Used to decide whether to even
+// attempt to set up fanotify before falling back to inotify.
+pub fn has_cap_sys_admin() -> bool {
+    match caps::has_cap(None, caps::CapSet::Effective, caps::Capability::CAP_SYS_ADMIN) {
+        Ok(has) => has,
+        Err(err) => {
+            warn!("Failed to query CAP_SYS_ADMIN, assuming it's missing: {err}");
+            false
+        }
+    }
+}
+
+// What to tell the kernel to do with the process that's blocked on a
+// permission event (FAN_OPEN_PERM / FAN_ACCESS_PERM).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Response {
+    Allow,
+    Deny,
+}
+
+impl Response {
+    fn as_raw(self) -> u32 {
+        match self {
+            Response::Allow => FAN_ALLOW,
+            Response::Deny => FAN_DENY,
+        }
+    }
+}
+
+// Mirrors the kernel's `struct fanotify_response`, written back to the
+// fanotify fd to unblock a process waiting on a permission event.
+#[repr(C)]
+struct RawResponse {
+    fd: RawFd,
+    response: u32,
+}
+
+// An opaque file identifier handed back by a `FAN_REPORT_FID` event in
+// place of an open fd -- resolve it to a path/fd on demand via `open_at`
+// rather than paying for an fd per event up front.
+#[derive(Clone, Debug)]
+pub struct FileHandle {
+    handle_type: i32,
+    bytes: Vec<u8>,
+}
+
+impl FileHandle {
+    // Resolves this handle back to an open file via `open_by_handle_at`.
+    // `mount_fd` must be an fd open on (or under) the filesystem the handle
+    // was issued from -- typically the mount fanotify was marked on.
+    pub fn open_at(&self, mount_fd: RawFd, flags: i32) -> Result<std::fs::File> {
+        #[repr(C)]
+        struct RawFileHandle {
+            handle_bytes: u32,
+            handle_type: i32,
+        }
+
+        let header = RawFileHandle {
+            handle_bytes: self.bytes.len() as u32,
+            handle_type: self.handle_type,
+        };
+
+        let mut buf = Vec::with_capacity(std::mem::size_of::<RawFileHandle>() + self.bytes.len());
+        buf.extend_from_slice(unsafe {
+            std::slice::from_raw_parts(&header as *const RawFileHandle as *const u8, std::mem::size_of::<RawFileHandle>())
+        });
+        buf.extend_from_slice(&self.bytes);
+
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_open_by_handle_at,
+                mount_fd as libc::c_int,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                flags as libc::c_int,
+            )
+        };
+
+        if ret < 0 {
+            return Err(anyhow!("open_by_handle_at(): {}", std::io::Error::last_os_error()));
+        }
+
+        let fd = unsafe { OwnedFd::from_raw_fd(ret as RawFd) };
+        Ok(fd.into())
+    }
+}
+
+// File identity/metadata as of the moment an `Event` fired, from `Event::stat`.
+#[derive(Clone, Copy, Debug)]
+pub struct Stat {
+    pub ino: u64,
+    pub mnt_id: u64,
+    pub size: u64,
+    pub mtime: SystemTime,
+}
+
 pub struct Event {
     pub mask: u64,
     pub fd: Option<OwnedFd>,
     pub pid: i32,
+    // Set instead of `fd` under FAN_REPORT_FID: the filesystem id and an
+    // opaque handle identifying the file, resolvable via `FileHandle::open_at`.
+    pub fsid: Option<[i32; 2]>,
+    pub file_handle: Option<FileHandle>,
+    // The fanotify group fd this event came from, so a permission event can
+    // be responded to (or defaulted to `Allow` on drop) without the caller
+    // having to thread the `Fanotify` handle back in.
+    fanotify_fd: RawFd,
+    responded: Cell<bool>,
 }
 
 impl Event {
@@ -33,51 +169,279 @@ impl Event {
             None => Err(anyhow!("No open file descriptor")),
         }
     }
+
+    // True for events raised under a permission-class mark (FAN_OPEN_PERM /
+    // FAN_ACCESS_PERM), where the faulting process stays blocked until
+    // `respond()` is called (or this `Event` is dropped, which defaults to
+    // `Allow`).
+    pub fn requires_response(&self) -> bool {
+        self.mask & (FAN_OPEN_PERM | FAN_ACCESS_PERM) != 0
+    }
+
+    // The file was opened (or exec'd) -- the only kind of event this
+    // tracker used to ever see.
+    pub fn is_open(&self) -> bool {
+        self.mask & (FAN_OPEN | FAN_OPEN_EXEC | FAN_OPEN_PERM) != 0
+    }
+
+    // The file's content was modified, so any cached hash/scan of it is
+    // stale and should be redone rather than trusted as-is.
+    pub fn is_modify(&self) -> bool {
+        self.mask & FAN_MODIFY != 0
+    }
+
+    // A writable fd on the file was closed -- the point at which a write
+    // actually lands, for callers that want to re-hash on close rather
+    // than on every individual FAN_MODIFY.
+    pub fn is_close_write(&self) -> bool {
+        self.mask & FAN_CLOSE_WRITE != 0
+    }
+
+    // A read-only fd on the file was closed.
+    pub fn is_close_nowrite(&self) -> bool {
+        self.mask & FAN_CLOSE_NOWRITE != 0
+    }
+
+    // The file was read (FAN_ACCESS / FAN_ACCESS_PERM).
+    pub fn is_access(&self) -> bool {
+        self.mask & (FAN_ACCESS | FAN_ACCESS_PERM) != 0
+    }
+
+    // statx()s the event's own fd (AT_EMPTY_PATH, no second path lookup),
+    // so a caller can dedupe by stable inode identity and skip a re-scan
+    // when mtime/size haven't changed since the last one -- one syscall,
+    // and no TOCTOU re-open of the path.
+    pub fn stat(&self) -> Result<Stat> {
+        let fd = self.fd.as_ref()
+            .ok_or_else(|| anyhow!("event has no file descriptor to stat"))?
+            .as_raw_fd();
+
+        let empty_path = CStr::from_bytes_with_nul(b"\0").unwrap();
+        let mask = libc::STATX_INO | libc::STATX_SIZE | libc::STATX_MTIME | libc::STATX_MNT_ID;
+
+        let mut statx: libc::statx = unsafe { std::mem::zeroed() };
+        let ret = unsafe {
+            libc::statx(fd, empty_path.as_ptr(), libc::AT_EMPTY_PATH, mask, &mut statx)
+        };
+
+        if ret != 0 {
+            return Err(anyhow!("statx(): {}", std::io::Error::last_os_error()));
+        }
+
+        Ok(Stat {
+            ino: statx.stx_ino,
+            mnt_id: statx.stx_mnt_id,
+            size: statx.stx_size,
+            mtime: UNIX_EPOCH + Duration::new(statx.stx_mtime.tv_sec as u64, statx.stx_mtime.tv_nsec),
+        })
+    }
+
+    // Unblocks the process that triggered a permission event. A no-op for
+    // non-permission events. Must be called promptly for permission events:
+    // the faulting process stays blocked until this (or the `Allow` default
+    // on drop) runs, and a caller that opens the same file before responding
+    // will deadlock itself.
+    pub fn respond(&self, response: Response) -> Result<()> {
+        if !self.requires_response() || self.responded.get() {
+            return Ok(());
+        }
+
+        let fd = self.fd.as_ref()
+            .ok_or_else(|| anyhow!("permission event has no file descriptor to respond on"))?
+            .as_raw_fd();
+
+        let raw = RawResponse { fd, response: response.as_raw() };
+        let ret = unsafe {
+            libc::write(
+                self.fanotify_fd,
+                &raw as *const RawResponse as *const libc::c_void,
+                std::mem::size_of::<RawResponse>(),
+            )
+        };
+
+        if ret < 0 {
+            return Err(anyhow!("write(fanotify_response): {}", std::io::Error::last_os_error()));
+        }
+
+        self.responded.set(true);
+        Ok(())
+    }
+}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        if self.requires_response() && !self.responded.get() {
+            if let Err(err) = self.respond(Response::Allow) {
+                error!("Failed to default-allow unresponded fanotify permission event: {err}");
+            }
+        }
+    }
+}
+
+// Whether events are reported with an open fd on the watched file (the
+// default) or, under FAN_REPORT_FID, with an fsid + file handle instead --
+// which needs its own read-and-parse path since `fanotify::low_level`
+// doesn't know about the trailing info records that mode adds.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ReportMode {
+    Fd,
+    Fid,
 }
 
 pub struct Fanotify {
     fd: AsyncFd<OwnedFd>,
+    // Marks we've applied so far, keyed by path with the mask each was
+    // applied with, so that on FAN_Q_OVERFLOW we can re-apply them after a
+    // re-scan instead of silently missing events for the gap.
+    marks: Mutex<HashMap<PathBuf, MarkSpec>>,
+    mode: ReportMode,
+}
+
+// How broadly a mark applies. Filesystem-wide is the default fanotify
+// scope (requires FAN_MARK_FILESYSTEM and CAP_SYS_ADMIN the same as
+// before); Mount and Inode narrow that down so the agent doesn't have to
+// watch (and filter) an entire backing filesystem just to care about one
+// mount or directory subtree, e.g. `/usr` or a container's rootfs mount.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkScope {
+    Filesystem,
+    Mount,
+    Inode,
+}
+
+impl MarkScope {
+    fn as_raw(self) -> u64 {
+        match self {
+            MarkScope::Filesystem => FAN_MARK_FILESYSTEM,
+            MarkScope::Mount => FAN_MARK_MOUNT,
+            // Inode marks need no scope flag -- fanotify_mark()'s default.
+            MarkScope::Inode => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct MarkSpec {
+    mask: u64,
+    scope: MarkScope,
+    onlydir: bool,
+}
+
+impl MarkSpec {
+    fn flags(&self, base: u64) -> u64 {
+        let mut flags = base | self.scope.as_raw();
+        if self.onlydir {
+            flags |= FAN_MARK_ONLYDIR;
+        }
+        flags
+    }
 }
 
 impl Fanotify {
     pub fn new() -> Result<Self> {
-        let fd = fanotify::low_level::fanotify_init(FAN_NONBLOCK, O_RDONLY)
+        Self::init(FAN_NONBLOCK, ReportMode::Fd)
+    }
+
+    // Like `new()`, but initializes the group with FAN_CLASS_PERM so marks
+    // applied with a permission bit set (FAN_OPEN_PERM / FAN_ACCESS_PERM)
+    // block the calling process until this fanotify's events are responded
+    // to via `Event::respond`. Unresponded permission events default to
+    // `Response::Allow` when dropped, so a caller that forgets to respond
+    // fails open rather than wedging the blocked process forever.
+    pub fn new_perm() -> Result<Self> {
+        Self::init(FAN_CLASS_PERM | FAN_NONBLOCK, ReportMode::Fd)
+    }
+
+    // Like `new()`, but events carry an fsid + file handle (`Event::fsid` /
+    // `Event::file_handle`) instead of an open fd, resolvable on demand via
+    // `FileHandle::open_at`. Dramatically lighter than per-event fds when
+    // watching a whole filesystem, and doesn't fail under fd pressure or
+    // once the opener has already closed the file. `with_name` additionally
+    // requests the parent directory's fid and the child's filename
+    // (FAN_REPORT_DFID_NAME) rather than just the file's own fid.
+    pub fn new_fid(with_name: bool) -> Result<Self> {
+        let mut flags = FAN_NONBLOCK | FAN_REPORT_FID;
+        if with_name {
+            flags |= FAN_REPORT_DFID_NAME;
+        }
+
+        Self::init(flags, ReportMode::Fid)
+    }
+
+    fn init(flags: u32, mode: ReportMode) -> Result<Self> {
+        let fd = fanotify::low_level::fanotify_init(flags, O_RDONLY)
             .map_err(|err| anyhow!("fanotify_init(): {err}"))?;
 
         let owned = unsafe { OwnedFd::from_raw_fd(fd) };
         Ok(Self {
             fd: AsyncFd::with_interest(owned, Interest::READABLE)?,
+            marks: Mutex::new(HashMap::new()),
+            mode,
         })
     }
 
-    pub fn add_open_mark(&self, path: PathBuf) -> Result<()> {
-        trace!("fanotify add mark: {}", path.display());
+    pub fn add_open_mark(&self, path: PathBuf, mask: u64, scope: MarkScope, onlydir: bool) -> Result<()> {
+        trace!("fanotify add mark: {} ({mask:#x}, {scope:?}, onlydir={onlydir})", path.display());
+        let spec = MarkSpec { mask, scope, onlydir };
+
         fanotify::low_level::fanotify_mark(
             self.fd.as_raw_fd(),
-            FAN_MARK_ADD | FAN_MARK_FILESYSTEM,
-            FAN_OPEN,
+            spec.flags(FAN_MARK_ADD),
+            mask,
             AT_FDCWD,
-            path.into_os_string().into_vec(),
+            path.clone().into_os_string().into_vec(),
         )
         .map_err(|err| anyhow!("fanotify_mark(add): {err}"))?;
 
+        self.marks.lock().unwrap().insert(path, spec);
+
         Ok(())
     }
 
-    pub fn remove_open_mark(&self, path: PathBuf) -> Result<()> {
+    pub fn remove_open_mark(&self, path: PathBuf, mask: u64, scope: MarkScope, onlydir: bool) -> Result<()> {
+        let spec = MarkSpec { mask, scope, onlydir };
+
         fanotify::low_level::fanotify_mark(
             self.fd.as_raw_fd(),
-            FAN_MARK_REMOVE | FAN_MARK_FILESYSTEM,
-            FAN_OPEN,
+            spec.flags(FAN_MARK_REMOVE),
+            mask,
             AT_FDCWD,
-            path.into_os_string().into_vec(),
+            path.clone().into_os_string().into_vec(),
         )
         .map_err(|err| anyhow!("fanotify_mark(remove): {err}"))?;
 
+        self.marks.lock().unwrap().remove(&path);
+
         Ok(())
     }
 
+    // Re-applies every currently-tracked mark. Used to recover from a
+    // FAN_Q_OVERFLOW, since an overflow can coincide with a remount that
+    // drops marks on the affected filesystem.
+    fn remark_all(&self) {
+        let marks: Vec<(PathBuf, MarkSpec)> = self.marks.lock().unwrap()
+            .iter()
+            .map(|(path, spec)| (path.clone(), *spec))
+            .collect();
+
+        for (path, spec) in marks {
+            if let Err(err) = fanotify::low_level::fanotify_mark(
+                self.fd.as_raw_fd(),
+                spec.flags(FAN_MARK_ADD),
+                spec.mask,
+                AT_FDCWD,
+                path.clone().into_os_string().into_vec(),
+            ) {
+                error!("fanotify_mark(remark {}): {err}", path.display());
+            }
+        }
+    }
+
     pub async fn next(&self) -> Result<Vec<Event>> {
+        if self.mode == ReportMode::Fid {
+            return self.next_fid().await;
+        }
+
         loop {
             let mut guard = self.fd.readable().await?;
 
@@ -86,14 +450,82 @@ impl Fanotify {
 
             match items_res {
                 Ok(Ok(items)) => {
-                    return Ok(items
+                    let mut overflowed = false;
+
+                    let fanotify_fd = self.fd.as_raw_fd();
+                    let events = items
                         .iter()
+                        .filter(|item| {
+                            if item.mask & FAN_Q_OVERFLOW != 0 {
+                                overflowed = true;
+                                false
+                            } else {
+                                true
+                            }
+                        })
                         .map(|item| Event {
                             mask: item.mask,
                             fd: owned_from_raw_fd(item.fd),
                             pid: item.pid,
+                            fsid: None,
+                            file_handle: None,
+                            fanotify_fd,
+                            responded: Cell::new(false),
                         })
-                        .collect())
+                        .collect();
+
+                    if overflowed {
+                        // The kernel dropped events for us; our marks may also
+                        // have been dropped (e.g. a remount). Re-apply them so
+                        // we don't silently stop watching.
+                        warn!("fanotify event queue overflowed, some opens were missed; re-applying marks");
+                        self.remark_all();
+                    }
+
+                    return Ok(events);
+                }
+                Ok(Err(err)) => match err.kind() {
+                    ErrorKind::WouldBlock => continue,
+                    _ => return Err(err.into()),
+                },
+                Err(_) => continue,
+            }
+        }
+    }
+
+    // `fanotify::low_level::fanotify_read` only decodes the fixed-size
+    // `fanotify_event_metadata` header; under FAN_REPORT_FID each event also
+    // carries one or more trailing info records (fsid + file handle, and
+    // optionally a name) that it doesn't know how to parse, so read and
+    // walk the raw buffer ourselves.
+    async fn next_fid(&self) -> Result<Vec<Event>> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+
+            let read_res = guard.try_io(|inner| {
+                let mut buf = [0u8; 4096];
+                let n = unsafe {
+                    libc::read(inner.get_ref().as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+                };
+
+                if n < 0 {
+                    Err(std::io::Error::last_os_error())
+                } else {
+                    Ok(buf[..n as usize].to_vec())
+                }
+            });
+
+            match read_res {
+                Ok(Ok(buf)) => {
+                    let fanotify_fd = self.fd.as_raw_fd();
+                    let (events, overflowed) = parse_fid_events(&buf, fanotify_fd);
+
+                    if overflowed {
+                        warn!("fanotify event queue overflowed, some opens were missed; re-applying marks");
+                        self.remark_all();
+                    }
+
+                    return Ok(events);
                 }
                 Ok(Err(err)) => match err.kind() {
                     ErrorKind::WouldBlock => continue,
@@ -105,6 +537,92 @@ impl Fanotify {
     }
 }
 
+// `struct fanotify_event_metadata`, always 24 bytes: event_len(4) +
+// vers(1) + reserved(1) + metadata_len(2) + mask(8) + fd(4) + pid(4).
+fn parse_fid_events(buf: &[u8], fanotify_fd: RawFd) -> (Vec<Event>, bool) {
+    let mut events = Vec::new();
+    let mut overflowed = false;
+    let mut offset = 0usize;
+
+    while offset + METADATA_LEN <= buf.len() {
+        let event_len = u32::from_ne_bytes(buf[offset..offset + 4].try_into().unwrap()) as usize;
+        let metadata_len = u16::from_ne_bytes(buf[offset + 6..offset + 8].try_into().unwrap()) as usize;
+        let mask = u64::from_ne_bytes(buf[offset + 8..offset + 16].try_into().unwrap());
+        let fd = i32::from_ne_bytes(buf[offset + 16..offset + 20].try_into().unwrap());
+        let pid = i32::from_ne_bytes(buf[offset + 20..offset + 24].try_into().unwrap());
+
+        if event_len == 0 || offset + event_len > buf.len() || metadata_len > event_len {
+            break;
+        }
+
+        if mask & FAN_Q_OVERFLOW != 0 {
+            overflowed = true;
+            offset += event_len;
+            continue;
+        }
+
+        let (fsid, file_handle) = parse_fid_info(&buf[offset + metadata_len..offset + event_len]);
+
+        events.push(Event {
+            mask,
+            fd: owned_from_raw_fd(fd),
+            pid,
+            fsid,
+            file_handle,
+            fanotify_fd,
+            responded: Cell::new(false),
+        });
+
+        offset += event_len;
+    }
+
+    (events, overflowed)
+}
+
+// Walks the info records trailing an event's fixed metadata, looking for
+// an FID/DFID/DFID_NAME record (an fsid followed by a variable-length
+// `struct file_handle`). Ignores record types it doesn't recognize (e.g.
+// FAN_EVENT_INFO_TYPE_PIDFD) rather than failing the whole event.
+fn parse_fid_info(info: &[u8]) -> (Option<[i32; 2]>, Option<FileHandle>) {
+    let mut offset = 0usize;
+
+    while offset + 4 <= info.len() {
+        let info_type = info[offset];
+        let len = u16::from_ne_bytes(info[offset + 2..offset + 4].try_into().unwrap()) as usize;
+
+        if len == 0 || offset + len > info.len() {
+            break;
+        }
+
+        let is_fid = matches!(
+            info_type,
+            FAN_EVENT_INFO_TYPE_FID | FAN_EVENT_INFO_TYPE_DFID | FAN_EVENT_INFO_TYPE_DFID_NAME
+        );
+
+        if is_fid && offset + 4 + 8 + 8 <= offset + len {
+            let body = offset + 4;
+            let fsid = [
+                i32::from_ne_bytes(info[body..body + 4].try_into().unwrap()),
+                i32::from_ne_bytes(info[body + 4..body + 8].try_into().unwrap()),
+            ];
+
+            let handle_off = body + 8;
+            let handle_bytes = u32::from_ne_bytes(info[handle_off..handle_off + 4].try_into().unwrap()) as usize;
+            let handle_type = i32::from_ne_bytes(info[handle_off + 4..handle_off + 8].try_into().unwrap());
+            let data_start = handle_off + 8;
+
+            if data_start + handle_bytes <= offset + len {
+                let bytes = info[data_start..data_start + handle_bytes].to_vec();
+                return (Some(fsid), Some(FileHandle { handle_type, bytes }));
+            }
+        }
+
+        offset += len;
+    }
+
+    (None, None)
+}
+
 fn owned_from_raw_fd(fd: i32) -> Option<OwnedFd> {
     if fd < 0 {
         None
@@ -112,3 +630,113 @@ fn owned_from_raw_fd(fd: i32) -> Option<OwnedFd> {
         Some(unsafe { OwnedFd::from_raw_fd(fd) })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+
+    use super::*;
+
+    // Builds a single FID/DFID/DFID_NAME info record: type(1) + pad(1) +
+    // len(2) + fsid(8) + handle_bytes(4) + handle_type(4) + handle data.
+    fn fid_info_record(fsid: [i32; 2], handle_type: i32, handle: &[u8]) -> Vec<u8> {
+        let len = 4 + 8 + 4 + 4 + handle.len();
+
+        let mut rec = Vec::new();
+        rec.push(FAN_EVENT_INFO_TYPE_FID);
+        rec.push(0);
+        rec.extend((len as u16).to_ne_bytes());
+        rec.extend(fsid[0].to_ne_bytes());
+        rec.extend(fsid[1].to_ne_bytes());
+        rec.extend((handle.len() as u32).to_ne_bytes());
+        rec.extend(handle_type.to_ne_bytes());
+        rec.extend_from_slice(handle);
+        rec
+    }
+
+    // Builds a full `fanotify_event_metadata` header plus a single trailing
+    // FID info record, matching what the kernel writes under
+    // FAN_REPORT_FID, so parse_fid_events can be exercised without a live
+    // fanotify fd.
+    fn fid_event_bytes(mask: u64, fsid: [i32; 2], handle_type: i32, handle: &[u8]) -> Vec<u8> {
+        let record = fid_info_record(fsid, handle_type, handle);
+        let metadata_len = METADATA_LEN as u16;
+        let event_len = (METADATA_LEN + record.len()) as u32;
+
+        let mut buf = Vec::new();
+        buf.extend(event_len.to_ne_bytes());
+        buf.push(0); // vers
+        buf.push(0); // reserved
+        buf.extend(metadata_len.to_ne_bytes());
+        buf.extend(mask.to_ne_bytes());
+        buf.extend((-1i32).to_ne_bytes()); // fd: no open fd under FAN_REPORT_FID
+        buf.extend(0i32.to_ne_bytes()); // pid
+        buf.extend(record);
+
+        buf
+    }
+
+    #[test]
+    fn test_parse_fid_events_decodes_fsid_and_file_handle() {
+        let buf = fid_event_bytes(FAN_OPEN, [1, 2], 0x81, &[9, 9, 9, 9]);
+
+        let (events, overflowed) = parse_fid_events(&buf, 7);
+
+        assert!(!overflowed);
+        assert!(events.len() == 1);
+        assert!(events[0].fsid == Some([1, 2]));
+
+        let handle = events[0].file_handle.as_ref().unwrap();
+        assert!(handle.handle_type == 0x81);
+        assert!(handle.bytes == vec![9, 9, 9, 9]);
+    }
+
+    #[test]
+    fn test_parse_fid_events_truncated_buffer_yields_no_events() {
+        let mut buf = fid_event_bytes(FAN_OPEN, [1, 2], 0x81, &[9, 9, 9, 9]);
+        buf.truncate(buf.len() - 4);
+
+        let (events, overflowed) = parse_fid_events(&buf, 7);
+
+        assert!(events.is_empty());
+        assert!(!overflowed);
+    }
+
+    #[test]
+    fn test_parse_fid_events_rejects_metadata_len_exceeding_event_len() {
+        let mut buf = fid_event_bytes(FAN_OPEN, [1, 2], 0x81, &[9, 9, 9, 9]);
+        let event_len = u32::from_ne_bytes(buf[0..4].try_into().unwrap());
+
+        // Corrupt metadata_len (offset 6..8) to claim more than event_len;
+        // this used to panic on the `buf[offset + metadata_len..]` slice.
+        buf[6..8].copy_from_slice(&((event_len + 1) as u16).to_ne_bytes());
+
+        let (events, overflowed) = parse_fid_events(&buf, 7);
+
+        assert!(events.is_empty());
+        assert!(!overflowed);
+    }
+
+    #[test]
+    fn test_parse_fid_info_zero_length_record_does_not_panic() {
+        let info = [FAN_EVENT_INFO_TYPE_FID, 0, 0, 0];
+
+        let (fsid, handle) = parse_fid_info(&info);
+
+        assert!(fsid.is_none());
+        assert!(handle.is_none());
+    }
+
+    #[test]
+    fn test_parse_fid_info_skips_unrecognized_record_to_reach_the_fid() {
+        // An 8-byte record of an unrecognized type, followed by a real FID
+        // record; parse_fid_info should skip the first and find the second.
+        let mut info = vec![99u8, 0, 8, 0, 0, 0, 0, 0];
+        info.extend(fid_info_record([3, 4], 0x42, &[1, 2, 3]));
+
+        let (fsid, handle) = parse_fid_info(&info);
+
+        assert!(fsid == Some([3, 4]));
+        assert!(handle.unwrap().handle_type == 0x42);
+    }
+}
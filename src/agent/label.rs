@@ -17,3 +17,12 @@ pub const LABEL_CLOUD_PROJECT_ID: &str = "cloud:project-id";
 //   kube:<resource>:labels:<label> (e.g. kube:pod:labels:app.kubernetes.io/managed-by)
 pub const LABEL_KUBE_POD_NAME: &str = "kube:pod:name";
 pub const LABEL_KUBE_NAMESPACE_NAME: &str = "kube:namespace:name";
+pub const LABEL_KUBE_NODE_NAME: &str = "kube:node:name";
+pub const LABEL_KUBE_CLUSTER_ID: &str = "kube:cluster:id";
+// The top-level controller a pod belongs to (e.g. a Deployment or
+// StatefulSet), resolved by walking up the `ownerReferences` chain past
+// any intermediate ReplicaSet/Job -- not just the pod's immediate owner.
+pub const LABEL_KUBE_WORKLOAD_KIND: &str = "kube:workload:kind";
+pub const LABEL_KUBE_WORKLOAD_NAME: &str = "kube:workload:name";
+pub const LABEL_KUBE_POD_LABEL_PREFIX: &str = "kube:pod:labels:";
+pub const LABEL_KUBE_POD_ANNOTATION_PREFIX: &str = "kube:pod:annotations:";
@@ -1,3 +1,5 @@
+pub mod admin;
+pub mod backoff;
 pub mod chroot_cmd;
 pub mod cloud_metadata;
 pub mod config;
@@ -5,37 +7,49 @@ pub mod containers;
 pub mod fanotify;
 pub mod jitter;
 pub mod label;
+pub mod metrics;
+pub mod open_event_queue;
 pub mod open_monitor;
 pub mod platform;
+pub mod repo;
 pub mod sbom;
 pub mod scoped_path;
 pub mod version;
+pub mod worker;
 pub mod workloads;
 
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::{Duration, Instant, SystemTime};
+use std::time::{Duration, SystemTime};
 
 use anyhow::{anyhow, Result};
 use clap::Parser;
 use log::*;
 use prost_types::Timestamp;
+use secrecy::SecretString;
 use tokio::sync::mpsc::Receiver;
+use tokio::sync::{watch, Mutex};
 use uuid::Uuid;
 
-use config::Config;
+use config::{Config, OpenMonitorBackend};
 use containers::{ContainerInfo, Containers};
-use jitter::JitteredDuration;
 use platform::pb;
 use sbom::Sbom;
 use scoped_path::*;
 use version::VERSION;
+use worker::WorkerManager;
+use workloads::checkpoint::{CheckpointSeed, CheckpointWorker};
 use workloads::host::HostWorkload;
-use workloads::{Event, Workloads};
-
-use crate::open_monitor::{FileOpenMonitorArc, NullOpenMonitor, OpenEvent, OpenMonitor};
-use crate::workloads::track_container_lifecycle;
+use workloads::in_use::PkgsInUseWorker;
+use workloads::report_loop::ReportLoopWorker;
+use workloads::scrub::SbomScrubWorker;
+use workloads::{ContainerLifecycleWorker, Event, Workloads};
+
+use crate::open_monitor::{
+    FileOpenMonitorArc, InotifyMonitor, NullOpenMonitor, OpenEventBarriers, OpenMonitor,
+    PollMonitor,
+};
 
 use crate::cloud_metadata::CloudMetadata;
 
@@ -45,7 +59,16 @@ const TIMESTAMP_INFINITY: Timestamp = Timestamp {
 };
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(300);
-const HEARTBEAT_JITTER: Duration = Duration::from_secs(30);
+
+// Poll interval used when Auto falls all the way through to the polling
+// monitor; an explicit Poll backend uses Config::monitor_backend's interval
+// instead.
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+// Bounds how long shutdown waits for the final flush to reach the server,
+// so a control plane that's unreachable at the exact moment of a SIGTERM
+// can't turn a graceful shutdown into a hung one.
+const SHUTDOWN_FLUSH_DEADLINE: Duration = Duration::from_secs(5);
 
 const MACHINE_ID_PATH: &str = "etc/machine-id";
 
@@ -93,91 +116,185 @@ async fn run(args: &CliArgs) -> Result<()> {
 
     info!("EdgeBit Agent v{VERSION}");
 
+    if let Some(addr) = config.metrics_addr() {
+        tokio::task::spawn(metrics::serve(addr));
+    }
+
     let url = config.edgebit_url();
-    let token = config.edgebit_id();
+    let token: SecretString = config.edgebit_id().into();
     let host_root = RootFsPath::from(config.host_root());
     let machine_id = read_machine_id(&host_root.join(MACHINE_ID_PATH))?;
 
+    let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel::<platform::CommandRequest>(10);
+
     info!("Connecting to EdgeBit at {url}");
-    let mut client =
-        platform::Client::connect(url.try_into()?, token, config.hostname(), machine_id).await?;
+    let client = platform::Client::connect(
+        url.try_into()?,
+        token,
+        config.client_tls(),
+        config.hostname(),
+        machine_id,
+        config.spool_dir(),
+        cmd_tx,
+        config.sbom_compression(),
+    )
+    .await?;
+    let client = Arc::new(Mutex::new(client));
 
     let host_image_id = if config.machine_sbom() {
-        load_sbom(args, config.clone(), &mut client).await?.id()
+        load_sbom(args, config.clone(), &client).await?.id()
     } else {
         Uuid::new_v4().to_string()
     };
 
-    client.reset_workloads().await?;
+    client.lock().await.reset_workloads().await?;
 
-    let cloud_meta = CloudMetadata::load().await;
+    let cloud_meta = CloudMetadata::load(config.cloud_provider()).await;
+
+    let (open_tx, open_rx_raw) = open_event_queue::channel(config.open_event_queue_capacity());
+    let open_barriers = OpenEventBarriers::new();
 
     let (open_mon, open_rx) = if config.pkg_tracking() {
-        let (tx, rx) = tokio::sync::mpsc::channel::<OpenEvent>(1000);
-        let mon: FileOpenMonitorArc = Arc::new(OpenMonitor::start(tx)?);
-        (mon, Some(rx))
+        let mon: FileOpenMonitorArc = match config.monitor_backend() {
+            OpenMonitorBackend::Poll { interval } => {
+                info!("Using the poll-based open monitor (interval {interval:?})");
+                Arc::new(PollMonitor::start(open_tx.clone(), interval))
+            }
+            OpenMonitorBackend::Auto => match OpenMonitor::start(
+                open_tx.clone(),
+                config.open_events_buf_pages(),
+                config.zombie_events_buf_pages(),
+            ) {
+                Ok(mon) => Arc::new(mon),
+                Err(err) => {
+                    warn!("eBPF/fanotify open monitor unavailable ({err}), falling back to inotify");
+                    match InotifyMonitor::start(open_tx.clone()) {
+                        Ok(mon) => Arc::new(mon),
+                        Err(err) => {
+                            warn!("inotify open monitor unavailable ({err}), falling back to polling");
+                            Arc::new(PollMonitor::start(open_tx.clone(), FALLBACK_POLL_INTERVAL))
+                        }
+                    }
+                }
+            },
+        };
+
+        (mon, Some(open_rx_raw))
     } else {
         let mon: FileOpenMonitorArc = Arc::new(NullOpenMonitor);
         (mon, None)
     };
 
     let (cont_tx, cont_rx) = tokio::sync::mpsc::channel(10);
-    let mut containers = Containers::new(config.clone(), cloud_meta.clone(), cont_tx);
-    if let Some(host) = config.docker_host() {
-        containers.track_docker(host);
-    }
+    let mut containers = Containers::new(
+        config.clone(),
+        cloud_meta.clone(),
+        cont_tx,
+        open_tx.clone(),
+        open_barriers.clone(),
+    );
+    containers.autodetect();
 
-    if let Some(host) = config.containerd_host() {
-        containers.track_k8s(host);
-    }
+    let checkpoint = CheckpointSeed::load();
 
     let (events_tx, events_rx) = tokio::sync::mpsc::channel::<Event>(1000);
     let host_wrkld = HostWorkload::new(
-        host_image_id,
+        host_image_id.clone(),
         config.clone(),
         open_mon.clone(),
         cloud_meta.host_labels(),
+        checkpoint.host_reported(),
     )?;
 
-    register_host_workload(&mut client, &host_wrkld, config.labels()).await?;
+    register_host_workload(&client, &host_wrkld, config.labels()).await?;
 
     let containers = Arc::new(containers);
-    let workloads = Workloads::new(config.clone(), host_wrkld, open_mon.clone());
+    let workloads = Workloads::new(config.clone(), host_wrkld, open_mon.clone(), checkpoint);
+
+    let (interval_tx, interval_rx) = watch::channel(HEARTBEAT_INTERVAL);
 
-    tokio::task::spawn(track_container_lifecycle(
+    let mut workers = WorkerManager::new();
+
+    workers.spawn(ContainerLifecycleWorker::new(
         cont_rx,
         workloads.containers.clone(),
         events_tx.clone(),
+        open_tx.clone(),
+        open_barriers.clone(),
     ));
 
     if let Some(rx) = open_rx {
-        tokio::task::spawn(workloads::in_use::track_pkgs_in_use(
+        workers.spawn(PkgsInUseWorker::new(
             containers.clone(),
             workloads.clone(),
             rx,
+            open_barriers.clone(),
         ));
     }
 
+    workers.spawn(ReportLoopWorker::new(
+        client.clone(),
+        workloads.clone(),
+        interval_rx,
+    ));
+
+    if config.machine_sbom() {
+        workers.spawn(SbomScrubWorker::new(
+            config.clone(),
+            client.clone(),
+            workloads.host.clone(),
+            host_image_id,
+        ));
+    }
+
+    workers.spawn(CheckpointWorker::new(
+        workloads.host.clone(),
+        workloads.containers.clone(),
+    ));
+
+    if let Some(addr) = config.admin_addr() {
+        let state = admin::AdminState::new(
+            containers.clone(),
+            workloads.clone(),
+            open_tx.clone(),
+            open_mon.clone(),
+            workers.registry(),
+        );
+        tokio::task::spawn(admin::serve(addr, state));
+    }
+
     info!("Monitoring workloads");
-    monitor(config, workloads, &mut client, events_rx).await;
+    monitor(config, &client, events_rx, cmd_rx, interval_tx).await;
+
+    info!("Shutting down, flushing pending telemetry");
+    flush_on_shutdown(&client, &workloads).await;
+
+    workers.stop().await;
 
     Ok(())
 }
 
+// Runs until the events channel closes (shouldn't happen in practice) or a
+// SIGTERM/SIGINT is received, at which point it stops reading new events
+// and commands so `run()` can do a final flush before exiting cleanly.
 async fn monitor(
     config: Arc<Config>,
-    workloads: Workloads,
-    client: &mut platform::Client,
+    client: &Arc<Mutex<platform::Client>>,
     mut events: Receiver<Event>,
+    mut commands: Receiver<platform::CommandRequest>,
+    interval_tx: watch::Sender<Duration>,
 ) {
-    let mut periods = tokio::time::interval(Duration::from_millis(1000));
     let labels = config.labels();
 
-    let mut last_reported = Instant::now();
-    let mut jitter = JitteredDuration::new(HEARTBEAT_JITTER);
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
 
     loop {
         tokio::select! {
+            _ = &mut shutdown => {
+                info!("Received shutdown signal");
+                break;
+            },
             evt = events.recv() => {
                 match evt {
                     Some(Event::ContainerStarted(id, info)) => handle_container_started(client, id, info, labels.clone()).await,
@@ -185,49 +302,123 @@ async fn monitor(
                     None => break,
                 }
             },
-            _ = periods.tick() => {
-                let mut reported = false;
+            cmd = commands.recv() => {
+                match cmd {
+                    Some(req) => handle_command(client, &config, &interval_tx, req).await,
+                    None => {}
+                }
+            },
+        }
+    }
+}
 
-                let (host_id, pkgs) = workloads.host.lock()
-                    .unwrap()
-                    .flush_in_use();
+// Waits for either a SIGTERM (how systemd/Kubernetes ask a process to stop)
+// or a SIGINT (Ctrl-C in a terminal), whichever comes first.
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
 
-                if !pkgs.is_empty() {
-                    if let Err(err) = client.report_in_use(host_id.clone(), pkgs).await {
-                        error!("Failed to report-in-use: {err}");
-                    }
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = sigterm.recv() => {}
+            }
+        }
+        Err(err) => {
+            error!("Failed to install SIGTERM handler, only SIGINT will trigger a graceful shutdown: {err}");
+            _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
 
-                    reported = true;
+// Gives the host and every still-tracked container one last chance to
+// report their pending in-use packages, and marks running containers
+// stopped, before the process exits. Bounded by SHUTDOWN_FLUSH_DEADLINE so
+// an unreachable control plane can't hang a graceful shutdown; anything
+// left over is still safe thanks to the on-disk report-in-use spool.
+async fn flush_on_shutdown(client: &Arc<Mutex<platform::Client>>, workloads: &Workloads) {
+    let flush = async {
+        let (host_id, pkgs) = workloads.host.lock().unwrap().flush_in_use();
+        if !pkgs.is_empty() {
+            if let Err(err) = client.lock().await.report_in_use(host_id, pkgs).await {
+                error!("Failed to flush host in-use packages on shutdown: {err}");
+            }
+        }
+
+        let batches = workloads.containers.lock().unwrap().flush_in_use();
+        for (id, pkgs) in batches {
+            if !pkgs.is_empty() {
+                if let Err(err) = client.lock().await.report_in_use(id, pkgs).await {
+                    error!("Failed to flush container in-use packages on shutdown: {err}");
                 }
+            }
+        }
 
-                let batches = workloads.containers.lock()
-                    .unwrap()
-                    .flush_in_use();
+        let running = workloads.containers.lock().unwrap().ids();
+        for id in running {
+            let req = pb::UpsertWorkloadRequest {
+                workload_id: id,
+                end_time: Some(SystemTime::now().into()),
+                ..Default::default()
+            };
 
-                for (id, pkgs) in batches {
-                    if !pkgs.is_empty() {
-                        if let Err(err) = client.report_in_use(id, pkgs).await {
-                            error!("Failed to report-in-use: {err}");
-                        }
+            if let Err(err) = client.lock().await.upsert_workload(req).await {
+                error!("Failed to report container stopped on shutdown: {err}");
+            }
+        }
 
-                        reported = true;
-                    }
-                }
+        CheckpointWorker::save_now(&workloads.host, &workloads.containers);
+    };
 
-                if reported {
-                    last_reported = Instant::now();
-                } else if last_reported.elapsed() >= jitter.add(HEARTBEAT_INTERVAL) {
-                    if let Err(err) = client.report_in_use(host_id, Vec::new()).await {
-                        error!("Failed to report-in-use (heartbeat): {err}");
-                    }
+    if tokio::time::timeout(SHUTDOWN_FLUSH_DEADLINE, flush).await.is_err() {
+        warn!("Shutdown flush did not complete within {SHUTDOWN_FLUSH_DEADLINE:?}, exiting anyway");
+    }
+}
 
-                    last_reported = Instant::now();
-                }
-            }
+// Handles a command pushed down by the control plane over the command
+// stream. The result is sent back to `Client`'s command loop so it can
+// report a CommandResult to the server.
+async fn handle_command(
+    client: &Arc<Mutex<platform::Client>>,
+    config: &Arc<Config>,
+    interval_tx: &watch::Sender<Duration>,
+    req: platform::CommandRequest,
+) {
+    info!("Handling command {} ({})", req.command_id, command_kind_name(&req.kind));
+
+    let result = match req.kind {
+        platform::CommandKind::RegenerateSbom => regenerate_sbom(config.clone(), client).await,
+        platform::CommandKind::ResyncWorkloads => client.lock().await.reset_workloads().await,
+        platform::CommandKind::SetInUseInterval(secs) => {
+            let interval = Duration::from_secs(secs as u64);
+            _ = interval_tx.send(interval);
+            info!("In-use reporting interval set to {secs}s");
+            Ok(())
         }
+    };
+
+    if let Err(ref err) = result {
+        error!("Command {} failed: {err}", req.command_id);
+    }
+
+    _ = req.reply.send(result);
+}
+
+fn command_kind_name(kind: &platform::CommandKind) -> &'static str {
+    match kind {
+        platform::CommandKind::RegenerateSbom => "regenerate-sbom",
+        platform::CommandKind::ResyncWorkloads => "resync-workloads",
+        platform::CommandKind::SetInUseInterval(_) => "set-in-use-interval",
     }
 }
 
+async fn regenerate_sbom(config: Arc<Config>, client: &Arc<Mutex<platform::Client>>) -> Result<()> {
+    let host_root = RootFsPath::from(config.host_root());
+    let tmp_file = sbom::generate(config.clone(), &host_root).await?;
+    let sbom = Sbom::load(&tmp_file.path().into())?;
+    upload_sbom(client, tmp_file.path(), sbom.id(), sbom.format()).await
+}
+
 fn to_upsert_workload_req(
     workload: &HostWorkload,
     mut extra_labels: HashMap<String, String>,
@@ -256,7 +447,7 @@ fn to_upsert_workload_req(
 }
 
 async fn handle_container_started(
-    client: &mut platform::Client,
+    client: &Arc<Mutex<platform::Client>>,
     id: String,
     info: ContainerInfo,
     mut extra_labels: HashMap<String, String>,
@@ -268,6 +459,8 @@ async fn handle_container_started(
     labels.extend(extra_labels.drain());
 
     let res = client
+        .lock()
+        .await
         .upsert_workload(pb::UpsertWorkloadRequest {
             workload_id: id,
             workload: Some(pb::Workload {
@@ -293,10 +486,12 @@ async fn handle_container_started(
     }
 }
 
-async fn handle_container_stopped(client: &mut platform::Client, id: String, info: ContainerInfo) {
+async fn handle_container_stopped(client: &Arc<Mutex<platform::Client>>, id: String, info: ContainerInfo) {
     info!("Registering container stopped: {id}");
 
     let res = client
+        .lock()
+        .await
         .upsert_workload(pb::UpsertWorkloadRequest {
             workload_id: id,
             end_time: info.end_time.map(|t| t.into()),
@@ -312,7 +507,7 @@ async fn handle_container_stopped(client: &mut platform::Client, id: String, inf
 async fn load_sbom(
     args: &CliArgs,
     config: Arc<Config>,
-    client: &mut platform::Client,
+    client: &Arc<Mutex<platform::Client>>,
 ) -> Result<Sbom> {
     let sbom = match &args.sbom {
         Some(sbom_path) => {
@@ -320,7 +515,7 @@ async fn load_sbom(
             let sbom = Sbom::load(&sbom_path.into())?;
 
             if !args.no_sbom_upload {
-                upload_sbom(client, sbom_path, sbom.id()).await?;
+                upload_sbom(client, sbom_path, sbom.id(), sbom.format()).await?;
             }
 
             sbom
@@ -332,7 +527,7 @@ async fn load_sbom(
             let sbom = Sbom::load(&tmp_file.path().into())?;
 
             if !args.no_sbom_upload {
-                upload_sbom(client, tmp_file.path(), sbom.id()).await?;
+                upload_sbom(client, tmp_file.path(), sbom.id(), sbom.format()).await?;
             }
 
             sbom
@@ -342,21 +537,26 @@ async fn load_sbom(
     Ok(sbom)
 }
 
-async fn upload_sbom(client: &mut platform::Client, path: &Path, image_id: String) -> Result<()> {
+async fn upload_sbom(
+    client: &Arc<Mutex<platform::Client>>,
+    path: &Path,
+    image_id: String,
+    format: sbom::SbomFormat,
+) -> Result<()> {
     info!("Uploading SBOM to EdgeBit");
     let f = std::fs::File::open(path)?;
-    client.upload_sbom(image_id, f).await?;
+    client.lock().await.upload_sbom(image_id, format, f).await?;
     Ok(())
 }
 
 async fn register_host_workload(
-    client: &mut platform::Client,
+    client: &Arc<Mutex<platform::Client>>,
     workload: &HostWorkload,
     extra_labels: HashMap<String, String>,
 ) -> Result<()> {
     info!("Registering BaseOS workload");
     let req = to_upsert_workload_req(workload, extra_labels);
-    client.upsert_workload(req).await?;
+    client.lock().await.upsert_workload(req).await?;
     Ok(())
 }
 
@@ -0,0 +1,111 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use lazy_static::lazy_static;
+use log::*;
+use prometheus::{register_histogram, register_int_counter, Encoder, Histogram, IntCounter, TextEncoder};
+
+lazy_static! {
+    pub static ref FILES_RESOLVED: IntCounter = register_int_counter!(
+        "edgebit_files_resolved_total",
+        "File-open events that resolved to a trackable package file"
+    )
+    .unwrap();
+
+    pub static ref FILES_FILTERED: IntCounter = register_int_counter!(
+        "edgebit_files_filtered_total",
+        "File-open events filtered out by includes/excludes or a non-file path"
+    )
+    .unwrap();
+
+    pub static ref FILES_ALREADY_REPORTED: IntCounter = register_int_counter!(
+        "edgebit_files_already_reported_total",
+        "File-open events for a path already marked in-use and reported"
+    )
+    .unwrap();
+
+    pub static ref RESOLVE_FAILURES: IntCounter = register_int_counter!(
+        "edgebit_resolve_failures_total",
+        "File-open events that failed to canonicalize against their workload root"
+    )
+    .unwrap();
+
+    pub static ref DROPPED_OPEN_EVENTS: IntCounter = register_int_counter!(
+        "edgebit_dropped_open_events_total",
+        "File-open events discarded because their container workload wasn't registered yet"
+    )
+    .unwrap();
+
+    pub static ref COALESCED_OPEN_EVENTS: IntCounter = register_int_counter!(
+        "edgebit_coalesced_open_events_total",
+        "File-open events collapsed into an already-pending entry for the same path"
+    )
+    .unwrap();
+
+    pub static ref DROPPED_OPEN_EVENTS_QUEUE_FULL: IntCounter = register_int_counter!(
+        "edgebit_open_event_queue_dropped_total",
+        "Pending open events evicted (oldest-first) because the open-event queue was at capacity"
+    )
+    .unwrap();
+
+    pub static ref IN_USE_BATCH_SIZE: Histogram = register_histogram!(
+        "edgebit_in_use_batch_size",
+        "Number of packages flushed per report-in-use batch",
+        vec![0.0, 1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0]
+    )
+    .unwrap();
+
+    pub static ref REPORT_IN_USE_OK: IntCounter = register_int_counter!(
+        "edgebit_report_in_use_success_total",
+        "ReportInUse RPCs that reached the server"
+    )
+    .unwrap();
+
+    pub static ref REPORT_IN_USE_ERR: IntCounter = register_int_counter!(
+        "edgebit_report_in_use_failure_total",
+        "ReportInUse RPCs that failed and were left spooled for retry"
+    )
+    .unwrap();
+
+    pub static ref UPSERT_WORKLOAD_OK: IntCounter = register_int_counter!(
+        "edgebit_upsert_workload_success_total",
+        "UpsertWorkload RPCs that reached the server"
+    )
+    .unwrap();
+
+    pub static ref UPSERT_WORKLOAD_ERR: IntCounter = register_int_counter!(
+        "edgebit_upsert_workload_failure_total",
+        "UpsertWorkload RPCs that failed and were left spooled for retry"
+    )
+    .unwrap();
+}
+
+async fn serve_req(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let encoder = TextEncoder::new();
+    let metric_families = prometheus::gather();
+
+    let mut buffer = Vec::new();
+    if let Err(err) = encoder.encode(&metric_families, &mut buffer) {
+        error!("Failed to encode metrics: {err}");
+    }
+
+    Ok(Response::builder()
+        .header("Content-Type", encoder.format_type())
+        .body(Body::from(buffer))
+        .unwrap())
+}
+
+// Serves a Prometheus text-exposition /metrics endpoint on `addr` for as
+// long as the process runs. Only started when `Config::metrics_addr` is
+// set, since most deployments don't want an extra open port by default.
+pub async fn serve(addr: SocketAddr) {
+    let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(serve_req)) });
+
+    info!("Serving metrics on http://{addr}/metrics");
+
+    if let Err(err) = Server::bind(&addr).serve(make_svc).await {
+        error!("Metrics server failed: {err}");
+    }
+}
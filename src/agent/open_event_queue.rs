@@ -0,0 +1,276 @@
+use std::collections::{HashSet, VecDeque};
+use std::ffi::OsString;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use log::*;
+use tokio::sync::Notify;
+
+use crate::metrics;
+use crate::open_monitor::OpenEvent;
+use crate::scoped_path::WorkloadPath;
+
+// Identifies a pending, not-yet-resolved open independent of its position in
+// the queue. Sentinels (see `open_monitor::OpenEventBarriers`) have no key:
+// each one is unique and must survive to prove ordering, so they're exempt
+// from both coalescing and eviction below.
+type PendingKey = (Option<OsString>, WorkloadPath);
+
+fn pending_key(event: &OpenEvent) -> Option<PendingKey> {
+    event.sentinel.is_none().then(|| (event.cgroup_name.clone(), event.filename.clone()))
+}
+
+struct State {
+    items: VecDeque<OpenEvent>,
+    pending: HashSet<PendingKey>,
+}
+
+struct Shared {
+    capacity: usize,
+    state: Mutex<State>,
+    notify: Notify,
+    senders: AtomicUsize,
+}
+
+// A bounded, in-memory queue of pending `OpenEvent`s, replacing an
+// unbounded buffer that could grow without limit under a file-open storm.
+// Two policies keep it self-limiting:
+//
+//   - Coalescing: a second open of a path already sitting in the queue
+//     collapses into the existing entry rather than growing it -- the
+//     `reported` LRU downstream already makes resending a duplicate path
+//     useless, so there's nothing to gain by queuing it twice.
+//   - Drop-oldest on overflow: when a genuinely new path arrives at
+//     capacity, the oldest pending entry is evicted to make room. A plain
+//     bounded channel would instead block the producer (the eBPF/fanotify/
+//     inotify/poll callback), which is worse: it stalls event capture
+//     rather than just losing the least useful thing we were holding.
+//     `edgebit_open_event_queue_dropped_total` makes the loss observable.
+pub struct OpenEventSender {
+    shared: Arc<Shared>,
+}
+
+impl Clone for OpenEventSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::AcqRel);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+pub struct OpenEventReceiver {
+    shared: Arc<Shared>,
+}
+
+pub fn channel(capacity: usize) -> (OpenEventSender, OpenEventReceiver) {
+    let shared = Arc::new(Shared {
+        capacity,
+        state: Mutex::new(State {
+            items: VecDeque::new(),
+            pending: HashSet::new(),
+        }),
+        notify: Notify::new(),
+        senders: AtomicUsize::new(1),
+    });
+
+    (
+        OpenEventSender {
+            shared: shared.clone(),
+        },
+        OpenEventReceiver { shared },
+    )
+}
+
+impl OpenEventSender {
+    // Never blocks and never fails: a full queue sheds its oldest entry
+    // instead of applying backpressure to the caller.
+    pub fn push(&self, event: OpenEvent) {
+        let key = pending_key(&event);
+
+        let mut state = self.shared.state.lock().unwrap();
+
+        if let Some(key) = &key {
+            if state.pending.contains(key) {
+                metrics::COALESCED_OPEN_EVENTS.inc();
+                return;
+            }
+
+            if state.items.len() >= self.shared.capacity {
+                // Armed barrier sentinels (see `pending_key`) are exempt from
+                // eviction: popping one out from under `OpenEventBarriers::arm`
+                // would silently break `OpenEventBarriers::resolve`. Evict the
+                // oldest *non-sentinel* entry instead.
+                let victim = state.items.iter().position(|item| item.sentinel.is_none());
+
+                if let Some(idx) = victim {
+                    let oldest = state.items.remove(idx).unwrap();
+                    if let Some(oldest_key) = pending_key(&oldest) {
+                        state.pending.remove(&oldest_key);
+                    }
+
+                    metrics::DROPPED_OPEN_EVENTS_QUEUE_FULL.inc();
+                    warn!(
+                        "Open-event queue at capacity ({}), dropping oldest pending event",
+                        self.shared.capacity
+                    );
+                } else {
+                    // Every queued entry is an armed sentinel; none are
+                    // eligible for eviction, so let the queue grow past
+                    // capacity rather than dropping one.
+                    warn!(
+                        "Open-event queue at capacity ({}) but every entry is an armed barrier sentinel, not evicting",
+                        self.shared.capacity
+                    );
+                }
+            }
+
+            state.pending.insert(key.clone());
+        }
+
+        state.items.push_back(event);
+        drop(state);
+
+        self.shared.notify.notify_one();
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    pub fn depth(&self) -> usize {
+        self.shared.state.lock().unwrap().items.len()
+    }
+}
+
+impl Drop for OpenEventSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.notify.notify_one();
+        }
+    }
+}
+
+impl OpenEventReceiver {
+    // Mirrors `tokio::sync::mpsc::Receiver::recv`: waits for the next
+    // pending event, or returns `None` once every `OpenEventSender` clone
+    // has been dropped and the queue has drained.
+    pub async fn recv(&mut self) -> Option<OpenEvent> {
+        loop {
+            let notified = {
+                let mut state = self.shared.state.lock().unwrap();
+
+                if let Some(event) = state.items.pop_front() {
+                    if let Some(key) = pending_key(&event) {
+                        state.pending.remove(&key);
+                    }
+
+                    return Some(event);
+                }
+
+                if self.shared.senders.load(Ordering::Acquire) == 0 {
+                    return None;
+                }
+
+                self.shared.notify.notified()
+            };
+
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use assert2::assert;
+
+    use super::*;
+    use crate::open_monitor::AccessKind;
+
+    fn open_event(cgroup: &str, filename: &str) -> OpenEvent {
+        OpenEvent {
+            cgroup_name: Some(OsString::from(cgroup)),
+            filename: WorkloadPath::from(PathBuf::from(filename)),
+            access_kind: AccessKind::Open,
+            sentinel: None,
+        }
+    }
+
+    fn sentinel_event(id: u64) -> OpenEvent {
+        OpenEvent {
+            cgroup_name: None,
+            filename: WorkloadPath::from(PathBuf::from("<sentinel>")),
+            access_kind: AccessKind::Open,
+            sentinel: Some(id),
+        }
+    }
+
+    #[test]
+    fn test_coalesces_duplicate_path() {
+        let (tx, _rx) = channel(4);
+
+        tx.push(open_event("cg", "/bin/a"));
+        tx.push(open_event("cg", "/bin/a"));
+
+        assert!(tx.depth() == 1);
+    }
+
+    #[tokio::test]
+    async fn test_evicts_oldest_non_sentinel_on_overflow() {
+        let (tx, mut rx) = channel(2);
+
+        tx.push(open_event("cg", "/bin/a"));
+        tx.push(open_event("cg", "/bin/b"));
+        tx.push(open_event("cg", "/bin/c"));
+
+        assert!(tx.depth() == 2);
+
+        let first = rx.recv().await.unwrap();
+        assert!(first.filename.as_raw() == Path::new("/bin/b"));
+
+        let second = rx.recv().await.unwrap();
+        assert!(second.filename.as_raw() == Path::new("/bin/c"));
+    }
+
+    #[tokio::test]
+    async fn test_eviction_never_pops_an_armed_sentinel() {
+        let (tx, mut rx) = channel(2);
+
+        tx.push(sentinel_event(1));
+        tx.push(open_event("cg", "/bin/a"));
+        // At capacity: the sentinel must survive, so /bin/a is evicted
+        // instead even though it's not the oldest entry.
+        tx.push(open_event("cg", "/bin/b"));
+
+        assert!(tx.depth() == 2);
+
+        let first = rx.recv().await.unwrap();
+        assert!(first.sentinel == Some(1));
+
+        let second = rx.recv().await.unwrap();
+        assert!(second.filename.as_raw() == Path::new("/bin/b"));
+    }
+
+    #[tokio::test]
+    async fn test_eviction_grows_past_capacity_when_every_entry_is_a_sentinel() {
+        let (tx, mut rx) = channel(1);
+
+        tx.push(sentinel_event(1));
+        tx.push(sentinel_event(2));
+
+        assert!(tx.depth() == 2);
+
+        assert!(rx.recv().await.unwrap().sentinel == Some(1));
+        assert!(rx.recv().await.unwrap().sentinel == Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_recv_returns_none_once_every_sender_is_dropped() {
+        let (tx, mut rx) = channel(4);
+        drop(tx);
+
+        assert!(rx.recv().await.is_none());
+    }
+}
@@ -1,26 +1,48 @@
-use std::ffi::{c_char, CStr};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{c_char, CStr, OsStr, OsString};
 use std::mem::size_of;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::{anyhow, Result};
+use inotify::{Inotify, WatchDescriptor, WatchMask};
 use libbpf_rs::skel::{OpenSkel, Skel, SkelBuilder};
 use libbpf_rs::{Map, MapFlags, MapHandle, PerfBufferBuilder, RingBufferBuilder};
 use thiserror::Error;
 
 use log::*;
-use tokio::sync::mpsc::Sender;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
 
-use crate::fanotify::Fanotify;
+use crate::fanotify::{has_cap_sys_admin, Fanotify, MarkScope, WATCH_MASK};
+use crate::open_event_queue::OpenEventSender;
 use crate::scoped_path::*;
 
 mod probes {
     include!(concat!(env!("OUT_DIR"), "/probes.skel.rs"));
 }
 
-const OPEN_EVENTS_BUF_SIZE: usize = 256;
-const ZOMBIE_EVENTS_BUF_SIZE: usize = 4;
+// Counts of events lost before `OpenEvent` decode ever sees them, as
+// opposed to `metrics::DROPPED_OPEN_EVENTS`/`DROPPED_OPEN_EVENTS_QUEUE_FULL`
+// which account for drops further downstream. Only `OpenMonitor` (the BPF
+// backend) tracks these; every other `FileOpenMonitor` backend has no
+// kernel buffer to lose events from.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DropCounts {
+    // Perf-buffer samples the kernel couldn't deliver because userspace
+    // wasn't draining fast enough (`PerfBufferBuilder::lost_cb`). Only
+    // nonzero when the ring buffer isn't in use.
+    pub lost_perf_events: u64,
+    // Ring-buffer reservations that failed in-kernel because the buffer
+    // was full, tallied by the eBPF side into a dedicated counter map
+    // since the ring buffer itself has no lost-sample callback.
+    pub dropped_ring_events: u64,
+}
 
 pub trait FileOpenMonitor {
     // NB: Adds the mountpoint of path, not the actual path.
@@ -28,6 +50,13 @@ pub trait FileOpenMonitor {
 
     // NB: Removes the mountpoint of path, not the actual path.
     fn remove_path(&self, path: &RootFsPath) -> Result<()>;
+
+    // Events lost before they ever reached the queue. Backends with no
+    // kernel buffer to lose events from (inotify, polling, the mock) just
+    // keep the default of all zeroes.
+    fn drop_counts(&self) -> DropCounts {
+        DropCounts::default()
+    }
 }
 
 pub type FileOpenMonitorArc = Arc<dyn FileOpenMonitor + Send + Sync>;
@@ -43,7 +72,7 @@ enum CommBuffer<'cb> {
 }
 
 impl<'cb> CommBuffer<'cb> {
-    fn load<F>(map: CommBufferMap<'_>, pages: usize, cb: F) -> Result<Self>
+    fn load<F>(map: CommBufferMap<'_>, pages: usize, cb: F, lost: Arc<AtomicU64>) -> Result<Self>
     where
         F: Fn(&[u8]) + Send + Sync + 'static,
     {
@@ -62,7 +91,10 @@ impl<'cb> CommBuffer<'cb> {
                 let pb = PerfBufferBuilder::new(pb)
                     .pages(pages)
                     .sample_cb(move |_cpu, buf: &[u8]| cb(buf))
-                    .lost_cb(handle_lost_events)
+                    .lost_cb(move |cpu, count| {
+                        warn!("Lost {count} events on CPU {cpu}");
+                        lost.fetch_add(count, Ordering::Relaxed);
+                    })
                     .build()?;
 
                 Ok(CommBuffer::PerfBuffer(pb))
@@ -70,18 +102,86 @@ impl<'cb> CommBuffer<'cb> {
         }
     }
 
-    fn poll(&self, dur: Duration) -> Result<()> {
+    // Drains the buffer as events arrive instead of polling on a fixed
+    // timer: wraps the map's epoll fd(s) in `AsyncFd` and calls the
+    // non-blocking `consume`/`consume_raw` only once woken. Runs its own
+    // single-threaded reactor on a blocking-pool thread rather than
+    // `tokio::spawn`, since the skel's raw `*mut` internals (see
+    // `BpfProbes`'s doc comment) aren't `Send` and can't cross an `.await`
+    // on the main runtime.
+    fn run(self) -> Result<JoinHandle<()>> {
         match self {
-            CommBuffer::RingBuffer(rb) => rb.poll(dur)?,
-            CommBuffer::PerfBuffer(pb) => pb.poll(dur)?,
+            CommBuffer::RingBuffer(rb) => {
+                let fd = rb.epoll_fd();
+
+                Ok(tokio::task::spawn_blocking(move || {
+                    if let Err(err) = run_local(async {
+                        let async_fd = AsyncFd::new(BorrowedRawFd(fd))?;
+
+                        loop {
+                            let mut guard = async_fd.readable().await?;
+
+                            if let Err(err) = rb.consume() {
+                                error!("ring buffer consume failed: {err}");
+                            }
+
+                            guard.clear_ready();
+                        }
+                    }) {
+                        error!("ring buffer reactor exited: {err}");
+                    }
+                }))
+            }
+            CommBuffer::PerfBuffer(pb) => {
+                let fds: Vec<RawFd> = pb.fds();
+
+                Ok(tokio::task::spawn_blocking(move || {
+                    if let Err(err) = run_local(async {
+                        let async_fds: Vec<AsyncFd<BorrowedRawFd>> = fds
+                            .into_iter()
+                            .map(|fd| AsyncFd::new(BorrowedRawFd(fd)))
+                            .collect::<std::io::Result<_>>()?;
+
+                        loop {
+                            for async_fd in &async_fds {
+                                let mut guard = async_fd.readable().await?;
+
+                                if let Err(err) = pb.consume_raw() {
+                                    error!("perf buffer consume failed: {err}");
+                                }
+
+                                guard.clear_ready();
+                            }
+                        }
+                    }) {
+                        error!("perf buffer reactor exited: {err}");
+                    }
+                }))
+            }
         }
+    }
+}
 
-        Ok(())
+struct BorrowedRawFd(RawFd);
+
+impl AsRawFd for BorrowedRawFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
     }
 }
 
-fn handle_lost_events(cpu: i32, count: u64) {
-    warn!("Lost {count} events on CPU {cpu}");
+// Runs a future to completion on a fresh single-threaded, IO-enabled
+// runtime. Used to drive `AsyncFd` waits from a blocking-pool thread without
+// requiring the future (and the non-`Send` libbpf types it closes over) to
+// be schedulable on the main multi-threaded runtime.
+fn run_local<F: std::future::Future<Output = std::io::Result<std::convert::Infallible>>>(
+    fut: F,
+) -> std::io::Result<std::convert::Infallible> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()?;
+
+    rt.block_on(fut)
 }
 
 #[derive(Error, Debug)]
@@ -93,10 +193,13 @@ struct BpfProbes {
     // making it not possible to use with .await
     skel: probes::ProbesSkel<'static>,
     use_ring_buf: bool,
+    open_buf_pages: usize,
+    zombie_buf_pages: usize,
+    lost_perf_events: Arc<AtomicU64>,
 }
 
 impl BpfProbes {
-    fn load() -> Result<Self> {
+    fn load(open_buf_pages: usize, zombie_buf_pages: usize) -> Result<Self> {
         // first thing is to bump the ulimit for locked memory for older kernels
         bump_rlimit()?;
 
@@ -106,7 +209,7 @@ impl BpfProbes {
         let mut with_optional = true;
 
         loop {
-            match Self::load_internal(use_ring_buf, with_optional) {
+            match Self::load_internal(use_ring_buf, with_optional, open_buf_pages, zombie_buf_pages) {
                 Ok(skel) => return Ok(skel),
                 Err(err) => {
                     if err.is::<LoadError>() {
@@ -126,7 +229,12 @@ impl BpfProbes {
         }
     }
 
-    fn load_internal(use_ring_buf: bool, with_optional_probes: bool) -> Result<Self> {
+    fn load_internal(
+        use_ring_buf: bool,
+        with_optional_probes: bool,
+        open_buf_pages: usize,
+        zombie_buf_pages: usize,
+    ) -> Result<Self> {
         let skel_builder = probes::ProbesSkelBuilder::default();
 
         let mut open_skel = skel_builder
@@ -163,11 +271,32 @@ impl BpfProbes {
             .exit_openat2()
             .set_autoload(with_optional_probes)?;
 
+        // execveat isn't available on older kernels either, so gate it the
+        // same way as the openat2 probes above rather than failing the
+        // whole load.
+        open_skel
+            .progs_mut()
+            .enter_execve()
+            .set_autoload(with_optional_probes)?;
+
+        open_skel
+            .progs_mut()
+            .exit_execve()
+            .set_autoload(with_optional_probes)?;
+
+        open_skel.progs_mut().exit_mmap().set_autoload(true)?;
+
         let mut skel = open_skel.load().map_err(LoadError)?;
 
         skel.attach().map_err(LoadError)?;
 
-        Ok(Self { skel, use_ring_buf })
+        Ok(Self {
+            skel,
+            use_ring_buf,
+            open_buf_pages,
+            zombie_buf_pages,
+            lost_perf_events: Arc::new(AtomicU64::new(0)),
+        })
     }
 
     fn open_events<'cb, F>(&self, cb: F) -> Result<CommBuffer<'cb>>
@@ -178,10 +307,10 @@ impl BpfProbes {
 
         if self.use_ring_buf {
             let map = CommBufferMap::RingBuffer(maps.rb_open_events());
-            CommBuffer::load(map, 0, cb)
+            CommBuffer::load(map, 0, cb, self.lost_perf_events.clone())
         } else {
             let map = CommBufferMap::PerfBuffer(maps.pb_open_events());
-            CommBuffer::load(map, OPEN_EVENTS_BUF_SIZE, cb)
+            CommBuffer::load(map, self.open_buf_pages, cb, self.lost_perf_events.clone())
         }
     }
 
@@ -193,14 +322,54 @@ impl BpfProbes {
 
         if self.use_ring_buf {
             let map = CommBufferMap::RingBuffer(maps.rb_zombie_events());
-            CommBuffer::load(map, 0, cb)
+            CommBuffer::load(map, 0, cb, self.lost_perf_events.clone())
         } else {
             let map = CommBufferMap::PerfBuffer(maps.pb_zombie_events());
-            CommBuffer::load(map, ZOMBIE_EVENTS_BUF_SIZE, cb)
+            CommBuffer::load(map, self.zombie_buf_pages, cb, self.lost_perf_events.clone())
         }
     }
 
-    fn lookup_cgroup(&self, pid: u32) -> Result<Option<String>> {
+    // Ring-buffer reservations the eBPF side failed because the buffer was
+    // full, tallied into a dedicated counter map since, unlike the perf
+    // buffer, the ring buffer has no lost-sample callback to observe this
+    // from userspace.
+    fn dropped_ring_events(&self) -> Result<u64> {
+        let key = 0u32.to_ne_bytes();
+        let val = self
+            .skel
+            .maps()
+            .ringbuf_drops()
+            .lookup(&key, MapFlags::ANY)
+            .map_err(|err| anyhow!("ringbuf_drops::lookup(): {err}"))?;
+
+        Ok(match val {
+            Some(bytes) => {
+                let mut buf = [0u8; 8];
+                let n = bytes.len().min(8);
+                buf[..n].copy_from_slice(&bytes[..n]);
+                u64::from_ne_bytes(buf)
+            }
+            None => 0,
+        })
+    }
+
+    fn drop_counts(&self) -> DropCounts {
+        let dropped_ring_events = if self.use_ring_buf {
+            self.dropped_ring_events().unwrap_or_else(|err| {
+                error!("dropped_ring_events: {err}");
+                0
+            })
+        } else {
+            0
+        };
+
+        DropCounts {
+            lost_perf_events: self.lost_perf_events.load(Ordering::Relaxed),
+            dropped_ring_events,
+        }
+    }
+
+    fn lookup_cgroup(&self, pid: u32) -> Result<Option<OsString>> {
         let key = pid.to_ne_bytes();
         let val = self
             .skel
@@ -216,9 +385,7 @@ impl BpfProbes {
                     .try_into()
                     .map_err(|_| anyhow!("error casting bytes into ProcessInfo"))?;
 
-                let cgroup = info.cgroup_path()?.to_string();
-
-                Some(cgroup)
+                Some(info.cgroup_path().to_os_string())
             }
             None => None,
         })
@@ -243,21 +410,17 @@ struct ProcessInfo {
 }
 
 impl ProcessInfo {
-    fn cgroup_path(&self) -> Result<&str> {
+    // Cgroup names aren't guaranteed to be valid UTF-8, so this is kept as
+    // an `OsStr` all the way through; callers only need a lossy `str` right
+    // at the point they report it (e.g. for display or as a map key).
+    fn cgroup_path(&self) -> &OsStr {
         let nul = self
             .cgroup
             .iter()
             .position(|b| *b == 0u8)
             .unwrap_or(self.cgroup.len());
 
-        let cg = std::str::from_utf8(&self.cgroup[..nul]).map_err(|_| {
-            anyhow!(
-                "cgroup name with non-UTF8 characters: {:?}",
-                &self.cgroup[..nul]
-            )
-        })?;
-
-        Ok(cg)
+        OsStr::from_bytes(&self.cgroup[..nul])
     }
 }
 
@@ -278,15 +441,21 @@ impl TryFrom<&[u8]> for ProcessInfo {
 pub struct OpenMonitor {
     fan: Arc<Fanotify>,
     fan_task: JoinHandle<()>,
-    _probes: Arc<Mutex<BpfProbes>>,
+    probes: Arc<Mutex<BpfProbes>>,
     zombie_task: JoinHandle<()>,
     opens_task: JoinHandle<()>,
 }
 
 impl OpenMonitor {
-    pub fn start(ch: Sender<OpenEvent>) -> Result<Self> {
+    pub fn start(ch: OpenEventSender, open_buf_pages: usize, zombie_buf_pages: usize) -> Result<Self> {
+        if !has_cap_sys_admin() {
+            return Err(anyhow!(
+                "missing CAP_SYS_ADMIN, required for the eBPF/fanotify open monitor"
+            ));
+        }
+
         let fan = Arc::new(Fanotify::new()?);
-        let probes = Arc::new(Mutex::new(BpfProbes::load()?));
+        let probes = Arc::new(Mutex::new(BpfProbes::load(open_buf_pages, zombie_buf_pages)?));
 
         let fan_task =
             tokio::task::spawn(monitor_fanotify(fan.clone(), probes.clone(), ch.clone()));
@@ -298,7 +467,7 @@ impl OpenMonitor {
         Ok(Self {
             fan,
             fan_task,
-            _probes: probes,
+            probes,
             zombie_task,
             opens_task,
         })
@@ -319,19 +488,23 @@ impl OpenMonitor {
 impl FileOpenMonitor for OpenMonitor {
     // NB: Adds the mountpoint of path, not the actual path.
     fn add_path(&self, path: &RootFsPath) -> Result<()> {
-        self.fan.add_open_mark(path.as_raw().to_path_buf())
+        self.fan.add_open_mark(path.as_raw().to_path_buf(), WATCH_MASK, MarkScope::Filesystem, false)
     }
 
     // NB: Removes the mountpoint of path, not the actual path.
     fn remove_path(&self, path: &RootFsPath) -> Result<()> {
-        self.fan.remove_open_mark(path.as_raw().to_path_buf())
+        self.fan.remove_open_mark(path.as_raw().to_path_buf(), WATCH_MASK, MarkScope::Filesystem, false)
+    }
+
+    fn drop_counts(&self) -> DropCounts {
+        self.probes.lock().unwrap().drop_counts()
     }
 }
 
 async fn monitor_fanotify(
     fan: Arc<Fanotify>,
     probes: Arc<Mutex<BpfProbes>>,
-    ch: Sender<OpenEvent>,
+    ch: OpenEventSender,
 ) {
     loop {
         let events = match fan.next().await {
@@ -364,52 +537,64 @@ async fn monitor_fanotify(
             let open = OpenEvent {
                 cgroup_name,
                 filename,
+                // fanotify only ever reports plain opens.
+                access_kind: AccessKind::Open,
+                sentinel: None,
             };
 
-            _ = ch.send(open).await;
+            ch.push(open);
         }
     }
 }
 
+// Decodes a raw `EvtOpen` perf/ring-buffer record into an `OpenEvent`,
+// resolving its cgroup through `lookup_cgroup`. Kept free of `BpfProbes` so
+// it can be exercised directly against byte buffers (e.g. from a mock
+// backend) without a live skel.
+fn decode_open_event(
+    buf: &[u8],
+    lookup_cgroup: impl FnOnce(u32) -> Result<Option<OsString>>,
+) -> OpenEvent {
+    let evt = buf.as_ptr() as *const EvtOpen;
+    let fname = unsafe { CStr::from_ptr(&((*evt).filename) as *const c_char) };
+    let pid = unsafe { u32::from_ne_bytes((*evt).pid) };
+    let access_kind = AccessKind::from(unsafe { (*evt).access_kind });
+
+    let filename = WorkloadPath::from_cstr(fname);
+
+    let cgroup_name = match lookup_cgroup(pid) {
+        Ok(cgroup) => cgroup,
+        Err(err) => {
+            error!("lookup_cgroup: {err}");
+            None
+        }
+    };
+
+    trace!("bpf: {} / {:?} ({:?})", filename.display(), cgroup_name, access_kind);
+
+    OpenEvent {
+        cgroup_name,
+        filename,
+        access_kind,
+        sentinel: None,
+    }
+}
+
 fn monitor_bpf_open_events(
     probes_arc: Arc<Mutex<BpfProbes>>,
-    ch: Sender<OpenEvent>,
+    ch: OpenEventSender,
 ) -> Result<JoinHandle<()>> {
     let events = {
         let probes = probes_arc.lock().unwrap();
         let probes_arc = probes_arc.clone();
 
         probes.open_events(move |buf| {
-            let evt = buf.as_ptr() as *const EvtOpen;
-            let fname = unsafe { CStr::from_ptr(&((*evt).filename) as *const c_char) };
-            let pid = unsafe { u32::from_ne_bytes((*evt).pid) };
-
-            let filename = WorkloadPath::from_cstr(fname);
-
-            let cgroup_name = match probes_arc.lock().unwrap().lookup_cgroup(pid) {
-                Ok(cgroup) => cgroup,
-                Err(err) => {
-                    error!("lookup_cgroup: {err}");
-                    None
-                }
-            };
-
-            trace!("bpf: {} / {:?}", filename.display(), cgroup_name);
-
-            let open = OpenEvent {
-                cgroup_name,
-                filename,
-            };
-
-            if let Err(err) = ch.blocking_send(open) {
-                error!("Error sending OpenEvent on a channel: {err}");
-            }
+            let open = decode_open_event(buf, |pid| probes_arc.lock().unwrap().lookup_cgroup(pid));
+            ch.push(open);
         })?
     };
 
-    Ok(tokio::task::spawn_blocking(move || loop {
-        _ = events.poll(Duration::from_millis(100));
-    }))
+    events.run()
 }
 
 fn monitor_zombies(probes_arc: Arc<Mutex<BpfProbes>>) -> Result<JoinHandle<()>> {
@@ -430,21 +615,106 @@ fn monitor_zombies(probes_arc: Arc<Mutex<BpfProbes>>) -> Result<JoinHandle<()>>
         })?
     };
 
-    Ok(tokio::task::spawn_blocking(move || loop {
-        _ = events.poll(Duration::from_millis(100));
-    }))
+    events.run()
 }
 
-// matches evt_open in probes.bpf.c
+// matches evt_open in probes.bpf.c, which also carries the enter_execve/
+// exit_execve/exit_mmap programs wired up in `BpfProbes::load_internal`:
+// execve(at) resolves its target the same way opens do (via the file's
+// dentry, not the user-supplied argv pointer), and the mmap exit probe
+// tags a mapping as `MmapExec` when it's backed by a file and requests
+// `PROT_EXEC`.
 #[repr(C)]
 struct EvtOpen {
     pid: [u8; 4],
     filename: [std::ffi::c_char; 256],
+    access_kind: u8,
+}
+
+// How a file was accessed, distinguishing loaded code from plain data
+// reads. Mirrors the `access_kind` enum probes.bpf.c tags each `EvtOpen`
+// with; an unrecognized value (e.g. an older probe build) falls back to
+// `Open` rather than failing decode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AccessKind {
+    Open,
+    Exec,
+    MmapExec,
+}
+
+impl From<u8> for AccessKind {
+    fn from(v: u8) -> Self {
+        match v {
+            1 => AccessKind::Exec,
+            2 => AccessKind::MmapExec,
+            _ => AccessKind::Open,
+        }
+    }
 }
 
 pub struct OpenEvent {
-    pub cgroup_name: Option<String>,
+    pub cgroup_name: Option<OsString>,
     pub filename: WorkloadPath,
+    // Distinguishes a plain open from the file being executed or mapped
+    // in as code, so downstream reporting can tell loaded code apart from
+    // a data read.
+    pub access_kind: AccessKind,
+    // Some(id) marks this as a barrier sentinel rather than a real file
+    // open - see `OpenEventBarriers`. Real events leave this `None`.
+    pub sentinel: Option<u64>,
+}
+
+impl OpenEvent {
+    fn sentinel(id: u64) -> Self {
+        Self {
+            cgroup_name: None,
+            filename: WorkloadPath::from(PathBuf::from("<open-event-barrier-sentinel>")),
+            access_kind: AccessKind::Open,
+            sentinel: Some(id),
+        }
+    }
+}
+
+// Coordinates the in-order barrier used to know when container metadata
+// registration (or teardown) has fully drained through the open-event
+// queue: `arm` sends a uniquely-numbered sentinel through the same channel
+// real OpenEvents travel on and returns a receiver that resolves once
+// PkgsInUseWorker pops that exact sentinel back out of the queue, proving
+// every open event enqueued ahead of it has already been handled. This
+// replaces a flat delay with a deterministic, in-band proof of ordering.
+#[derive(Clone, Default)]
+pub struct OpenEventBarriers {
+    next_id: Arc<AtomicU64>,
+    waiters: Arc<Mutex<HashMap<u64, oneshot::Sender<()>>>>,
+}
+
+impl OpenEventBarriers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn arm(&self, ch: &OpenEventSender) -> oneshot::Receiver<()> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+
+        self.waiters.lock().unwrap().insert(id, tx);
+
+        // Sentinels are exempt from the queue's coalescing/drop-oldest
+        // policy, so this always reaches `resolve` eventually (the fallback
+        // timeouts at the call sites are only insurance against bugs, not a
+        // documented drop path).
+        ch.push(OpenEvent::sentinel(id));
+
+        rx
+    }
+
+    // Called by PkgsInUseWorker when it pops a sentinel back out of the
+    // queue, unblocking whoever is waiting on the matching `arm` receiver.
+    pub fn resolve(&self, id: u64) {
+        if let Some(tx) = self.waiters.lock().unwrap().remove(&id) {
+            _ = tx.send(());
+        }
+    }
 }
 
 fn bump_rlimit() -> Result<()> {
@@ -490,3 +760,380 @@ impl FileOpenMonitor for NullOpenMonitor {
         Ok(())
     }
 }
+
+// A `FileOpenMonitor` backend with no kernel dependency, for exercising
+// `Containers`/workload code against synthetic events. `handle()` lets a
+// test push `OpenEvent`s through the same channel a real backend would use,
+// and inspect which paths were armed/disarmed via `add_path`/`remove_path`.
+pub struct MockOpenMonitor {
+    ch: OpenEventSender,
+    added: Arc<Mutex<Vec<PathBuf>>>,
+    removed: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl MockOpenMonitor {
+    pub fn new(ch: OpenEventSender) -> Self {
+        Self {
+            ch,
+            added: Arc::new(Mutex::new(Vec::new())),
+            removed: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn handle(&self) -> MockOpenMonitorHandle {
+        MockOpenMonitorHandle {
+            ch: self.ch.clone(),
+            added: self.added.clone(),
+            removed: self.removed.clone(),
+        }
+    }
+}
+
+impl FileOpenMonitor for MockOpenMonitor {
+    fn add_path(&self, path: &RootFsPath) -> Result<()> {
+        self.added.lock().unwrap().push(path.as_raw().to_path_buf());
+        Ok(())
+    }
+
+    fn remove_path(&self, path: &RootFsPath) -> Result<()> {
+        self.removed.lock().unwrap().push(path.as_raw().to_path_buf());
+        Ok(())
+    }
+}
+
+pub struct MockOpenMonitorHandle {
+    ch: OpenEventSender,
+    added: Arc<Mutex<Vec<PathBuf>>>,
+    removed: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl MockOpenMonitorHandle {
+    pub fn push_open(&self, cgroup_name: Option<OsString>, filename: WorkloadPath) {
+        self.ch.push(OpenEvent {
+            cgroup_name,
+            filename,
+            access_kind: AccessKind::Open,
+            sentinel: None,
+        });
+    }
+
+    pub fn added_paths(&self) -> Vec<PathBuf> {
+        self.added.lock().unwrap().clone()
+    }
+
+    pub fn removed_paths(&self) -> Vec<PathBuf> {
+        self.removed.lock().unwrap().clone()
+    }
+}
+
+// Userspace fallback for hosts where fanotify/eBPF aren't available (e.g. the
+// agent lacks CAP_SYS_ADMIN, or is running in an unprivileged container).
+// Unlike the fanotify/eBPF backends, it watches individual subtrees rather
+// than a whole filesystem, since inotify has no filesystem-wide mode.
+pub struct InotifyMonitor {
+    handle: Mutex<inotify::Watches>,
+    // Maps a watch descriptor to the directory it watches. inotify events
+    // only carry the watched directory's descriptor plus the changed file's
+    // name, so this lets monitor_inotify reconstitute the full path, and
+    // lets remove_path tear every watch under a subtree back down.
+    watches: Arc<Mutex<HashMap<WatchDescriptor, PathBuf>>>,
+    task: JoinHandle<()>,
+}
+
+const INOTIFY_WATCH_MASK: WatchMask = WatchMask::OPEN;
+
+impl InotifyMonitor {
+    pub fn start(ch: OpenEventSender) -> Result<Self> {
+        let inotify = Inotify::init().map_err(|err| anyhow!("Inotify::init(): {err}"))?;
+        let handle = inotify.watches();
+
+        let stream = inotify
+            .into_event_stream(vec![0u8; 4096])
+            .map_err(|err| anyhow!("inotify event stream: {err}"))?;
+
+        let watches: Arc<Mutex<HashMap<WatchDescriptor, PathBuf>>> =
+            Arc::new(Mutex::new(HashMap::new()));
+
+        let task = tokio::task::spawn(monitor_inotify(stream, watches.clone(), ch));
+
+        Ok(Self {
+            handle: Mutex::new(handle),
+            watches,
+            task,
+        })
+    }
+
+    fn add_watch(&self, path: &Path) -> Result<()> {
+        let wd = self
+            .handle
+            .lock()
+            .unwrap()
+            .add(path, INOTIFY_WATCH_MASK)
+            .map_err(|err| anyhow!("inotify add watch {}: {err}", path.display()))?;
+
+        self.watches.lock().unwrap().insert(wd, path.to_path_buf());
+
+        Ok(())
+    }
+
+    fn add_watch_recursive(&self, path: &Path) {
+        if let Err(err) = self.add_watch(path) {
+            debug!("{err}");
+            return;
+        }
+
+        let Ok(entries) = std::fs::read_dir(path) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            if let Ok(file_type) = entry.file_type() {
+                if file_type.is_dir() {
+                    self.add_watch_recursive(&entry.path());
+                }
+            }
+        }
+    }
+}
+
+impl FileOpenMonitor for InotifyMonitor {
+    fn add_path(&self, path: &RootFsPath) -> Result<()> {
+        self.add_watch_recursive(path.as_raw());
+        Ok(())
+    }
+
+    fn remove_path(&self, path: &RootFsPath) -> Result<()> {
+        let mut watches = self.watches.lock().unwrap();
+        let under_path: Vec<WatchDescriptor> = watches
+            .iter()
+            .filter(|(_, dir)| dir.starts_with(path.as_raw()))
+            .map(|(wd, _)| wd.clone())
+            .collect();
+
+        let mut handle = self.handle.lock().unwrap();
+        for wd in under_path {
+            watches.remove(&wd);
+            _ = handle.remove(wd);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for InotifyMonitor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// Last-resort userspace fallback for hosts where neither eBPF/fanotify nor
+// inotify are usable (e.g. inotify's per-process watch limit is already
+// exhausted, or the kernel lacks the needed interfaces entirely). Instead
+// of watching for opens it periodically walks each registered subtree and
+// synthesizes an open-equivalent OpenEvent for every file it finds, trading
+// real-time accuracy for working everywhere `std::fs` does.
+pub struct PollMonitor {
+    roots: Arc<Mutex<HashSet<PathBuf>>>,
+    task: JoinHandle<()>,
+}
+
+impl PollMonitor {
+    pub fn start(ch: OpenEventSender, interval: Duration) -> Self {
+        let roots: Arc<Mutex<HashSet<PathBuf>>> = Arc::new(Mutex::new(HashSet::new()));
+        let task = tokio::task::spawn(poll_walk(roots.clone(), interval, ch));
+
+        Self { roots, task }
+    }
+}
+
+impl FileOpenMonitor for PollMonitor {
+    fn add_path(&self, path: &RootFsPath) -> Result<()> {
+        self.roots
+            .lock()
+            .unwrap()
+            .insert(path.as_raw().to_path_buf());
+
+        Ok(())
+    }
+
+    fn remove_path(&self, path: &RootFsPath) -> Result<()> {
+        self.roots.lock().unwrap().remove(path.as_raw());
+        Ok(())
+    }
+}
+
+impl Drop for PollMonitor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn poll_walk(roots: Arc<Mutex<HashSet<PathBuf>>>, interval: Duration, ch: OpenEventSender) {
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let roots: Vec<PathBuf> = roots.lock().unwrap().iter().cloned().collect();
+
+        for root in roots {
+            let mut pending = vec![root];
+
+            while let Some(dir) = pending.pop() {
+                let Ok(entries) = std::fs::read_dir(&dir) else {
+                    continue;
+                };
+
+                for entry in entries.flatten() {
+                    let Ok(file_type) = entry.file_type() else {
+                        continue;
+                    };
+
+                    if file_type.is_dir() {
+                        pending.push(entry.path());
+                    } else if file_type.is_file() {
+                        let open = OpenEvent {
+                            cgroup_name: None,
+                            filename: WorkloadPath::from(entry.path()),
+                            access_kind: AccessKind::Open,
+                            sentinel: None,
+                        };
+
+                        ch.push(open);
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn monitor_inotify(
+    mut stream: inotify::EventStream<Vec<u8>>,
+    watches: Arc<Mutex<HashMap<WatchDescriptor, PathBuf>>>,
+    ch: OpenEventSender,
+) {
+    use futures::stream::StreamExt;
+
+    while let Some(evt) = stream.next().await {
+        let evt = match evt {
+            Ok(evt) => evt,
+            Err(err) => {
+                error!("inotify read: {err}");
+                continue;
+            }
+        };
+
+        let Some(name) = evt.name else {
+            continue;
+        };
+
+        let Some(dir) = watches.lock().unwrap().get(&evt.wd).cloned() else {
+            continue;
+        };
+
+        let filename = WorkloadPath::from(dir.join(name));
+
+        let open = OpenEvent {
+            cgroup_name: None,
+            filename,
+            access_kind: AccessKind::Open,
+            sentinel: None,
+        };
+
+        ch.push(open);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use assert2::assert;
+
+    use super::*;
+    use crate::open_event_queue;
+
+    // Builds a raw `EvtOpen` record the same shape probes.bpf.c writes into
+    // the perf/ring buffer, so `decode_open_event` can be exercised without
+    // a live skel.
+    fn evt_open_bytes(pid: u32, filename: &str, access_kind: u8) -> Vec<u8> {
+        let mut evt = EvtOpen {
+            pid: pid.to_ne_bytes(),
+            filename: [0; 256],
+            access_kind,
+        };
+
+        for (dst, src) in evt
+            .filename
+            .iter_mut()
+            .zip(filename.bytes().chain(std::iter::once(0)))
+        {
+            *dst = src as c_char;
+        }
+
+        let ptr = &evt as *const EvtOpen as *const u8;
+        unsafe { slice::from_raw_parts(ptr, size_of::<EvtOpen>()) }.to_vec()
+    }
+
+    #[test]
+    fn test_decode_open_event() {
+        let buf = evt_open_bytes(42, "/bin/sh", 1);
+
+        let event = decode_open_event(&buf, |pid| {
+            assert!(pid == 42);
+            Ok(Some(OsString::from("my-cgroup")))
+        });
+
+        assert!(event.filename.as_raw() == Path::new("/bin/sh"));
+        assert!(event.access_kind == AccessKind::Exec);
+        assert!(event.cgroup_name == Some(OsString::from("my-cgroup")));
+        assert!(event.sentinel.is_none());
+    }
+
+    #[test]
+    fn test_decode_open_event_unknown_access_kind_defaults_to_open() {
+        let buf = evt_open_bytes(1, "/etc/passwd", 99);
+
+        let event = decode_open_event(&buf, |_| Ok(None));
+
+        assert!(event.access_kind == AccessKind::Open);
+    }
+
+    #[test]
+    fn test_decode_open_event_cgroup_lookup_error_yields_no_cgroup() {
+        let buf = evt_open_bytes(7, "/lib/libc.so", 0);
+
+        let event = decode_open_event(&buf, |_| Err(anyhow!("no such pid")));
+
+        assert!(event.cgroup_name.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mock_open_monitor_tracks_add_remove_paths() {
+        let (tx, _rx) = open_event_queue::channel(16);
+        let monitor = MockOpenMonitor::new(tx);
+        let handle = monitor.handle();
+
+        let path = RootFsPath::from(PathBuf::from("/proc/123/root"));
+        monitor.add_path(&path).unwrap();
+        monitor.remove_path(&path).unwrap();
+
+        assert!(handle.added_paths() == vec![PathBuf::from("/proc/123/root")]);
+        assert!(handle.removed_paths() == vec![PathBuf::from("/proc/123/root")]);
+    }
+
+    #[tokio::test]
+    async fn test_mock_open_monitor_handle_pushes_open_events() {
+        let (tx, mut rx) = open_event_queue::channel(16);
+        let monitor = MockOpenMonitor::new(tx);
+        let handle = monitor.handle();
+
+        handle.push_open(
+            Some(OsString::from("cg")),
+            WorkloadPath::from(PathBuf::from("/bin/ls")),
+        );
+
+        let event = rx.recv().await.unwrap();
+        assert!(event.cgroup_name == Some(OsString::from("cg")));
+        assert!(event.filename.as_raw() == Path::new("/bin/ls"));
+        assert!(event.sentinel.is_none());
+    }
+}
@@ -1,130 +1,410 @@
 use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 
 use anyhow::{anyhow, Result};
 use async_stream::stream;
-use futures::stream::StreamExt;
-use futures::Stream;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use crc32c::crc32c;
+use hkdf::Hkdf;
 use log::*;
+use prost::Message;
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc, oneshot, Notify};
 use tokio::task::JoinHandle;
 use tonic::codegen::InterceptedService;
 use tonic::metadata::AsciiMetadataValue;
 use tonic::service::Interceptor;
-use tonic::transport::{Channel, Uri};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity, Uri};
 use tonic::{Request, Status};
 
 pub mod pb {
     tonic::include_proto!("edgebit.agent.v1alpha");
 }
 
+use pb::command_service_client::CommandServiceClient;
 use pb::inventory_service_client::InventoryServiceClient;
 use pb::token_service_client::TokenServiceClient;
 
+use crate::backoff::DecorrelatedJitter;
+use crate::config::{ClientTls, SbomCompression};
 use crate::registry::PkgRef;
+use crate::repo::{Repo, SledRepo};
+use crate::sbom::SbomFormat;
 use crate::version::VERSION;
 
 const EXPIRATION_SLACK: Duration = Duration::from_secs(10 * 60);
 const DEFAULT_EXPIRATION: Duration = Duration::from_secs(60 * 60);
-const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_CAP: Duration = Duration::from_secs(60);
+const REPLAY_INTERVAL_BASE: Duration = Duration::from_secs(5);
+const REPLAY_INTERVAL_CAP: Duration = Duration::from_secs(60);
+
+const TOKEN_FILE: &str = "/var/lib/edgebit/token";
+
+// Prefixes an encrypted token file so `load_refresh_token` can tell it apart
+// from a plaintext one left over from before this file's format changed.
+const TOKEN_MAGIC: &[u8] = b"EBT1";
+
+// Per-install salt for `derive_key`, kept separate from the token itself so
+// a copy of the token file alone isn't enough to derive the key it was
+// sealed with.
+const SALT_FILE: &str = "/var/lib/edgebit/token.salt";
+const SALT_LEN: usize = 16;
+
+// Bounds the number of not-yet-acked in-use reports spooled to disk, so a
+// prolonged EdgeBit outage on a host with many churning containers can't
+// grow the spool without limit. Once over the cap, the oldest spooled
+// entries are dropped to make room for newer ones.
+const MAX_SPOOLED_IN_USE: usize = 256;
+
+// Spool queue names, one per kind of durably-queued inventory call.
+const QUEUE_UPLOAD_SBOM: &str = "upload_sbom";
+const QUEUE_UPSERT_WORKLOAD: &str = "upsert_workload";
+const QUEUE_REPORT_IN_USE: &str = "report_in_use";
+
+type InventorySvc = InventoryServiceClient<InterceptedService<Channel, AuthToken>>;
+type CommandSvc = CommandServiceClient<InterceptedService<Channel, AuthToken>>;
+
+impl From<SbomFormat> for pb::SbomFormat {
+    fn from(format: SbomFormat) -> Self {
+        match format {
+            SbomFormat::Syft => pb::SbomFormat::Syft,
+            SbomFormat::Spdx => pb::SbomFormat::Spdx,
+            SbomFormat::CycloneDx => pb::SbomFormat::CycloneDx,
+        }
+    }
+}
+
+impl From<SbomCompression> for pb::SbomCompression {
+    fn from(compression: SbomCompression) -> Self {
+        match compression {
+            SbomCompression::None => pb::SbomCompression::None,
+            SbomCompression::Gzip => pb::SbomCompression::Gzip,
+            SbomCompression::Zstd => pb::SbomCompression::Zstd,
+        }
+    }
+}
+
+fn compress_sbom(bytes: &[u8], compression: SbomCompression) -> Result<Vec<u8>> {
+    match compression {
+        SbomCompression::None => Ok(bytes.to_vec()),
+        SbomCompression::Gzip => {
+            let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            std::io::Write::write_all(&mut enc, bytes)?;
+            Ok(enc.finish()?)
+        }
+        SbomCompression::Zstd => Ok(zstd::stream::encode_all(bytes, 0)?),
+    }
+}
+
+// The spool only has room for raw bytes, so the detected format is tagged
+// onto the front of the spooled value; otherwise a replay after a restart
+// would forget whether the bytes were Syft, SPDX or CycloneDX JSON and
+// upload them under the wrong header.
+fn encode_spooled_sbom(format: SbomFormat, bytes: &[u8]) -> Vec<u8> {
+    let tag: u8 = match format {
+        SbomFormat::Syft => 0,
+        SbomFormat::Spdx => 1,
+        SbomFormat::CycloneDx => 2,
+    };
+
+    let mut spooled = Vec::with_capacity(bytes.len() + 1);
+    spooled.push(tag);
+    spooled.extend_from_slice(bytes);
+    spooled
+}
+
+fn decode_spooled_sbom(spooled: &[u8]) -> Result<(SbomFormat, Vec<u8>)> {
+    let (tag, bytes) = spooled
+        .split_first()
+        .ok_or_else(|| anyhow!("spooled SBOM entry is empty"))?;
+
+    let format = match tag {
+        0 => SbomFormat::Syft,
+        1 => SbomFormat::Spdx,
+        2 => SbomFormat::CycloneDx,
+        _ => return Err(anyhow!("spooled SBOM entry has an unrecognized format tag {tag}")),
+    };
+
+    Ok((format, bytes.to_vec()))
+}
+
+// Like `encode_spooled_sbom`, tags a spooled in-use report with the time it
+// was queued so an over-the-cap spool can be trimmed oldest-first.
+fn encode_spooled_in_use(req: &pb::ReportInUseRequest) -> Vec<u8> {
+    let spooled_at_ms = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    let mut spooled = Vec::with_capacity(8 + req.encoded_len());
+    spooled.extend_from_slice(&spooled_at_ms.to_be_bytes());
+    req.encode(&mut spooled).expect("Vec<u8> grows on demand");
+    spooled
+}
+
+fn decode_spooled_in_use(spooled: &[u8]) -> Result<(u64, pb::ReportInUseRequest)> {
+    if spooled.len() < 8 {
+        return Err(anyhow!("spooled in-use report is too short"));
+    }
+
+    let (ts, bytes) = spooled.split_at(8);
+    let spooled_at_ms = u64::from_be_bytes(ts.try_into().unwrap());
+    let req = pb::ReportInUseRequest::decode(bytes)?;
+
+    Ok((spooled_at_ms, req))
+}
+
+// Evicts the oldest spooled in-use reports once the queue grows past
+// `MAX_SPOOLED_IN_USE`, so a prolonged outage can't grow the on-disk spool
+// without bound.
+fn evict_oldest_in_use(repo: &dyn Repo) {
+    let entries = match repo.drain(QUEUE_REPORT_IN_USE) {
+        Ok(entries) => entries,
+        Err(err) => {
+            error!("Failed to read spooled in-use reports for eviction: {err}");
+            return;
+        }
+    };
+
+    if entries.len() <= MAX_SPOOLED_IN_USE {
+        return;
+    }
+
+    let mut by_age: Vec<(u64, String)> = entries
+        .into_iter()
+        .filter_map(|(workload_id, spooled)| match decode_spooled_in_use(&spooled) {
+            Ok((spooled_at_ms, _)) => Some((spooled_at_ms, workload_id)),
+            Err(_) => Some((0, workload_id)),
+        })
+        .collect();
+
+    by_age.sort_by_key(|(spooled_at_ms, _)| *spooled_at_ms);
+
+    let to_drop = by_age.len() - MAX_SPOOLED_IN_USE;
+    for (_, workload_id) in by_age.into_iter().take(to_drop) {
+        warn!("In-use report spool is over capacity, dropping oldest entry for {workload_id}");
+        _ = repo.remove(QUEUE_REPORT_IN_USE, &workload_id);
+    }
+}
+
+// A command pushed down by the control plane over the command stream,
+// translated into something `main` can act on without depending on `pb`
+// directly. `reply` carries the outcome back to `command_loop_task` so it
+// can be reported to the server as a `CommandResult`.
+pub struct CommandRequest {
+    pub command_id: String,
+    pub kind: CommandKind,
+    pub reply: oneshot::Sender<Result<()>>,
+}
+
+pub enum CommandKind {
+    RegenerateSbom,
+    ResyncWorkloads,
+    SetInUseInterval(u32),
+}
 
 pub struct Client {
-    inventory_svc: InventoryServiceClient<InterceptedService<Channel, AuthToken>>,
+    inventory_svc: InventorySvc,
+    repo: Arc<dyn Repo>,
     sess_keeper_task: JoinHandle<()>,
+    replay_task: JoinHandle<()>,
+    command_task: JoinHandle<()>,
+    sbom_compression: SbomCompression,
 }
 
 impl Client {
     pub async fn connect(
         endpoint: Uri,
-        deploy_token: String,
+        deploy_token: SecretString,
+        tls: Option<ClientTls>,
         hostname: String,
         machine_id: String,
+        spool_dir: PathBuf,
+        cmd_tx: mpsc::Sender<CommandRequest>,
+        sbom_compression: SbomCompression,
     ) -> Result<Self> {
-        let channel = Channel::builder(endpoint).connect().await?;
+        let mut builder = Channel::builder(endpoint);
+        if let Some(tls) = tls {
+            builder = builder.tls_config(build_tls_config(&tls)?)?;
+        }
 
-        let mut token = enroll_loop(
-            channel.clone(),
-            deploy_token.clone(),
-            hostname.clone(),
-            machine_id.clone(),
-        )
-        .await;
+        let channel = builder.connect().await?;
+
+        // A previously persisted refresh token lets the agent resume its
+        // existing session instead of enrolling (and minting a brand new
+        // identity) on every restart; any rejection -- tampered file, token
+        // revoked server-side -- falls back to full enrollment.
+        let mut token = match load_refresh_token(&machine_id, &hostname) {
+            Ok(refresh_token) => match fetch_session_token(channel.clone(), &refresh_token).await {
+                Ok(tok) => tok,
+                Err(err) => {
+                    warn!("Stored refresh token was rejected, re-enrolling: {err}");
+                    enroll_loop(
+                        channel.clone(),
+                        deploy_token.clone(),
+                        hostname.clone(),
+                        machine_id.clone(),
+                    )
+                    .await?
+                }
+            },
+            Err(_) => {
+                enroll_loop(
+                    channel.clone(),
+                    deploy_token.clone(),
+                    hostname.clone(),
+                    machine_id.clone(),
+                )
+                .await?
+            }
+        };
+
+        save_refresh_token(&token.refresh_token, &machine_id, &hostname)
+            .unwrap_or_else(|err| error!("Error saving agent refresh token: {err}"));
 
-        let auth_token = AuthToken::new(&token.session_token);
+        let auth_token = AuthToken::new(token.session_token.expose_secret())?;
 
         let inventory_svc =
             InventoryServiceClient::with_interceptor(channel.clone(), auth_token.clone());
 
-        let sess_keeper_task = tokio::task::spawn(async move {
-            while let Err(err) = refresh_loop(
-                channel.clone(),
-                token.refresh_token.clone(),
-                auth_token.clone(),
-                token.expiration,
-            )
-            .await
-            {
-                error!("Session renewal failed: {err}");
+        // Lets a RotateSessionToken command wake refresh_loop up immediately
+        // instead of waiting for the token to near its natural expiration.
+        let rotate = Arc::new(Notify::new());
+        let command_auth_token = auth_token.clone();
+        let command_channel = channel.clone();
 
-                // try re-enrolling
-                token = enroll_loop(
+        let sess_keeper_task = tokio::task::spawn({
+            let rotate = rotate.clone();
+            async move {
+                while let Err(err) = refresh_loop(
                     channel.clone(),
-                    deploy_token.clone(),
-                    hostname.clone(),
-                    machine_id.clone(),
+                    token.refresh_token.clone(),
+                    auth_token.clone(),
+                    token.expiration,
+                    rotate.clone(),
                 )
-                .await;
-                auth_token.set(&token.session_token);
+                .await
+                {
+                    if matches!(err.classify(), Retriability::Fatal) {
+                        error!("Session renewal failed fatally, giving up: {err}");
+                        break;
+                    }
+
+                    warn!("Session expired or invalid, re-enrolling: {err}");
+
+                    token = match enroll_loop(
+                        channel.clone(),
+                        deploy_token.clone(),
+                        hostname.clone(),
+                        machine_id.clone(),
+                    )
+                    .await
+                    {
+                        Ok(token) => token,
+                        Err(err) => {
+                            error!(
+                                "Re-enrollment failed fatally, giving up on session renewal: {err}"
+                            );
+                            break;
+                        }
+                    };
+
+                    save_refresh_token(&token.refresh_token, &machine_id, &hostname)
+                        .unwrap_or_else(|err| error!("Error saving agent refresh token: {err}"));
+
+                    if let Err(err) = auth_token.set(token.session_token.expose_secret()) {
+                        error!(
+                            "Re-enrolled session token is invalid, giving up on session renewal: {err}"
+                        );
+                        break;
+                    }
+                }
             }
         });
 
+        let repo: Arc<dyn Repo> = Arc::new(SledRepo::open(&spool_dir)?);
+
+        let replay_task = tokio::task::spawn(replay_loop(inventory_svc.clone(), repo.clone()));
+
+        let command_task = tokio::task::spawn(command_loop_task(
+            command_channel,
+            command_auth_token,
+            cmd_tx,
+            rotate,
+        ));
+
         Ok(Self {
             inventory_svc,
+            repo,
             sess_keeper_task,
+            replay_task,
+            command_task,
+            sbom_compression,
         })
     }
 
+    // Spools the SBOM durably before attempting to send it, so it survives
+    // an agent restart or a control-plane outage instead of being dropped;
+    // the replay task retries it until it's acknowledged.
     pub async fn upload_sbom(
         &mut self,
         image_id: String,
-        sbom_reader: std::fs::File,
+        format: SbomFormat,
+        mut sbom_reader: std::fs::File,
     ) -> Result<()> {
-        // Header first
-        let header_req = pb::UploadSbomRequest {
-            kind: Some(pb::upload_sbom_request::Kind::Header(
-                pb::UploadSbomHeader {
-                    format: pb::SbomFormat::Syft as i32,
-                    image_id,
-                    image: Some(pb::Image {
-                        kind: Some(pb::image::Kind::Generic(pb::GenericImage {})),
-                    }),
-                },
-            )),
-        };
+        let mut bytes = Vec::new();
+        sbom_reader.read_to_end(&mut bytes)?;
 
-        let header_stream = futures::stream::once(futures::future::ready(header_req));
+        self.repo.put(
+            QUEUE_UPLOAD_SBOM,
+            &image_id,
+            encode_spooled_sbom(format, &bytes),
+        )?;
 
-        // TODO: There must be a simpler way to deal with a stream causing an error
-        let result = Arc::new(Mutex::new(Result::Ok(())));
-        let stream = header_stream.chain(data_stream(sbom_reader, result.clone()));
-
-        self.inventory_svc
-            .upload_sbom(stream)
-            .await
-            .map_err(|e| anyhow!("{}", e.message()))?;
-
-        std::sync::Arc::<std::sync::Mutex<Result<(), anyhow::Error>>>::try_unwrap(result)
-            .unwrap()
-            .into_inner()
-            .unwrap()
+        match send_sbom(
+            &mut self.inventory_svc,
+            image_id.clone(),
+            format,
+            bytes,
+            self.sbom_compression,
+        )
+        .await
+        {
+            Ok(()) => {
+                _ = self.repo.remove(QUEUE_UPLOAD_SBOM, &image_id);
+                Ok(())
+            }
+            Err(err) => {
+                warn!("Failed to upload SBOM for {image_id}, will retry from spool: {err}");
+                Ok(())
+            }
+        }
     }
 
     pub async fn upsert_workload(&mut self, workload: pb::UpsertWorkloadRequest) -> Result<()> {
-        self.inventory_svc
-            .upsert_workload(workload)
-            .await
-            .map_err(|e| anyhow!("{}", e.message()))?;
-        Ok(())
+        let workload_id = workload.workload_id.clone();
+        self.repo
+            .put(QUEUE_UPSERT_WORKLOAD, &workload_id, workload.encode_to_vec())?;
+
+        match send_upsert_workload(&mut self.inventory_svc, workload).await {
+            Ok(()) => {
+                _ = self.repo.remove(QUEUE_UPSERT_WORKLOAD, &workload_id);
+                crate::metrics::UPSERT_WORKLOAD_OK.inc();
+                Ok(())
+            }
+            Err(err) => {
+                warn!("Failed to upsert workload {workload_id}, will retry from spool: {err}");
+                crate::metrics::UPSERT_WORKLOAD_ERR.inc();
+                Ok(())
+            }
+        }
     }
 
     pub async fn report_in_use(&mut self, workload_id: String, pkgs: Vec<PkgRef>) -> Result<()> {
@@ -142,15 +422,34 @@ impl Client {
 
         let req = pb::ReportInUseRequest {
             in_use,
-            workload_id,
+            workload_id: workload_id.clone(),
         };
 
         trace!("ReportInUse: {req:?}");
-        self.inventory_svc
-            .report_in_use(req)
-            .await
-            .map_err(|e| anyhow!("{}", e.message()))?;
-        Ok(())
+
+        self.repo
+            .put(QUEUE_REPORT_IN_USE, &workload_id, encode_spooled_in_use(&req))?;
+        evict_oldest_in_use(self.repo.as_ref());
+
+        match send_report_in_use(&mut self.inventory_svc, req).await {
+            Ok(()) => {
+                _ = self.repo.remove(QUEUE_REPORT_IN_USE, &workload_id);
+                crate::metrics::REPORT_IN_USE_OK.inc();
+                Ok(())
+            }
+            Err(err) => {
+                warn!("Failed to report in-use packages for {workload_id}, will retry from spool: {err}");
+                crate::metrics::REPORT_IN_USE_ERR.inc();
+                Ok(())
+            }
+        }
+    }
+
+    // Number of in-use reports still waiting to be acked by the server,
+    // so callers (e.g. the heartbeat path) can tell a quiet agent from one
+    // that's just draining a backlog after an outage.
+    pub fn pending_report_in_use(&self) -> usize {
+        self.repo.len(QUEUE_REPORT_IN_USE).unwrap_or(0)
     }
 
     pub async fn reset_workloads(&mut self) -> Result<()> {
@@ -167,6 +466,330 @@ impl Client {
     pub async fn stop(self) {
         self.sess_keeper_task.abort();
         _ = self.sess_keeper_task.await;
+
+        self.replay_task.abort();
+        _ = self.replay_task.await;
+
+        self.command_task.abort();
+        _ = self.command_task.await;
+    }
+}
+
+async fn send_sbom(
+    svc: &mut InventorySvc,
+    image_id: String,
+    format: SbomFormat,
+    bytes: Vec<u8>,
+    compression: SbomCompression,
+) -> Result<()> {
+    // Syft/SPDX documents are highly compressible JSON, so the wire payload
+    // (and everything downstream of it -- chunking, the CRC32C/SHA-256
+    // checks, the resume offset) is the *compressed* bytes, not the
+    // original SBOM; the server reverses this once in `upload_sbom`.
+    let payload = compress_sbom(&bytes, compression)?;
+    let sha256 = Sha256::digest(&payload).to_vec();
+
+    // A retried upload of the exact same SBOM (same digest) after a dropped
+    // connection resumes from wherever the control plane last committed
+    // bytes, rather than re-sending a payload that can be large.
+    let offset = svc
+        .get_sbom_upload_offset(pb::GetSbomUploadOffsetRequest {
+            image_id: image_id.clone(),
+            sha256: sha256.clone(),
+        })
+        .await
+        .map(|resp| resp.into_inner().offset as usize)
+        .unwrap_or(0)
+        .min(payload.len());
+
+    let header = pb::UploadSbomRequest {
+        kind: Some(pb::upload_sbom_request::Kind::Header(
+            pb::UploadSbomHeader {
+                format: pb::SbomFormat::from(format) as i32,
+                image_id,
+                image: Some(pb::Image {
+                    kind: Some(pb::image::Kind::Generic(pb::GenericImage {})),
+                }),
+                sha256: sha256.clone(),
+                offset: offset as u64,
+                compression: pb::SbomCompression::from(compression) as i32,
+            },
+        )),
+    };
+
+    // Each chunk carries its own CRC32C so the server can catch corruption
+    // as it arrives instead of only at the very end, and the whole payload
+    // is verified again by `sha256` in the trailer once every chunk is in.
+    let chunks = payload[offset..]
+        .chunks(64 * 1024)
+        .map(|chunk| pb::UploadSbomRequest {
+            kind: Some(pb::upload_sbom_request::Kind::Data(pb::SbomDataChunk {
+                bytes: chunk.to_vec(),
+                crc32c: crc32c(chunk),
+            })),
+        })
+        .collect::<Vec<_>>();
+
+    let trailer = pb::UploadSbomRequest {
+        kind: Some(pb::upload_sbom_request::Kind::Trailer(
+            pb::UploadSbomTrailer { sha256 },
+        )),
+    };
+
+    let stream = futures::stream::iter(
+        std::iter::once(header).chain(chunks).chain(std::iter::once(trailer)),
+    );
+
+    svc.upload_sbom(stream)
+        .await
+        .map_err(|e| anyhow!("{}", e.message()))?;
+
+    Ok(())
+}
+
+async fn send_upsert_workload(svc: &mut InventorySvc, req: pb::UpsertWorkloadRequest) -> Result<()> {
+    svc.upsert_workload(req)
+        .await
+        .map_err(|e| anyhow!("{}", e.message()))?;
+    Ok(())
+}
+
+async fn send_report_in_use(svc: &mut InventorySvc, req: pb::ReportInUseRequest) -> Result<()> {
+    svc.report_in_use(req)
+        .await
+        .map_err(|e| anyhow!("{}", e.message()))?;
+    Ok(())
+}
+
+// Periodically drains the spooled inventory calls and replays them against
+// the control plane. Reuses the live session, so a call queued while
+// unauthenticated is retried with whatever valid session token
+// `sess_keeper_task` has since obtained.
+//
+// Paced with decorrelated-jitter backoff rather than a fixed interval: a
+// pass that replays at least one entry resets the backoff to its base so a
+// draining backlog catches up quickly, while a pass that replays nothing
+// (either an empty spool or a still-down control plane) backs off up to
+// `REPLAY_INTERVAL_CAP` instead of polling sled every few seconds forever.
+async fn replay_loop(mut svc: InventorySvc, repo: Arc<dyn Repo>) {
+    let mut backoff = DecorrelatedJitter::new(REPLAY_INTERVAL_BASE, REPLAY_INTERVAL_CAP);
+
+    loop {
+        let mut replayed_any = false;
+
+        match repo.drain(QUEUE_UPLOAD_SBOM) {
+            Ok(entries) => {
+                for (image_id, spooled) in entries {
+                    let (format, bytes) = match decode_spooled_sbom(&spooled) {
+                        Ok(decoded) => decoded,
+                        Err(err) => {
+                            error!("Dropping corrupt spooled SBOM for {image_id}: {err}");
+                            _ = repo.remove(QUEUE_UPLOAD_SBOM, &image_id);
+                            continue;
+                        }
+                    };
+
+                    match send_sbom(&mut svc, image_id.clone(), format, bytes).await {
+                        Ok(()) => {
+                            _ = repo.remove(QUEUE_UPLOAD_SBOM, &image_id);
+                            replayed_any = true;
+                        }
+                        Err(err) => debug!("Replay of SBOM upload for {image_id} still failing: {err}"),
+                    }
+                }
+            }
+            Err(err) => error!("Failed to read spooled SBOM uploads: {err}"),
+        }
+
+        match repo.drain(QUEUE_UPSERT_WORKLOAD) {
+            Ok(entries) => {
+                for (workload_id, bytes) in entries {
+                    let req = match pb::UpsertWorkloadRequest::decode(bytes.as_slice()) {
+                        Ok(req) => req,
+                        Err(err) => {
+                            error!("Dropping corrupt spooled workload upsert for {workload_id}: {err}");
+                            _ = repo.remove(QUEUE_UPSERT_WORKLOAD, &workload_id);
+                            continue;
+                        }
+                    };
+
+                    match send_upsert_workload(&mut svc, req).await {
+                        Ok(()) => {
+                            _ = repo.remove(QUEUE_UPSERT_WORKLOAD, &workload_id);
+                            replayed_any = true;
+                        }
+                        Err(err) => {
+                            debug!("Replay of workload upsert for {workload_id} still failing: {err}")
+                        }
+                    }
+                }
+            }
+            Err(err) => error!("Failed to read spooled workload upserts: {err}"),
+        }
+
+        match repo.drain(QUEUE_REPORT_IN_USE) {
+            Ok(entries) => {
+                for (workload_id, spooled) in entries {
+                    let req = match decode_spooled_in_use(&spooled) {
+                        Ok((_, req)) => req,
+                        Err(err) => {
+                            error!("Dropping corrupt spooled in-use report for {workload_id}: {err}");
+                            _ = repo.remove(QUEUE_REPORT_IN_USE, &workload_id);
+                            continue;
+                        }
+                    };
+
+                    match send_report_in_use(&mut svc, req).await {
+                        Ok(()) => {
+                            _ = repo.remove(QUEUE_REPORT_IN_USE, &workload_id);
+                            replayed_any = true;
+                        }
+                        Err(err) => {
+                            debug!("Replay of in-use report for {workload_id} still failing: {err}")
+                        }
+                    }
+                }
+            }
+            Err(err) => error!("Failed to read spooled in-use reports: {err}"),
+        }
+
+        if replayed_any {
+            backoff.reset();
+        }
+
+        tokio::time::sleep(backoff.next_delay()).await;
+    }
+}
+
+// Keeps a long-lived, bidirectional CommandLoop stream open so the control
+// plane can push Commands at any time. Dispatch is handed off to `main` via
+// `cmd_tx` rather than acted on here, since `main` owns the state (SBOM
+// generation, workload tracking, the report-in-use interval) that commands
+// need to touch. Reconnects with the same decorrelated-jitter backoff used
+// for enrollment.
+async fn command_loop_task(
+    channel: Channel,
+    auth_token: AuthToken,
+    cmd_tx: mpsc::Sender<CommandRequest>,
+    rotate: Arc<Notify>,
+) {
+    let mut backoff = DecorrelatedJitter::new(BACKOFF_BASE, BACKOFF_CAP);
+
+    loop {
+        let mut cmd_svc: CommandSvc =
+            CommandServiceClient::with_interceptor(channel.clone(), auth_token.clone());
+
+        let (result_tx, mut result_rx) = mpsc::channel::<pb::CommandResult>(16);
+        let outbound = stream! {
+            while let Some(result) = result_rx.recv().await {
+                yield result;
+            }
+        };
+
+        let mut inbound = match cmd_svc.command_loop(outbound).await {
+            Ok(resp) => resp.into_inner(),
+            Err(err) => {
+                warn!("Failed to open command stream, retrying: {}", err.message());
+                tokio::time::sleep(backoff.next_delay()).await;
+                continue;
+            }
+        };
+
+        backoff.reset();
+        info!("Command stream connected");
+
+        loop {
+            match inbound.message().await {
+                Ok(Some(command)) => {
+                    let cmd_tx = cmd_tx.clone();
+                    let rotate = rotate.clone();
+                    let result_tx = result_tx.clone();
+                    tokio::task::spawn(async move {
+                        let result = dispatch_command(&cmd_tx, &rotate, command).await;
+                        _ = result_tx.send(result).await;
+                    });
+                }
+                Ok(None) => {
+                    warn!("Command stream closed by server, reconnecting");
+                    break;
+                }
+                Err(err) => {
+                    warn!("Command stream error, reconnecting: {}", err.message());
+                    break;
+                }
+            }
+        }
+
+        tokio::time::sleep(backoff.next_delay()).await;
+    }
+}
+
+async fn dispatch_command(
+    cmd_tx: &mpsc::Sender<CommandRequest>,
+    rotate: &Notify,
+    command: pb::Command,
+) -> pb::CommandResult {
+    let command_id = command.command_id;
+
+    // A RotateSessionToken is handled right here since Client already owns
+    // the session machinery; everything else is forwarded to `main`.
+    let kind = match command.kind {
+        Some(pb::command::Kind::RotateSessionToken(_)) => {
+            rotate.notify_one();
+            return pb::CommandResult {
+                command_id,
+                ok: true,
+                error: String::new(),
+            };
+        }
+        Some(pb::command::Kind::RegenerateSbom(_)) => CommandKind::RegenerateSbom,
+        Some(pb::command::Kind::ResyncWorkloads(_)) => CommandKind::ResyncWorkloads,
+        Some(pb::command::Kind::SetInUseInterval(req)) => {
+            CommandKind::SetInUseInterval(req.interval_secs)
+        }
+        // Forward-compatible with command kinds this agent doesn't know
+        // about yet: ack it rather than erroring out.
+        None => {
+            debug!("Ignoring command {command_id} with an unrecognized kind");
+            return pb::CommandResult {
+                command_id,
+                ok: true,
+                error: String::new(),
+            };
+        }
+    };
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let req = CommandRequest {
+        command_id: command_id.clone(),
+        kind,
+        reply: reply_tx,
+    };
+
+    if cmd_tx.send(req).await.is_err() {
+        return pb::CommandResult {
+            command_id,
+            ok: false,
+            error: "agent has no command handler registered".to_string(),
+        };
+    }
+
+    match reply_rx.await {
+        Ok(Ok(())) => pb::CommandResult {
+            command_id,
+            ok: true,
+            error: String::new(),
+        },
+        Ok(Err(err)) => pb::CommandResult {
+            command_id,
+            ok: false,
+            error: err.to_string(),
+        },
+        Err(_) => pb::CommandResult {
+            command_id,
+            ok: false,
+            error: "command handler dropped the reply channel".to_string(),
+        },
     }
 }
 
@@ -176,20 +799,21 @@ struct AuthToken {
 }
 
 impl AuthToken {
-    fn new(token: &str) -> Self {
-        let bearer = format_bearer(token);
+    fn new(token: &str) -> Result<Self> {
+        let bearer = format_bearer(token)?;
 
-        Self {
+        Ok(Self {
             inner: Arc::new(Mutex::new(bearer)),
-        }
+        })
     }
 
     fn bearer(&self) -> AsciiMetadataValue {
         self.inner.lock().unwrap().clone()
     }
 
-    fn set(&self, token: &str) {
-        *self.inner.lock().unwrap() = format_bearer(token);
+    fn set(&self, token: &str) -> Result<()> {
+        *self.inner.lock().unwrap() = format_bearer(token)?;
+        Ok(())
     }
 }
 
@@ -202,55 +826,123 @@ impl Interceptor for AuthToken {
     }
 }
 
-fn format_bearer(val: &str) -> AsciiMetadataValue {
-    // val must be ASCII
-    format!("Bearer {val}").parse().unwrap()
+// A session token containing a byte that isn't a valid HTTP header value
+// (e.g. a stray control character from a buggy or compromised control-plane
+// response) is a malformed response, not a condition worth panicking the
+// session-refresh task over -- surfaced as an error so callers can treat it
+// the same as any other failed renewal.
+fn format_bearer(val: &str) -> Result<AsciiMetadataValue> {
+    format!("Bearer {val}")
+        .parse()
+        .map_err(|_| anyhow!("session token is not valid ASCII"))
 }
 
 struct EnrolledToken {
-    refresh_token: String,
-    session_token: String,
+    refresh_token: SecretString,
+    session_token: SecretString,
     expiration: SystemTime,
 }
 
+// Retriability of a failed call to the control plane, classified from the
+// tonic::Status code it returned.
+enum Retriability {
+    // Worth retrying after a backoff: the server or network is having a
+    // bad time, but the same request could succeed later.
+    Transient,
+    // The session is gone; re-enrolling should fix it immediately.
+    ReAuth,
+    // Retrying can't possibly help (e.g. a bad deployment token); surface
+    // the error instead of spinning forever.
+    Fatal,
+}
+
+#[derive(thiserror::Error, Debug)]
+enum RpcError {
+    #[error("{0}")]
+    Status(#[from] tonic::Status),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl RpcError {
+    fn classify(&self) -> Retriability {
+        let RpcError::Status(status) = self else {
+            return Retriability::Transient;
+        };
+
+        match status.code() {
+            tonic::Code::Unavailable
+            | tonic::Code::DeadlineExceeded
+            | tonic::Code::ResourceExhausted => Retriability::Transient,
+            tonic::Code::Unauthenticated => Retriability::ReAuth,
+            tonic::Code::InvalidArgument
+            | tonic::Code::PermissionDenied
+            | tonic::Code::NotFound => Retriability::Fatal,
+            _ => Retriability::Transient,
+        }
+    }
+}
+
 async fn enroll(
     channel: Channel,
-    deploy_token: String,
+    deploy_token: SecretString,
     hostname: String,
     machine_id: String,
-) -> Result<EnrolledToken> {
+) -> Result<EnrolledToken, RpcError> {
     let mut token_svc = TokenServiceClient::new(channel);
 
     let req = pb::EnrollAgentRequest {
-        deployment_token: deploy_token,
+        deployment_token: deploy_token.expose_secret().to_string(),
         hostname,
         agent_version: VERSION.to_string(),
         machine_id,
     };
 
-    let resp = token_svc
-        .enroll_agent(req)
-        .await
-        .map_err(|e| anyhow!("{}", e.message()))?
-        .into_inner();
+    let resp = token_svc.enroll_agent(req).await?.into_inner();
+
+    Ok(EnrolledToken {
+        refresh_token: resp.refresh_token.into(),
+        session_token: resp.session_token.into(),
+        expiration: get_expiration(resp.session_token_expiration),
+    })
+}
 
-    // ensure the token is ascii
-    _ = AsciiMetadataValue::try_from(&resp.session_token)
-        .map_err(|_| anyhow!("session token is not ASCII"))?;
+// Exchanges a persisted refresh token for a session token without going
+// through a fresh enrollment. Used once at startup to resume the previous
+// session from `load_refresh_token`; unlike `refresh_loop`'s copy of this
+// RPC call, there's no session token yet to authenticate the request with,
+// so this one goes out over an un-intercepted client.
+async fn fetch_session_token(
+    channel: Channel,
+    refresh_token: &SecretString,
+) -> Result<EnrolledToken, RpcError> {
+    let mut token_svc = TokenServiceClient::new(channel);
+
+    let req = pb::GetSessionTokenRequest {
+        refresh_token: refresh_token.expose_secret().to_string(),
+        agent_version: VERSION.to_string(),
+    };
+
+    let resp = token_svc.get_session_token(req).await?.into_inner();
 
     Ok(EnrolledToken {
-        refresh_token: resp.refresh_token,
-        session_token: resp.session_token,
+        refresh_token: resp.refresh_token.into(),
+        session_token: resp.session_token.into(),
         expiration: get_expiration(resp.session_token_expiration),
     })
 }
 
+// Retries enrollment with decorrelated-jitter backoff. Gives up and
+// surfaces the error if the control plane rejects the deployment token
+// itself, since retrying that can never succeed.
 async fn enroll_loop(
     channel: Channel,
-    deploy_token: String,
+    deploy_token: SecretString,
     hostname: String,
     machine_id: String,
-) -> EnrolledToken {
+) -> Result<EnrolledToken> {
+    let mut backoff = DecorrelatedJitter::new(BACKOFF_BASE, BACKOFF_CAP);
+
     loop {
         match enroll(
             channel.clone(),
@@ -260,10 +952,14 @@ async fn enroll_loop(
         )
         .await
         {
-            Ok(tok) => return tok,
+            Ok(tok) => return Ok(tok),
             Err(err) => {
-                error!("Agent enrollment failed: {err}");
-                tokio::time::sleep(RETRY_INTERVAL).await;
+                if matches!(err.classify(), Retriability::Fatal) {
+                    return Err(anyhow!("Agent enrollment rejected: {err}"));
+                }
+
+                error!("Agent enrollment failed, retrying: {err}");
+                tokio::time::sleep(backoff.next_delay()).await;
             }
         }
     }
@@ -271,11 +967,13 @@ async fn enroll_loop(
 
 async fn refresh_loop(
     channel: Channel,
-    refresh_token: String,
+    refresh_token: SecretString,
     auth_token: AuthToken,
     mut expiration: SystemTime,
-) -> Result<()> {
+    rotate: Arc<Notify>,
+) -> Result<(), RpcError> {
     let mut token_svc = TokenServiceClient::with_interceptor(channel, auth_token.clone());
+    let mut backoff = DecorrelatedJitter::new(BACKOFF_BASE, BACKOFF_CAP);
 
     loop {
         let mut deadline = expiration - EXPIRATION_SLACK;
@@ -287,24 +985,35 @@ async fn refresh_loop(
         let dt: chrono::DateTime<chrono::Utc> = deadline.into();
         info!("Next session renewal at {}", dt.to_rfc2822());
 
-        sleep_until(deadline).await;
+        tokio::select! {
+            _ = sleep_until(deadline) => {}
+            _ = rotate.notified() => {
+                info!("Forcing session token rotation");
+            }
+        }
 
         let req = pb::GetSessionTokenRequest {
-            refresh_token: refresh_token.clone(),
+            refresh_token: refresh_token.expose_secret().to_string(),
             agent_version: VERSION.to_string(),
         };
 
-        let resp = token_svc
-            .get_session_token(req)
-            .await
-            .map_err(|e| anyhow!("{}", e.message()))?
-            .into_inner();
+        let resp = loop {
+            match token_svc.get_session_token(req.clone()).await {
+                Ok(resp) => break resp.into_inner(),
+                Err(status) => {
+                    let err = RpcError::from(status);
+                    if !matches!(err.classify(), Retriability::Transient) {
+                        return Err(err);
+                    }
 
-        // ensure the token is ascii
-        _ = AsciiMetadataValue::try_from(&resp.session_token)
-            .map_err(|_| anyhow!("session token is not ASCII"))?;
+                    warn!("Session renewal attempt failed, retrying: {err}");
+                    tokio::time::sleep(backoff.next_delay()).await;
+                }
+            }
+        };
+        backoff.reset();
 
-        auth_token.set(&resp.session_token);
+        auth_token.set(&resp.session_token)?;
         expiration = get_expiration(resp.session_token_expiration);
 
         info!("Session renewed");
@@ -327,35 +1036,6 @@ fn get_expiration(expiration: Option<prost_types::Timestamp>) -> SystemTime {
     }
 }
 
-fn data_stream<'a, R: Read + Send + 'a>(
-    mut rd: R,
-    result: Arc<Mutex<Result<()>>>,
-) -> impl Stream<Item = pb::UploadSbomRequest> + Send {
-    stream! {
-        let mut buf = vec![0u8; 64*1024];
-        loop {
-            match rd.read(&mut buf) {
-                Ok(0) => break,
-                Ok(n) => {
-                    yield pb::UploadSbomRequest{
-                        kind: Some(pb::upload_sbom_request::Kind::Data(buf[0..n].to_vec())),
-                    };
-                },
-                Err(e) => {
-                    match e.kind() {
-                        std::io::ErrorKind::Interrupted => continue,
-                        kind => {
-                            use std::ops::DerefMut;
-                            *(result.lock().unwrap().deref_mut()) = Err(anyhow!("io error: {kind}"));
-                            break;
-                        },
-                    }
-                }
-            }
-        }
-    }
-}
-
 async fn sleep_until(deadline: SystemTime) {
     // Avoid sleeping for more than a minute.
     // On virtualized machines, time is not always accurately kept
@@ -364,3 +1044,136 @@ async fn sleep_until(deadline: SystemTime) {
         tokio::time::sleep(dur).await;
     }
 }
+
+// Builds tonic's TLS config from whichever of the CA/client cert/key are
+// configured; a custom CA tightens server verification beyond the system
+// roots, a client cert+key pair additionally presents mTLS credentials.
+fn build_tls_config(tls: &ClientTls) -> Result<ClientTlsConfig> {
+    let mut config = ClientTlsConfig::new();
+
+    if let Some(ca_cert) = &tls.ca_cert {
+        let pem = std::fs::read(ca_cert)
+            .map_err(|err| anyhow!("reading TLS CA cert {}: {err}", ca_cert.display()))?;
+        config = config.ca_certificate(Certificate::from_pem(pem));
+    }
+
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(cert), Some(key)) => {
+            let cert_pem = std::fs::read(cert)
+                .map_err(|err| anyhow!("reading TLS client cert {}: {err}", cert.display()))?;
+            let key_pem = std::fs::read(key)
+                .map_err(|err| anyhow!("reading TLS client key {}: {err}", key.display()))?;
+            config = config.identity(Identity::from_pem(cert_pem, key_pem));
+        }
+        (None, None) => (),
+        _ => return Err(anyhow!("TLS client cert and key must both be set, or neither")),
+    }
+
+    Ok(config)
+}
+
+// A stolen token file shouldn't by itself be enough to impersonate the
+// agent, so the refresh token is sealed with an AEAD keyed off this host's
+// identity (its machine-id plus a random per-install salt, run through
+// HKDF) with the hostname bound in as associated data. Only the refresh
+// token is persisted -- the session token it's exchanged for is short-lived
+// and kept in memory only (see `EnrolledToken`). `load_refresh_token` fails
+// closed: a tampered or transplanted-from-another-host file fails the auth
+// tag check and is treated the same as "no token", triggering re-enrollment.
+// The decrypted value is immediately wrapped in a `SecretString` so it's
+// zeroized on drop and never shows up if `EnrolledToken` or `Client` end up
+// in a log line.
+fn load_refresh_token(machine_id: &str, hostname: &str) -> Result<SecretString> {
+    let data = std::fs::read(TOKEN_FILE)?;
+
+    match data.strip_prefix(TOKEN_MAGIC) {
+        Some(sealed) => decrypt_token(sealed, machine_id, hostname).map(SecretString::from),
+        None => {
+            // No magic header: a plaintext token left over from before this
+            // file started encrypting it. Accept it once, then immediately
+            // re-save it encrypted so every read after this one goes
+            // through the AEAD path.
+            let token: SecretString = std::str::from_utf8(&data)?.trim().to_string().into();
+            warn!("Migrating plaintext agent token at {TOKEN_FILE} to an encrypted one");
+            if let Err(err) = save_refresh_token(&token, machine_id, hostname) {
+                error!("Failed to re-save migrated agent token: {err}");
+            }
+            Ok(token)
+        }
+    }
+}
+
+fn save_refresh_token(token: &SecretString, machine_id: &str, hostname: &str) -> Result<()> {
+    let token_file = PathBuf::from(TOKEN_FILE);
+    let dir = token_file.parent().unwrap();
+    std::fs::create_dir_all(dir)?;
+
+    let mut sealed = TOKEN_MAGIC.to_vec();
+    sealed.extend(encrypt_token(token.expose_secret(), machine_id, hostname)?);
+
+    std::fs::write(TOKEN_FILE, &sealed)?;
+    restrict_to_root(TOKEN_FILE)
+}
+
+fn restrict_to_root(path: &str) -> Result<()> {
+    Ok(std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?)
+}
+
+fn load_or_create_salt() -> Result<Vec<u8>> {
+    if let Ok(salt) = std::fs::read(SALT_FILE) {
+        if salt.len() == SALT_LEN {
+            return Ok(salt);
+        }
+    }
+
+    let mut salt = vec![0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+
+    let salt_file = PathBuf::from(SALT_FILE);
+    let dir = salt_file.parent().unwrap();
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(SALT_FILE, &salt)?;
+    restrict_to_root(SALT_FILE)?;
+
+    Ok(salt)
+}
+
+fn derive_key(machine_id: &str) -> Result<chacha20poly1305::Key> {
+    let salt = load_or_create_salt()?;
+
+    let mut key_bytes = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), machine_id.trim().as_bytes())
+        .expand(b"edgebit-agent-token", &mut key_bytes)
+        .map_err(|_| anyhow!("HKDF key derivation failed"))?;
+
+    Ok(key_bytes.into())
+}
+
+fn encrypt_token(token: &str, machine_id: &str, hostname: &str) -> Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(&derive_key(machine_id)?);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: token.as_bytes(), aad: hostname.as_bytes() })
+        .map_err(|_| anyhow!("failed to encrypt agent token"))?;
+
+    let mut sealed = Vec::with_capacity(nonce.len() + ciphertext.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+fn decrypt_token(sealed: &[u8], machine_id: &str, hostname: &str) -> Result<String> {
+    let nonce_len = Nonce::default().len();
+    if sealed.len() < nonce_len {
+        return Err(anyhow!("token file is too short to contain a nonce"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(nonce_len);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(machine_id)?);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: hostname.as_bytes() })
+        .map_err(|_| anyhow!("failed to decrypt agent token: auth tag mismatch"))?;
+
+    Ok(String::from_utf8(plaintext)?)
+}
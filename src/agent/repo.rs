@@ -0,0 +1,65 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+// A durable store-and-forward queue for outbound inventory calls, modeled
+// after pict-rs's repository abstraction: `Client` only depends on this
+// trait, so the on-disk backend can be swapped without touching the RPC
+// call sites in platform.rs.
+//
+// Entries are addressed by an application-chosen key (an image_id or
+// workload_id) so that re-`put`ting the same key replaces the previous
+// entry instead of piling up stale duplicates, e.g. a newer SBOM upload
+// for an image supersedes an older still-pending one.
+pub trait Repo: Send + Sync {
+    fn put(&self, queue: &str, key: &str, value: Vec<u8>) -> Result<()>;
+
+    fn drain(&self, queue: &str) -> Result<Vec<(String, Vec<u8>)>>;
+
+    fn remove(&self, queue: &str, key: &str) -> Result<()>;
+
+    fn len(&self, queue: &str) -> Result<usize>;
+}
+
+pub struct SledRepo {
+    db: sled::Db,
+}
+
+impl SledRepo {
+    pub fn open(path: &Path) -> Result<Self> {
+        std::fs::create_dir_all(path)?;
+        let db = sled::open(path)?;
+
+        Ok(Self { db })
+    }
+}
+
+impl Repo for SledRepo {
+    fn put(&self, queue: &str, key: &str, value: Vec<u8>) -> Result<()> {
+        self.db.open_tree(queue)?.insert(key, value)?;
+        Ok(())
+    }
+
+    fn drain(&self, queue: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        self.db
+            .open_tree(queue)?
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let key = String::from_utf8(key.to_vec())
+                    .map_err(|_| anyhow!("spooled entry has a non-utf8 key"))?;
+
+                Ok((key, value.to_vec()))
+            })
+            .collect()
+    }
+
+    fn remove(&self, queue: &str, key: &str) -> Result<()> {
+        self.db.open_tree(queue)?.remove(key)?;
+        Ok(())
+    }
+
+    fn len(&self, queue: &str) -> Result<usize> {
+        Ok(self.db.open_tree(queue)?.len())
+    }
+}
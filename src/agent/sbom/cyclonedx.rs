@@ -0,0 +1,135 @@
+use anyhow::Result;
+use serde::Deserialize;
+
+use super::{purl_artifact_type, Artifact, File, Metadata, SbomDoc, Source};
+
+#[derive(Deserialize)]
+struct CycloneDxDoc {
+    #[serde(rename = "serialNumber")]
+    serial_number: Option<String>,
+    components: Vec<CycloneDxComponent>,
+}
+
+#[derive(Deserialize)]
+struct CycloneDxComponent {
+    #[serde(rename = "bom-ref")]
+    bom_ref: Option<String>,
+    name: String,
+    purl: Option<String>,
+    evidence: Option<CycloneDxEvidence>,
+}
+
+#[derive(Deserialize)]
+struct CycloneDxEvidence {
+    #[serde(default)]
+    occurrences: Vec<CycloneDxOccurrence>,
+}
+
+#[derive(Deserialize)]
+struct CycloneDxOccurrence {
+    location: String,
+}
+
+pub(super) fn parse(doc: serde_json::Value) -> Result<SbomDoc> {
+    let doc: CycloneDxDoc = serde_json::from_value(doc)?;
+
+    let artifacts = doc
+        .components
+        .into_iter()
+        .filter_map(|comp| {
+            let (type_, metadata_type) = purl_artifact_type(comp.purl.as_deref()?)?;
+
+            let files = comp
+                .evidence
+                .map(|e| e.occurrences)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|occ| File {
+                    path: Some(occ.location),
+                })
+                .collect();
+
+            Some(Artifact {
+                id: comp.bom_ref.unwrap_or(comp.name),
+                type_,
+                metadata_type: Some(metadata_type.to_string()),
+                metadata: Some(Metadata {
+                    files: Some(files),
+                    site_packages_root_path: None,
+                }),
+            })
+        })
+        .collect();
+
+    let id = doc.serial_number.unwrap_or_default();
+
+    Ok(SbomDoc {
+        artifacts,
+        source: Source { id },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_component_with_evidence() {
+        let doc = json!({
+            "serialNumber": "urn:uuid:1234",
+            "components": [{
+                "bom-ref": "bash@5.1-2",
+                "name": "bash",
+                "purl": "pkg:deb/debian/bash@5.1-2?arch=amd64",
+                "evidence": {
+                    "occurrences": [{"location": "/usr/bin/bash"}]
+                }
+            }]
+        });
+
+        let sbom = parse(doc).unwrap();
+
+        assert!(sbom.source.id == "urn:uuid:1234");
+        assert!(sbom.artifacts.len() == 1);
+
+        let artifact = &sbom.artifacts[0];
+        assert!(artifact.id == "bash@5.1-2");
+        assert!(artifact.type_ == "deb");
+        assert!(artifact.metadata_type.as_deref() == Some("DpkgMetadata"));
+
+        let files = artifact.metadata.as_ref().unwrap().files.as_ref().unwrap();
+        assert!(files.len() == 1);
+        assert!(files[0].path.as_deref() == Some("/usr/bin/bash"));
+    }
+
+    #[test]
+    fn test_parse_falls_back_to_name_without_bom_ref() {
+        let doc = json!({
+            "components": [{
+                "name": "bash",
+                "purl": "pkg:deb/debian/bash@5.1-2"
+            }]
+        });
+
+        let sbom = parse(doc).unwrap();
+
+        assert!(sbom.source.id == "");
+        assert!(sbom.artifacts[0].id == "bash");
+    }
+
+    #[test]
+    fn test_parse_skips_components_without_a_purl() {
+        let doc = json!({
+            "components": [{
+                "name": "unidentified-file"
+            }]
+        });
+
+        let sbom = parse(doc).unwrap();
+
+        assert!(sbom.artifacts.is_empty());
+    }
+}
@@ -1,3 +1,6 @@
+mod cyclonedx;
+mod spdx;
+
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -91,18 +94,35 @@ async fn generate_with_chroot(
     Ok(sbom)
 }
 
+// Which SBOM schema a document was detected as. Mirrors `pb::SbomFormat` on
+// the wire, but is kept independent of `pb` so this module doesn't have to
+// depend on `platform`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SbomFormat {
+    Syft,
+    Spdx,
+    CycloneDx,
+}
+
 pub struct Sbom {
     doc: SbomDoc,
+    format: SbomFormat,
 }
 
 impl Sbom {
     pub fn load(path: &RootFsPath) -> Result<Self> {
         let file = std::fs::File::open(path.as_raw())?;
         let reader = BufReader::new(file);
+        let raw: serde_json::Value = serde_json::from_reader(reader)?;
 
-        Ok(Self {
-            doc: serde_json::from_reader(reader)?,
-        })
+        let format = detect_format(&raw);
+        let doc = match format {
+            SbomFormat::Syft => serde_json::from_value(raw)?,
+            SbomFormat::Spdx => spdx::parse(raw)?,
+            SbomFormat::CycloneDx => cyclonedx::parse(raw)?,
+        };
+
+        Ok(Self { doc, format })
     }
 
     pub fn artifacts(&self) -> &Vec<Artifact> {
@@ -112,6 +132,20 @@ impl Sbom {
     pub fn id(&self) -> String {
         self.doc.source.id.clone()
     }
+
+    pub fn format(&self) -> SbomFormat {
+        self.format
+    }
+}
+
+fn detect_format(doc: &serde_json::Value) -> SbomFormat {
+    if doc.get("bomFormat").and_then(|v| v.as_str()) == Some("CycloneDX") {
+        SbomFormat::CycloneDx
+    } else if doc.get("spdxVersion").is_some() {
+        SbomFormat::Spdx
+    } else {
+        SbomFormat::Syft
+    }
 }
 
 #[derive(Deserialize)]
@@ -140,6 +174,11 @@ impl Artifact {
             "deb" => (PackageType::Deb, "DpkgMetadata"),
             "rpm" => (PackageType::Rpm, "RpmMetadata"),
             "python" => (PackageType::Python, "PythonPackageMetadata"),
+            "npm" => (PackageType::Npm, "JavaScriptNpmPackageMetadata"),
+            "go-module" => (PackageType::GoModule, "GolangBinMetadata"),
+            "rust-crate" => (PackageType::RustCrate, "RustCargoPackageMetadata"),
+            "gem" => (PackageType::Gem, "GemMetadata"),
+            "apk" => (PackageType::Apk, "ApkMetadata"),
             _ => return Err(anyhow!("'{}' is an unsupported artifact type", self.type_)),
         };
 
@@ -180,7 +219,13 @@ impl Metadata {
     ) -> Result<Vec<WorkloadPath>> {
         match self.files {
             Some(ref files) => match pkg_type {
-                PackageType::Rpm | PackageType::Deb => generic_files(files, host_root),
+                PackageType::Rpm
+                | PackageType::Deb
+                | PackageType::Npm
+                | PackageType::GoModule
+                | PackageType::RustCrate
+                | PackageType::Gem
+                | PackageType::Apk => generic_files(files, host_root),
                 PackageType::Python => python_files(files, self, host_root),
             },
             None => Ok(Vec::new()),
@@ -197,6 +242,11 @@ pub enum PackageType {
     Rpm,
     Deb,
     Python,
+    Npm,
+    GoModule,
+    RustCrate,
+    Gem,
+    Apk,
 }
 
 fn generic_files(files: &[File], host_root: &RootFsPath) -> Result<Vec<WorkloadPath>> {
@@ -245,3 +295,25 @@ fn normalize(host_root: &RootFsPath, path: &WorkloadPath) -> WorkloadPath {
         Err(_) => path.clone(),
     }
 }
+
+// Shared by the `spdx` and `cyclonedx` parsers: both identify a package's
+// ecosystem via its PURL (e.g. "pkg:deb/debian/bash@5.1-2") rather than
+// Syft's own `type`/`metadataType` fields. Maps the PURL type to the
+// `Artifact.type_`/`metadata_type` pair `files()` already knows how to
+// resolve; an unrecognized PURL type is passed through as-is so `files()`
+// reports it as an unsupported artifact type, same as it would for Syft.
+fn purl_artifact_type(purl: &str) -> Option<(String, &'static str)> {
+    let purl_type = purl.strip_prefix("pkg:")?.split('/').next()?;
+
+    Some(match purl_type {
+        "deb" => ("deb".to_string(), "DpkgMetadata"),
+        "rpm" => ("rpm".to_string(), "RpmMetadata"),
+        "pypi" => ("python".to_string(), "PythonPackageMetadata"),
+        "npm" => ("npm".to_string(), "JavaScriptNpmPackageMetadata"),
+        "golang" => ("go-module".to_string(), "GolangBinMetadata"),
+        "cargo" => ("rust-crate".to_string(), "RustCargoPackageMetadata"),
+        "gem" => ("gem".to_string(), "GemMetadata"),
+        "apk" => ("apk".to_string(), "ApkMetadata"),
+        other => (other.to_string(), ""),
+    })
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use super::{purl_artifact_type, Artifact, File, Metadata, SbomDoc, Source};
+
+// Just the fields we need out of an SPDX JSON document. Packages reference
+// their files indirectly through `hasFiles`/SPDXID, rather than embedding
+// paths inline the way Syft does, so the top-level `files` array has to be
+// joined in separately.
+#[derive(Deserialize)]
+struct SpdxDoc {
+    #[serde(rename = "documentNamespace")]
+    document_namespace: Option<String>,
+    name: Option<String>,
+    packages: Vec<SpdxPackage>,
+    #[serde(default)]
+    files: Vec<SpdxFile>,
+}
+
+#[derive(Deserialize)]
+struct SpdxPackage {
+    #[serde(rename = "SPDXID")]
+    spdx_id: String,
+    #[serde(rename = "externalRefs", default)]
+    external_refs: Vec<SpdxExternalRef>,
+    #[serde(rename = "hasFiles", default)]
+    has_files: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct SpdxExternalRef {
+    #[serde(rename = "referenceType")]
+    reference_type: String,
+    #[serde(rename = "referenceLocator")]
+    reference_locator: String,
+}
+
+#[derive(Deserialize)]
+struct SpdxFile {
+    #[serde(rename = "SPDXID")]
+    spdx_id: String,
+    #[serde(rename = "fileName")]
+    file_name: String,
+    // Distinguishes e.g. "BINARY" from "SOURCE"; not filtered on today but
+    // parsed so the schema mapping stays explicit.
+    #[serde(rename = "fileTypes", default)]
+    #[allow(dead_code)]
+    file_types: Vec<String>,
+}
+
+pub(super) fn parse(doc: serde_json::Value) -> Result<SbomDoc> {
+    let doc: SpdxDoc = serde_json::from_value(doc)?;
+
+    let files_by_id: HashMap<&str, &str> = doc
+        .files
+        .iter()
+        .map(|f| (f.spdx_id.as_str(), f.file_name.as_str()))
+        .collect();
+
+    let artifacts = doc
+        .packages
+        .into_iter()
+        .filter_map(|pkg| {
+            let purl = pkg
+                .external_refs
+                .iter()
+                .find(|r| r.reference_type == "purl")?
+                .reference_locator
+                .as_str();
+            let (type_, metadata_type) = purl_artifact_type(purl)?;
+
+            let files = pkg
+                .has_files
+                .iter()
+                .filter_map(|id| files_by_id.get(id.as_str()))
+                .map(|path| File {
+                    path: Some((*path).to_string()),
+                })
+                .collect();
+
+            Some(Artifact {
+                id: pkg.spdx_id,
+                type_,
+                metadata_type: Some(metadata_type.to_string()),
+                metadata: Some(Metadata {
+                    files: Some(files),
+                    site_packages_root_path: None,
+                }),
+            })
+        })
+        .collect();
+
+    let id = doc.document_namespace.or(doc.name).unwrap_or_default();
+
+    Ok(SbomDoc {
+        artifacts,
+        source: Source { id },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::assert;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_package_with_files() {
+        let doc = json!({
+            "documentNamespace": "https://example.com/spdx/doc-1",
+            "packages": [{
+                "SPDXID": "SPDXRef-Package-bash",
+                "externalRefs": [{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": "pkg:deb/debian/bash@5.1-2?arch=amd64"
+                }],
+                "hasFiles": ["SPDXRef-File-bash", "SPDXRef-File-missing"]
+            }],
+            "files": [{
+                "SPDXID": "SPDXRef-File-bash",
+                "fileName": "/usr/bin/bash",
+                "fileTypes": ["BINARY"]
+            }]
+        });
+
+        let sbom = parse(doc).unwrap();
+
+        assert!(sbom.source.id == "https://example.com/spdx/doc-1");
+        assert!(sbom.artifacts.len() == 1);
+
+        let artifact = &sbom.artifacts[0];
+        assert!(artifact.id == "SPDXRef-Package-bash");
+        assert!(artifact.type_ == "deb");
+        assert!(artifact.metadata_type.as_deref() == Some("DpkgMetadata"));
+
+        let files = artifact.metadata.as_ref().unwrap().files.as_ref().unwrap();
+        assert!(files.len() == 1);
+        assert!(files[0].path.as_deref() == Some("/usr/bin/bash"));
+    }
+
+    #[test]
+    fn test_parse_skips_packages_without_a_purl() {
+        let doc = json!({
+            "name": "fallback-doc-name",
+            "packages": [{
+                "SPDXID": "SPDXRef-Package-no-purl",
+                "externalRefs": []
+            }]
+        });
+
+        let sbom = parse(doc).unwrap();
+
+        assert!(sbom.source.id == "fallback-doc-name");
+        assert!(sbom.artifacts.is_empty());
+    }
+}
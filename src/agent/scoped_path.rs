@@ -1,6 +1,7 @@
 use std::path::{PathBuf, Path, Display};
 use std::ffi::{CStr, OsStr};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
 // Relative to the host rootfs
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -68,7 +69,7 @@ impl <T: Into<PathBuf>> From<T> for RootFsPath {
 }
 
 // Relative to the workload root
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct WorkloadPath(PathBuf);
 
 impl WorkloadPath {
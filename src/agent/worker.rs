@@ -0,0 +1,161 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::*;
+use tokio::task::JoinHandle;
+
+use crate::backoff::DecorrelatedJitter;
+
+const RESTART_BACKOFF_BASE: Duration = Duration::from_millis(500);
+const RESTART_BACKOFF_CAP: Duration = Duration::from_secs(60);
+
+// One iteration's worth of progress a `Worker` made. `WorkerManager` uses
+// this to decide when to call `step()` again: immediately for `Busy`, after
+// `next_run` for `Idle`, and never again for `Done`.
+#[derive(Clone, Debug)]
+pub enum WorkerState {
+    Busy,
+    Idle { next_run: Instant },
+    Done,
+}
+
+// A unit of background agent concurrency. Implementors own whatever state
+// they need (channel receivers, timers, clients) and advance it one step at
+// a time rather than looping internally, so `WorkerManager` can supervise
+// them uniformly -- restarting on error, tracking run counts, and reporting
+// status -- instead of each one being a bare, unsupervised `tokio::spawn`.
+#[async_trait]
+pub trait Worker: Send {
+    fn name(&self) -> &str;
+
+    async fn step(&mut self) -> Result<WorkerState>;
+}
+
+// A point-in-time snapshot of one worker's health, returned by
+// `WorkerManager::statuses()`.
+#[derive(Clone, Debug)]
+pub struct WorkerStatus {
+    pub name: String,
+    pub state: WorkerState,
+    pub last_error: Option<String>,
+    pub consecutive_errors: u32,
+    pub runs: u64,
+}
+
+struct Supervised {
+    status: Arc<Mutex<WorkerStatus>>,
+    task: JoinHandle<()>,
+}
+
+// Owns every background worker's join handle and last-known status, so the
+// agent has one inspectable place to ask "is anything stuck or dying"
+// instead of that information being lost the moment a bare `tokio::spawn`
+// task panics or returns.
+#[derive(Default)]
+pub struct WorkerManager {
+    workers: Vec<Supervised>,
+}
+
+impl WorkerManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Spawns `worker` and supervises it for the lifetime of the manager: a
+    // `Done` ends it cleanly, an `Err` is logged and retried with
+    // decorrelated-jitter backoff instead of letting the task vanish.
+    pub fn spawn<W: Worker + 'static>(&mut self, mut worker: W) {
+        let status = Arc::new(Mutex::new(WorkerStatus {
+            name: worker.name().to_string(),
+            state: WorkerState::Busy,
+            last_error: None,
+            consecutive_errors: 0,
+            runs: 0,
+        }));
+
+        let task_status = status.clone();
+        let task = tokio::task::spawn(async move {
+            let mut backoff = DecorrelatedJitter::new(RESTART_BACKOFF_BASE, RESTART_BACKOFF_CAP);
+
+            loop {
+                match worker.step().await {
+                    Ok(WorkerState::Done) => {
+                        task_status.lock().unwrap().state = WorkerState::Done;
+                        break;
+                    }
+                    Ok(state) => {
+                        let mut status = task_status.lock().unwrap();
+                        let idle_until = match &state {
+                            WorkerState::Idle { next_run } => Some(*next_run),
+                            _ => None,
+                        };
+                        status.state = state;
+                        status.runs += 1;
+                        status.consecutive_errors = 0;
+                        drop(status);
+
+                        backoff.reset();
+
+                        if let Some(next_run) = idle_until {
+                            tokio::time::sleep_until(tokio::time::Instant::from_std(next_run)).await;
+                        }
+                    }
+                    Err(err) => {
+                        let mut status = task_status.lock().unwrap();
+                        status.last_error = Some(err.to_string());
+                        status.consecutive_errors += 1;
+                        let name = status.name.clone();
+                        let attempt = status.consecutive_errors;
+                        drop(status);
+
+                        let delay = backoff.next_delay();
+                        error!("Worker '{name}' failed (attempt {attempt}), restarting in {delay:?}: {err}");
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+            }
+        });
+
+        self.workers.push(Supervised { status, task });
+    }
+
+    pub fn statuses(&self) -> Vec<WorkerStatus> {
+        self.workers
+            .iter()
+            .map(|w| w.status.lock().unwrap().clone())
+            .collect()
+    }
+
+    // A cheap, clonable handle onto every worker's live status, so something
+    // like an admin endpoint can poll it repeatedly without owning (or
+    // racing `stop()` for) the `WorkerManager` itself.
+    pub fn registry(&self) -> WorkerRegistry {
+        WorkerRegistry {
+            statuses: self.workers.iter().map(|w| w.status.clone()).collect(),
+        }
+    }
+
+    // Aborts every supervised worker, e.g. on agent shutdown.
+    pub async fn stop(self) {
+        for worker in self.workers {
+            worker.task.abort();
+            _ = worker.task.await;
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct WorkerRegistry {
+    statuses: Vec<Arc<Mutex<WorkerStatus>>>,
+}
+
+impl WorkerRegistry {
+    pub fn snapshot(&self) -> Vec<WorkerStatus> {
+        self.statuses
+            .iter()
+            .map(|s| s.lock().unwrap().clone())
+            .collect()
+    }
+}
@@ -11,6 +11,7 @@ use crate::containers::ContainerInfo;
 use crate::open_monitor::FileOpenMonitorArc;
 use crate::scoped_path::*;
 
+use super::checkpoint::CheckpointSeed;
 use super::PathSet;
 
 struct ContainerWorkload {
@@ -21,7 +22,7 @@ struct ContainerWorkload {
 }
 
 impl ContainerWorkload {
-    fn new(root: RootFsPath, excludes: &[PathBuf]) -> Result<Self> {
+    fn new(root: RootFsPath, excludes: &[PathBuf], reported_seed: Vec<WorkloadPath>) -> Result<Self> {
         let mut exclude_set = PathSet::new()?;
         for path in excludes {
             let path = WorkloadPath::from(path);
@@ -39,10 +40,15 @@ impl ContainerWorkload {
             };
         }
 
+        let mut reported = LruCache::new(super::REPORTED_LRU_SIZE);
+        for path in reported_seed {
+            reported.put(path, ());
+        }
+
         Ok(Self {
             root,
             excludes: exclude_set,
-            reported: LruCache::new(super::REPORTED_LRU_SIZE),
+            reported,
             in_use_batch: Vec::new(),
         })
     }
@@ -94,20 +100,34 @@ impl ContainerWorkload {
     fn flush_in_use(&mut self) -> Vec<WorkloadPath> {
         self.in_use_batch.split_off(0)
     }
+
+    fn reported_snapshot(&self) -> Vec<WorkloadPath> {
+        self.reported.iter().map(|(path, _)| path.clone()).collect()
+    }
+}
+
+// Per-container counts exposed by `ContainerWorkloads::counts`, for the
+// admin endpoint.
+pub struct WorkloadCounts {
+    pub id: String,
+    pub reported: usize,
+    pub pending_in_use: usize,
 }
 
 pub struct ContainerWorkloads {
     config: Arc<Config>,
     workloads: HashMap<String, ContainerWorkload>,
     open_monitor: FileOpenMonitorArc,
+    checkpoint: CheckpointSeed,
 }
 
 impl ContainerWorkloads {
-    pub fn new(config: Arc<Config>, open_mon: FileOpenMonitorArc) -> Self {
+    pub fn new(config: Arc<Config>, open_mon: FileOpenMonitorArc, checkpoint: CheckpointSeed) -> Self {
         Self {
             config,
             workloads: HashMap::new(),
             open_monitor: open_mon,
+            checkpoint,
         }
     }
 
@@ -118,6 +138,7 @@ impl ContainerWorkloads {
             workload.file_opened(filename);
         } else {
             error!("Container workload missing for id={id}");
+            crate::metrics::DROPPED_OPEN_EVENTS.inc();
         }
     }
 
@@ -128,7 +149,8 @@ impl ContainerWorkloads {
                 let mut excludes = self.config.container_excludes();
                 excludes.append(&mut info.mounts);
 
-                match ContainerWorkload::new(rootfs, &excludes) {
+                let reported_seed = self.checkpoint.container_reported(&id);
+                match ContainerWorkload::new(rootfs, &excludes, reported_seed) {
                     Ok(workload) => {
                         for path in workload.watchset() {
                             _ = self.open_monitor.add_path(&path);
@@ -162,4 +184,31 @@ impl ContainerWorkloads {
 
         in_use
     }
+
+    // Ids of containers still tracked as running, so a graceful shutdown
+    // can report them stopped even though they're still up (the agent is
+    // the one going away, not the container).
+    pub fn ids(&self) -> Vec<String> {
+        self.workloads.keys().cloned().collect()
+    }
+
+    // Snapshot of every tracked container's reported set, for checkpointing.
+    pub fn reported_snapshot(&self) -> HashMap<String, Vec<WorkloadPath>> {
+        self.workloads
+            .iter()
+            .map(|(id, w)| (id.clone(), w.reported_snapshot()))
+            .collect()
+    }
+
+    // Per-container reported/pending-in-use counts, for the admin endpoint.
+    pub fn counts(&self) -> Vec<WorkloadCounts> {
+        self.workloads
+            .iter()
+            .map(|(id, w)| WorkloadCounts {
+                id: id.clone(),
+                reported: w.reported.len(),
+                pending_in_use: w.in_use_batch.len(),
+            })
+            .collect()
+    }
 }
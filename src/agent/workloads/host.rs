@@ -41,6 +41,7 @@ impl HostWorkload {
         config: Arc<Config>,
         open_mon: FileOpenMonitorArc,
         labels: HashMap<String, String>,
+        reported_seed: Vec<WorkloadPath>,
     ) -> Result<Self> {
         let host_root = RootFsPath::from(config.host_root());
         let id = load_baseos_id();
@@ -79,6 +80,11 @@ impl HostWorkload {
             };
         }
 
+        let mut reported = LruCache::new(REPORTED_LRU_SIZE);
+        for path in reported_seed {
+            reported.put(path, ());
+        }
+
         Ok(Self {
             id,
             labels,
@@ -88,7 +94,7 @@ impl HostWorkload {
             pkgs: Registry::new(),
             host_root,
             includes,
-            reported: LruCache::new(REPORTED_LRU_SIZE),
+            reported,
             in_use_batch: Vec::new(),
         })
     }
@@ -104,9 +110,11 @@ impl HostWorkload {
                     if !pkgs.is_empty() {
                         self.in_use_batch.append(&mut pkgs);
                     }
+
+                    crate::metrics::FILES_RESOLVED.inc();
                 }
             }
-            Ok(None) => (),
+            Ok(None) => crate::metrics::FILES_FILTERED.inc(),
             Err(err) => super::resolve_failed(filename, err),
         }
     }
@@ -115,6 +123,28 @@ impl HostWorkload {
         (self.id.clone(), self.in_use_batch.split_off(0))
     }
 
+    // Snapshot of everything currently reported, for checkpointing.
+    pub fn reported_snapshot(&self) -> Vec<WorkloadPath> {
+        self.reported.iter().map(|(path, _)| path.clone()).collect()
+    }
+
+    // For the admin endpoint's per-workload counts.
+    pub fn reported_count(&self) -> usize {
+        self.reported.len()
+    }
+
+    pub fn pending_in_use(&self) -> usize {
+        self.in_use_batch.len()
+    }
+
+    // Atomically replaces the package registry with one rebuilt from a
+    // freshly re-scanned SBOM, so packages installed/upgraded since the
+    // last scan start matching report-in-use lookups.
+    pub fn swap_registry(&mut self, image_id: String, pkgs: Registry) {
+        self.image_id = image_id;
+        self.pkgs = pkgs;
+    }
+
     // Checks if the path is not filtered out and returns canonicalized verison
     fn resolve(&self, path: &WorkloadPath) -> Result<Option<WorkloadPath>> {
         let rp = path.to_rootfs(&self.host_root).realpath()?;
@@ -134,7 +164,13 @@ impl HostWorkload {
 
     // Returns true if the file was already reported
     fn check_and_mark_reported(&mut self, filename: WorkloadPath) -> bool {
-        self.reported.put(filename, ()).is_some()
+        let already_reported = self.reported.put(filename, ()).is_some();
+
+        if already_reported {
+            crate::metrics::FILES_ALREADY_REPORTED.inc();
+        }
+
+        already_reported
     }
 }
 
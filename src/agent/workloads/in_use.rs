@@ -1,71 +1,79 @@
-use std::sync::{Arc, Mutex};
-use std::collections::VecDeque;
-use std::time::{Instant, Duration};
+use std::sync::Arc;
 
+use anyhow::Result;
+use async_trait::async_trait;
 use log::*;
-use tokio::sync::mpsc::Receiver;
 
 use crate::containers::Containers;
-use crate::open_monitor::OpenEvent;
+use crate::open_event_queue::OpenEventReceiver;
+use crate::open_monitor::{OpenEvent, OpenEventBarriers};
+use crate::worker::{Worker, WorkerState};
 use super::Workloads;
 
-const OPEN_EVENT_LAG: Duration = Duration::from_millis(500);
-
-struct OpenEventQueueItem {
-    timestamp: Instant,
-    evt: OpenEvent,
+// Resolves incoming file-open events against the container or host
+// workload they belong to. Registered as a `Worker` rather than a bare
+// task. Sentinels injected via `barriers` are resolved in-place rather
+// than attributed to a workload - popping one here, in order, is what
+// proves every real open enqueued ahead of it has already been handled.
+pub struct PkgsInUseWorker {
+    containers: Arc<Containers>,
+    workloads: Workloads,
+    rx: OpenEventReceiver,
+    barriers: OpenEventBarriers,
 }
 
-pub async fn track_pkgs_in_use(containers: Arc<Containers>, workloads: Workloads, mut rx: Receiver<OpenEvent>) {
-    let mut open_event_q = Mutex::new(VecDeque::<OpenEventQueueItem>::new());
+impl PkgsInUseWorker {
+    pub fn new(
+        containers: Arc<Containers>,
+        workloads: Workloads,
+        rx: OpenEventReceiver,
+        barriers: OpenEventBarriers,
+    ) -> Self {
+        Self {
+            containers,
+            workloads,
+            rx,
+            barriers,
+        }
+    }
+}
 
-    let mut periods = tokio::time::interval(Duration::from_millis(100));
+#[async_trait]
+impl Worker for PkgsInUseWorker {
+    fn name(&self) -> &str {
+        "pkgs-in-use"
+    }
 
-    loop {
-        tokio::select!{
-            _ = periods.tick() => {
-                let cutoff = Instant::now()
-                    .checked_sub(OPEN_EVENT_LAG)
-                    .unwrap();
+    async fn step(&mut self) -> Result<WorkerState> {
+        match self.rx.recv().await {
+            Some(evt) => {
+                if let Some(id) = evt.sentinel {
+                    self.barriers.resolve(id);
+                    return Ok(WorkerState::Busy);
+                }
 
-                while let Some(evt) = pop_open_event(&mut open_event_q, cutoff) {
-                    let cgroup = evt.cgroup_name.unwrap_or(String::new());
-                    trace!("[{cgroup}]: {}", evt.filename.display());
+                // `id_from_cgroup` only needs the name to match a regex, so a
+                // lossy conversion here is fine; non-UTF-8 cgroup names are
+                // exceedingly rare and at worst fail to match, same as today.
+                let cgroup = evt
+                    .cgroup_name
+                    .map(|c| c.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                trace!("[{cgroup}]: {}", evt.filename.display());
 
-                    if let Some(id) = containers.id_from_cgroup(&cgroup) {
-                        workloads.containers.lock()
-                            .unwrap()
-                            .file_opened(&id, &evt.filename)
-                    } else {
-                        workloads.host.lock()
-                            .unwrap()
-                            .file_opened(&evt.filename)
-                    }
-                }
-            },
-            evt = rx.recv() => {
-                match evt {
-                    Some(evt) => {
-                        open_event_q.lock()
-                            .unwrap()
-                            .push_back(OpenEventQueueItem{
-                                    timestamp: Instant::now(),
-                                    evt,
-                                });
-                    },
-                    None => break,
+                if let Some(id) = self.containers.id_from_cgroup(&cgroup) {
+                    self.workloads
+                        .containers
+                        .lock()
+                        .unwrap()
+                        .file_opened(&id, &evt.filename)
+                } else {
+                    self.workloads.host.lock().unwrap().file_opened(&evt.filename)
                 }
+
+                Ok(WorkerState::Busy)
             }
+            None => Ok(WorkerState::Done),
         }
     }
 }
-
-fn pop_open_event(q: &mut Mutex<VecDeque<OpenEventQueueItem>>, cutoff: Instant) -> Option<OpenEvent> {
-    let q = q.get_mut().unwrap();
-    if q.front()?.timestamp > cutoff {
-        None
-    } else {
-        q.pop_front()
-            .map(|item| item.evt)
-    }
-}
\ No newline at end of file
@@ -1,25 +1,38 @@
+pub mod checkpoint;
 pub mod containers;
 pub mod host;
 pub mod in_use;
+pub mod report_loop;
+pub mod scrub;
 
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::num::NonZeroUsize;
+use std::time::Duration;
 
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use log::*;
-use anyhow::Result;
 use tokio::sync::mpsc::{Sender, Receiver};
 
-use crate::open_monitor::FileOpenMonitorArc;
+use crate::open_event_queue::OpenEventSender;
+use crate::open_monitor::{FileOpenMonitorArc, OpenEventBarriers};
 use crate::scoped_path::*;
 use crate::config::Config;
 use crate::containers::{ContainerEvent, ContainerInfo};
+use crate::worker::{Worker, WorkerState};
 
+use checkpoint::CheckpointSeed;
 use host::HostWorkload;
 use containers::ContainerWorkloads;
 
 pub(crate) const REPORTED_LRU_SIZE: NonZeroUsize = unsafe { NonZeroUsize::new_unchecked(256) };
 
+// How long ContainerLifecycleWorker waits for the open-event barrier to
+// confirm a new container's watch is armed before attributing opens to it
+// anyway. Only hit if the sentinel is ever lost (e.g. the queue is full).
+const OPEN_EVENT_BARRIER_TIMEOUT: Duration = Duration::from_millis(500);
+
 #[derive(Debug)]
 pub enum Event {
     ContainerStarted(String, ContainerInfo),
@@ -33,10 +46,15 @@ pub struct Workloads {
 }
 
 impl Workloads {
-    pub fn new(config: Arc<Config>, host: HostWorkload, open_mon: FileOpenMonitorArc) -> Self {
+    pub fn new(
+        config: Arc<Config>,
+        host: HostWorkload,
+        open_mon: FileOpenMonitorArc,
+        checkpoint: CheckpointSeed,
+    ) -> Self {
         Self {
             host: Arc::new(Mutex::new(host)),
-            containers: Arc::new(Mutex::new(ContainerWorkloads::new(config, open_mon)))
+            containers: Arc::new(Mutex::new(ContainerWorkloads::new(config, open_mon, checkpoint)))
         }
     }
 }
@@ -74,10 +92,12 @@ pub(crate) fn resolve_failed(filepath: &WorkloadPath, err: anyhow::Error) {
             // This almost exclusively occurs on data files
             if io_err.kind() != std::io::ErrorKind::NotFound {
                 info!("Failed to canonicalize {}: {io_err}", filepath.display());
+                crate::metrics::RESOLVE_FAILURES.inc();
             }
         },
         Err(err) => {
             info!("Failed to canonicalize {}: {err}", filepath.display());
+            crate::metrics::RESOLVE_FAILURES.inc();
         }
     }
 }
@@ -98,28 +118,84 @@ fn is_file(path: &RootFsPath) -> bool {
     }
 }
 
-pub async fn track_container_lifecycle(mut rx: Receiver<ContainerEvent>, workloads: Arc<Mutex<ContainerWorkloads>>, events: Sender<Event>) {
-    loop {
-        match rx.recv().await {
+// Drains container start/stop events into `ContainerWorkloads` and forwards
+// them on to `monitor()`'s event loop as a registered `Worker` instead of a
+// bare, unsupervised task.
+pub struct ContainerLifecycleWorker {
+    rx: Receiver<ContainerEvent>,
+    workloads: Arc<Mutex<ContainerWorkloads>>,
+    events: Sender<Event>,
+    open_ch: OpenEventSender,
+    barriers: OpenEventBarriers,
+}
+
+impl ContainerLifecycleWorker {
+    pub fn new(
+        rx: Receiver<ContainerEvent>,
+        workloads: Arc<Mutex<ContainerWorkloads>>,
+        events: Sender<Event>,
+        open_ch: OpenEventSender,
+        barriers: OpenEventBarriers,
+    ) -> Self {
+        Self {
+            rx,
+            workloads,
+            events,
+            open_ch,
+            barriers,
+        }
+    }
+}
+
+#[async_trait]
+impl Worker for ContainerLifecycleWorker {
+    fn name(&self) -> &str {
+        "container-lifecycle"
+    }
+
+    async fn step(&mut self) -> Result<WorkerState> {
+        match self.rx.recv().await {
             Some(ContainerEvent::Started(id, info)) => {
-                workloads.lock()
+                self.workloads
+                    .lock()
                     .unwrap()
                     .container_started(id.clone(), info.clone());
 
-                if let Err(err) = events.send(Event::ContainerStarted(id, info)).await {
-                    error!("Failed to send events on a channel: {err}");
+                // Don't report this container as started until its
+                // open-event watch is provably armed, so an open racing the
+                // registration doesn't silently fall back to being
+                // attributed to the host instead.
+                let barrier = self.barriers.arm(&self.open_ch).await;
+                if tokio::time::timeout(OPEN_EVENT_BARRIER_TIMEOUT, barrier)
+                    .await
+                    .is_err()
+                {
+                    warn!(
+                        "Open-event barrier for container {id} timed out after {OPEN_EVENT_BARRIER_TIMEOUT:?}; proceeding anyway"
+                    );
                 }
-            },
+
+                self.events
+                    .send(Event::ContainerStarted(id, info))
+                    .await
+                    .map_err(|err| anyhow!("Failed to send events on a channel: {err}"))?;
+
+                Ok(WorkerState::Busy)
+            }
             Some(ContainerEvent::Stopped(id, info)) => {
-                workloads.lock()
+                self.workloads
+                    .lock()
                     .unwrap()
                     .container_stopped(id.clone(), info.clone());
 
-                if let Err(err) = events.send(Event::ContainerStopped(id, info)).await {
-                    error!("Failed to send events on a channel: {err}");
-                }
-            },
-            None => break,
+                self.events
+                    .send(Event::ContainerStopped(id, info))
+                    .await
+                    .map_err(|err| anyhow!("Failed to send events on a channel: {err}"))?;
+
+                Ok(WorkerState::Busy)
+            }
+            None => Ok(WorkerState::Done),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::*;
+use tokio::sync::{watch, Mutex};
+
+use crate::jitter::JitteredDuration;
+use crate::platform;
+use crate::worker::{Worker, WorkerState};
+
+use super::Workloads;
+
+const HEARTBEAT_JITTER: Duration = Duration::from_secs(30);
+
+// Periodically flushes each workload's in-use package batch to the control
+// plane. When nothing was flushed for `interval` it sends an empty report
+// as a heartbeat instead, so the server can tell a quiet agent from a dead
+// one. `interval` is read from a watch channel so a `SetInUseInterval`
+// command can retune it without restarting the worker.
+pub struct ReportLoopWorker {
+    client: Arc<Mutex<platform::Client>>,
+    workloads: Workloads,
+    periods: tokio::time::Interval,
+    last_reported: Instant,
+    jitter: JitteredDuration,
+    interval: watch::Receiver<Duration>,
+}
+
+impl ReportLoopWorker {
+    pub fn new(
+        client: Arc<Mutex<platform::Client>>,
+        workloads: Workloads,
+        interval: watch::Receiver<Duration>,
+    ) -> Self {
+        Self {
+            client,
+            workloads,
+            periods: tokio::time::interval(Duration::from_millis(1000)),
+            last_reported: Instant::now(),
+            jitter: JitteredDuration::new(HEARTBEAT_JITTER),
+            interval,
+        }
+    }
+}
+
+#[async_trait]
+impl Worker for ReportLoopWorker {
+    fn name(&self) -> &str {
+        "report-loop"
+    }
+
+    async fn step(&mut self) -> Result<WorkerState> {
+        self.periods.tick().await;
+
+        let mut reported = false;
+
+        let (host_id, pkgs) = self.workloads.host.lock().unwrap().flush_in_use();
+
+        if !pkgs.is_empty() {
+            crate::metrics::IN_USE_BATCH_SIZE.observe(pkgs.len() as f64);
+
+            if let Err(err) = self
+                .client
+                .lock()
+                .await
+                .report_in_use(host_id.clone(), pkgs)
+                .await
+            {
+                error!("Failed to report-in-use: {err}");
+            }
+
+            reported = true;
+        }
+
+        let batches = self.workloads.containers.lock().unwrap().flush_in_use();
+
+        for (id, pkgs) in batches {
+            if !pkgs.is_empty() {
+                crate::metrics::IN_USE_BATCH_SIZE.observe(pkgs.len() as f64);
+
+                if let Err(err) = self.client.lock().await.report_in_use(id, pkgs).await {
+                    error!("Failed to report-in-use: {err}");
+                }
+
+                reported = true;
+            }
+        }
+
+        if reported {
+            self.last_reported = Instant::now();
+        } else {
+            let interval = *self.interval.borrow();
+
+            if self.last_reported.elapsed() >= self.jitter.add(interval) {
+                let mut client = self.client.lock().await;
+
+                // A non-empty spool means the agent isn't quiet, it's
+                // catching up from an earlier outage - skip the heartbeat
+                // this tick rather than compete with replay for the wire.
+                if client.pending_report_in_use() > 0 {
+                    debug!("Skipping heartbeat, in-use report spool is still draining");
+                } else if let Err(err) = client.report_in_use(host_id, Vec::new()).await {
+                    error!("Failed to report-in-use (heartbeat): {err}");
+                }
+
+                self.last_reported = Instant::now();
+            }
+        }
+
+        Ok(WorkerState::Busy)
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::*;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+use crate::platform;
+use crate::registry::Registry;
+use crate::sbom::Sbom;
+use crate::scoped_path::RootFsPath;
+use crate::worker::{Worker, WorkerState};
+
+use super::host::HostWorkload;
+
+// Kept next to BASEOS_ID_PATH so the scrub schedule survives restarts.
+const SCRUB_STATE_PATH: &str = "/var/lib/edgebit/scrub-state";
+
+#[derive(Serialize, Deserialize)]
+struct ScrubState {
+    next_run_unix_secs: u64,
+    sbom_id: String,
+}
+
+// Periodically re-scans the host root to catch packages installed or
+// upgraded since the last scan, so long-lived hosts don't drift out of
+// sync with report-in-use matching (see HostWorkload::swap_registry).
+// Self-paces via a "tranquility" factor: after a scan taking wall-time
+// `t`, it waits `tranquility * t` before scanning again, so rescanning
+// doesn't thrash CPU/IO on a production box.
+pub struct SbomScrubWorker {
+    config: Arc<Config>,
+    client: Arc<Mutex<platform::Client>>,
+    host: Arc<StdMutex<HostWorkload>>,
+    last_sbom_id: String,
+    initial_delay: Option<Instant>,
+}
+
+impl SbomScrubWorker {
+    pub fn new(
+        config: Arc<Config>,
+        client: Arc<Mutex<platform::Client>>,
+        host: Arc<StdMutex<HostWorkload>>,
+        last_sbom_id: String,
+    ) -> Self {
+        let initial_delay = load_scrub_state().and_then(|state| {
+            let next_run = UNIX_EPOCH + Duration::from_secs(state.next_run_unix_secs);
+            next_run
+                .duration_since(SystemTime::now())
+                .ok()
+                .map(|remaining| Instant::now() + remaining)
+        });
+
+        Self {
+            config,
+            client,
+            host,
+            last_sbom_id,
+            initial_delay,
+        }
+    }
+}
+
+#[async_trait]
+impl Worker for SbomScrubWorker {
+    fn name(&self) -> &str {
+        "sbom-scrub"
+    }
+
+    async fn step(&mut self) -> Result<WorkerState> {
+        // Honor whatever schedule was persisted from the previous run
+        // before ever touching the filesystem.
+        if let Some(next_run) = self.initial_delay.take() {
+            return Ok(WorkerState::Idle { next_run });
+        }
+
+        let started = Instant::now();
+
+        let host_root = RootFsPath::from(self.config.host_root());
+        let tmp_file = crate::sbom::generate(self.config.clone(), &host_root).await?;
+        let sbom = Sbom::load(&tmp_file.path().into())?;
+        let registry = Registry::from_sbom(&sbom, &host_root)?;
+        let sbom_id = sbom.id();
+
+        if sbom_id != self.last_sbom_id {
+            info!("SBOM scrub found a new SBOM ({sbom_id}), re-uploading");
+
+            let f = std::fs::File::open(tmp_file.path())?;
+            self.client
+                .lock()
+                .await
+                .upload_sbom(sbom_id.clone(), sbom.format(), f)
+                .await?;
+
+            self.last_sbom_id = sbom_id.clone();
+        } else {
+            debug!("SBOM scrub found no changes");
+        }
+
+        self.host
+            .lock()
+            .unwrap()
+            .swap_registry(sbom_id.clone(), registry);
+
+        let tranquility = self.config.scrub_tranquility().max(0.0);
+        let rest = started.elapsed().mul_f64(tranquility);
+        let next_run = Instant::now() + rest;
+
+        save_scrub_state(&ScrubState {
+            next_run_unix_secs: SystemTime::now()
+                .checked_add(rest)
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            sbom_id,
+        });
+
+        Ok(WorkerState::Idle { next_run })
+    }
+}
+
+fn save_scrub_state(state: &ScrubState) {
+    match serde_json::to_string(state) {
+        Ok(json) => {
+            if let Err(err) = std::fs::write(SCRUB_STATE_PATH, json) {
+                error!("Failed to save SBOM scrub state to {SCRUB_STATE_PATH}: {err}");
+            }
+        }
+        Err(err) => error!("Failed to serialize SBOM scrub state: {err}"),
+    }
+}
+
+fn load_scrub_state() -> Option<ScrubState> {
+    let data = std::fs::read_to_string(SCRUB_STATE_PATH).ok()?;
+    serde_json::from_str(&data).ok()
+}
@@ -3,7 +3,9 @@ pub mod control_plane;
 pub mod sbom;
 pub mod registry;
 
+use std::collections::HashSet;
 use std::path::Path;
+use std::time::Duration;
 
 use anyhow::{Result, anyhow};
 use log::*;
@@ -13,6 +15,8 @@ use open_monitor::OpenEvent;
 use registry::Registry;
 use sbom::Sbom;
 
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Parser)]
 struct CliArgs {
     #[clap(long = "sbom")]
@@ -46,7 +50,8 @@ async fn run(args: &CliArgs) -> Result<()> {
     info!("Connecting to Edgebit at {url}");
     let mut client = control_plane::Client::connect(
         url.try_into()?,
-        token.try_into()?,
+        token.into(),
+        None,
     ).await?;
 
     let sbom = match &args.sbom {
@@ -81,29 +86,48 @@ async fn run(args: &CliArgs) -> Result<()> {
     Ok(())
 }
 
+// Coalesces every filename opened since the last tick into one `report_in_use`
+// call instead of one per `open()`, which otherwise pummels the control plane
+// under any real load.
 async fn report_in_use(client: &mut control_plane::Client, pkg_registry: &mut Registry) -> Result<()> {
     let (tx, mut rx) = tokio::sync::mpsc::channel::<OpenEvent>(1000);
     let monitor_task = tokio::task::spawn_blocking(move || open_monitor::run(tx));
 
-    // batch in 1s intervals
-
-    while let Some(evt) = rx.recv().await {
-        match evt.filename.into_string() {
-            Ok(filename) => {
-                let filenames = vec![filename];
-                let pkgs = pkg_registry.get_packages(filenames);
-                _ = client.report_in_use(pkgs).await;
-            },
+    let mut ticks = tokio::time::interval(BATCH_INTERVAL);
+    let mut seen = HashSet::new();
+
+    loop {
+        tokio::select! {
+            evt = rx.recv() => {
+                match evt {
+                    Some(evt) => {
+                        if let Ok(filename) = evt.filename.into_string() {
+                            seen.insert(filename);
+                        }
+                    }
+                    None => break,
+                }
+            }
 
-            Err(_) => (),
+            _ = ticks.tick() => flush_in_use(client, pkg_registry, &mut seen).await,
         }
     }
 
+    flush_in_use(client, pkg_registry, &mut seen).await;
     monitor_task.await.unwrap().unwrap();
 
     Ok(())
 }
 
+async fn flush_in_use(client: &mut control_plane::Client, pkg_registry: &mut Registry, seen: &mut HashSet<String>) {
+    if seen.is_empty() {
+        return;
+    }
+
+    let pkgs = pkg_registry.get_packages(seen.drain().collect());
+    _ = client.report_in_use(pkgs).await;
+}
+
 async fn upload_sbom(client: &mut control_plane::Client, path: &Path) -> Result<()> {
     info!("Uploading SBOM to Edgebit");
     let f = std::fs::File::open(path)?;
@@ -1,7 +1,9 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 
 use anyhow::Result;
+use sha2::{Digest, Sha256};
 use tonic::transport::Server;
 use tonic::{Request, Response, Status, Streaming};
 
@@ -14,7 +16,12 @@ use pb::inventory_service_server::{InventoryService, InventoryServiceServer};
 use pb::token_service_server::{TokenService, TokenServiceServer};
 
 #[derive(Debug, Default)]
-pub struct Service {}
+pub struct Service {
+    // Bytes committed so far per in-progress SBOM upload, keyed by the
+    // whole payload's SHA-256 so a client that reconnects mid-upload can
+    // be told where to resume from instead of starting over.
+    partial_sboms: Mutex<HashMap<Vec<u8>, Vec<u8>>>,
+}
 
 #[tonic::async_trait]
 impl TokenService for Service {
@@ -67,11 +74,33 @@ impl TokenService for Service {
 
 #[tonic::async_trait]
 impl InventoryService for Service {
+    async fn get_sbom_upload_offset(
+        &self,
+        request: Request<pb::GetSbomUploadOffsetRequest>,
+    ) -> Result<Response<pb::GetSbomUploadOffsetResponse>, Status> {
+        let req = request.into_inner();
+        let offset = self
+            .partial_sboms
+            .lock()
+            .unwrap()
+            .get(&req.sha256)
+            .map(|bytes| bytes.len() as u64)
+            .unwrap_or(0);
+
+        Ok(Response::new(pb::GetSbomUploadOffsetResponse { offset }))
+    }
+
     async fn upload_sbom(
         &self,
         request: Request<Streaming<pb::UploadSbomRequest>>,
     ) -> Result<Response<pb::UploadSbomResponse>, Status> {
         let mut request = request.into_inner();
+        let mut sha256 = Vec::new();
+        let mut compression = pb::SbomCompression::None;
+        // Holds the wire payload -- compressed, if `compression` isn't
+        // `None` -- until the trailer's checked; `whole` is only produced
+        // by decompressing it once the full transfer has been verified.
+        let mut payload = Vec::new();
         let mut whole = Vec::new();
 
         loop {
@@ -79,17 +108,47 @@ impl InventoryService for Service {
                 Ok(Some(msg)) => match msg.kind {
                     Some(pb::upload_sbom_request::Kind::Header(hdr)) => {
                         println!("upload_sbom: {hdr:?}");
+                        sha256 = hdr.sha256.clone();
+                        compression = pb::SbomCompression::try_from(hdr.compression)
+                            .unwrap_or(pb::SbomCompression::None);
+                        payload = self
+                            .partial_sboms
+                            .lock()
+                            .unwrap()
+                            .get(&sha256)
+                            .cloned()
+                            .unwrap_or_default();
                     }
 
-                    Some(pb::upload_sbom_request::Kind::Data(mut part)) => {
-                        whole.append(&mut part);
+                    Some(pb::upload_sbom_request::Kind::Data(part)) => {
+                        if crc32c::crc32c(&part.bytes) != part.crc32c {
+                            self.partial_sboms.lock().unwrap().remove(&sha256);
+                            return Err(Status::data_loss("sbom chunk failed its CRC32C check"));
+                        }
+                        payload.extend_from_slice(&part.bytes);
+                        self.partial_sboms
+                            .lock()
+                            .unwrap()
+                            .insert(sha256.clone(), payload.clone());
                     }
 
-                    _ => (),
+                    Some(pb::upload_sbom_request::Kind::Trailer(trailer)) => {
+                        if Sha256::digest(&payload).as_slice() != trailer.sha256.as_slice() {
+                            self.partial_sboms.lock().unwrap().remove(&sha256);
+                            return Err(Status::data_loss("sbom payload failed its SHA-256 check"));
+                        }
+
+                        whole = decompress_sbom(&payload, compression).map_err(|err| {
+                            Status::data_loss(format!("failed to decompress sbom: {err}"))
+                        })?;
+                    }
+
+                    None => (),
                 },
 
                 Ok(None) => {
                     println!("upload_sbom: len={}", whole.len());
+                    self.partial_sboms.lock().unwrap().remove(&sha256);
                     return Ok(Response::new(pb::UploadSbomResponse {}));
                 }
 
@@ -149,6 +208,18 @@ impl InventoryService for Service {
     }
 }
 
+fn decompress_sbom(payload: &[u8], compression: pb::SbomCompression) -> Result<Vec<u8>> {
+    match compression {
+        pb::SbomCompression::None => Ok(payload.to_vec()),
+        pb::SbomCompression::Gzip => {
+            let mut decoded = Vec::new();
+            std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(payload), &mut decoded)?;
+            Ok(decoded)
+        }
+        pb::SbomCompression::Zstd => Ok(zstd::stream::decode_all(payload)?),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr = "0.0.0.0:7777".parse()?;